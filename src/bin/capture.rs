@@ -0,0 +1,111 @@
+use rfraptor::*;
+
+use clap::Parser;
+
+use anyhow::Context;
+
+/// Capture the packets of the configured devices to a pcap file, without the TUI
+#[derive(Parser, Debug)]
+#[command(
+    name = "capture",
+    version = format!("{}-{}", env!("CARGO_PKG_VERSION"), env!("GIT_HASH")),
+)]
+struct Args {
+    /// Device config (YAML)
+    #[arg(short, long)]
+    path: String,
+
+    /// pcap file to write
+    #[arg(short, long)]
+    output: String,
+
+    /// Stop after N packets
+    #[arg(long)]
+    count: Option<usize>,
+
+    /// Stop after S seconds
+    #[arg(long)]
+    duration: Option<f64>,
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    soapysdr::configure_logging();
+
+    let args = Args::parse();
+
+    let file =
+        std::fs::File::open(&args.path).with_context(|| format!("failed to open {}", args.path))?;
+    let config: device::config::List =
+        serde_yaml::from_reader(file).context("failed to parse config")?;
+    config.validate()?;
+
+    let devices = device::open_device(config)?;
+
+    let stop_signals = devices
+        .iter()
+        .map(|d| d.running.clone())
+        .collect::<Vec<_>>();
+    ctrlc::set_handler(move || {
+        log::warn!("ctrl-c received, stopping...");
+        for s in &stop_signals {
+            *s.lock().unwrap() = false;
+        }
+    })?;
+
+    let mut pcap = pcap::PcapWriter::new(&args.output)?;
+
+    let deadline = args
+        .duration
+        .map(|s| std::time::Instant::now() + std::time::Duration::from_secs_f64(s));
+
+    let mut devices = device::MultiDevice::new(devices);
+    let mut rx = devices.start_rx_with_error()?;
+
+    let mut written = 0;
+    while args.count.map_or(true, |count| written < count) {
+        let r = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(std::time::Instant::now());
+                match rx.recv_timeout(timeout) {
+                    Ok(r) => r,
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match rx.next() {
+                Some(r) => r,
+                None => break,
+            },
+        };
+
+        match r {
+            (_, stream::StreamResult::Packet(p)) => {
+                pcap.write_packet(&p)?;
+                written += 1;
+            }
+            // the channel was restarted
+            (i, stream::StreamResult::Error(e @ stream::StreamError::CatcherPanic { .. })) => {
+                log::error!("device[{}]: {}", i, e);
+            }
+            // end of the input, ctrl-c, ...
+            (i, stream::StreamResult::Error(e)) => {
+                log::info!("device[{}]: {}", i, e);
+                break;
+            }
+            (_, stream::StreamResult::ProcessFail(_)) => {}
+        }
+    }
+
+    pcap.flush()?;
+
+    let stats = rx.stats();
+    rx.shutdown()?;
+
+    println!("{} packets written to {}", written, args.output);
+    if let Some(summary) = stats.summary() {
+        println!("{}", summary);
+    }
+
+    Ok(())
+}
@@ -3,13 +3,7 @@ use rfraptor::*;
 use bluetooth::{MacAddress, PacketInner};
 use stream::{RxStream, Stream, TxStream};
 
-use std::{
-    collections::HashMap,
-    io::BufWriter,
-    sync::mpsc::{Receiver, Sender},
-    thread,
-    time::Duration,
-};
+use std::{io::BufWriter, thread, time::Duration};
 
 use ratatui::{
     crossterm::event::{self, Event, KeyCode},
@@ -20,111 +14,14 @@ use ratatui::{
     Frame,
 };
 
-static WORLD: std::sync::Mutex<World> = std::sync::Mutex::new(World::new());
-
-struct World {
-    from_device: Vec<Receiver<bluetooth::Bluetooth>>,
-    to_device: Vec<Sender<bluetooth::Bluetooth>>,
-}
-
-impl World {
-    const fn new() -> Self {
-        Self {
-            from_device: Vec::new(),
-            to_device: Vec::new(),
-        }
-    }
-
-    fn channel(&mut self) -> (Sender<bluetooth::Bluetooth>, Receiver<bluetooth::Bluetooth>) {
-        let (dev_to_world_tx, dev_to_world_rx) = std::sync::mpsc::channel();
-        let (world_to_dev_tx, world_to_dev_rx) = std::sync::mpsc::channel();
-
-        self.from_device.push(dev_to_world_rx);
-        self.to_device.push(world_to_dev_tx);
-
-        (dev_to_world_tx, world_to_dev_rx)
-    }
-}
-
-fn spawn() {
-    std::thread::spawn(|| loop {
-        let world = WORLD.lock().unwrap();
-
-        for (i, from_device) in world.from_device.iter().enumerate() {
-            if let Ok(packet) = from_device.try_recv() {
-                for (j, to_device) in world.to_device.iter().enumerate() {
-                    if i != j {
-                        to_device.send(packet.clone()).unwrap();
-                    }
-                }
-            }
-        }
+// medium of the simulated devices (when `real_rf` is off)
+static LOOPBACK: std::sync::LazyLock<stream::LoopbackBroker> =
+    std::sync::LazyLock::new(stream::LoopbackBroker::new);
 
-        drop(world);
-        std::thread::sleep(std::time::Duration::from_millis(10));
-    });
-}
-
-pub enum VirtualStream {
-    WaitRxStart(RxStream<crate::bluetooth::Bluetooth>),
-    WaitTxStart(TxStream<crate::bluetooth::Bluetooth>),
-    Ready,
-    Started,
-}
-
-impl VirtualStream {
-    pub fn new() -> Self {
-        VirtualStream::Ready
-    }
-}
-
-impl Default for VirtualStream {
-    fn default() -> Self {
-        VirtualStream::new()
-    }
-}
-
-impl Stream for VirtualStream {
-    fn start_rx(&mut self) -> anyhow::Result<RxStream<crate::bluetooth::Bluetooth>> {
-        match self {
-            VirtualStream::WaitRxStart(_) => {
-                let rx = core::mem::replace(self, VirtualStream::Started);
-                if let VirtualStream::WaitRxStart(rx) = rx {
-                    Ok(rx)
-                } else {
-                    unreachable!()
-                }
-            }
-            VirtualStream::WaitTxStart(_) => anyhow::bail!("Already started as Tx"),
-            VirtualStream::Ready => {
-                let (tx, rx) = WORLD.lock().unwrap().channel();
-                *self = VirtualStream::WaitTxStart(TxStream { sink: tx });
-                Ok(RxStream { source: rx })
-            }
-            VirtualStream::Started => anyhow::bail!("Already started"),
-        }
-    }
-
-    fn start_tx(&mut self) -> anyhow::Result<TxStream<crate::bluetooth::Bluetooth>> {
-        match self {
-            VirtualStream::WaitRxStart(_) => anyhow::bail!("Already started as Rx"),
-            VirtualStream::WaitTxStart(_) => {
-                let tx = core::mem::replace(self, VirtualStream::Started);
-                if let VirtualStream::WaitTxStart(tx) = tx {
-                    Ok(tx)
-                } else {
-                    unreachable!()
-                }
-            }
-            VirtualStream::Ready => {
-                let (tx, rx) = WORLD.lock().unwrap().channel();
-                *self = VirtualStream::WaitRxStart(RxStream { source: rx });
-                Ok(TxStream { sink: tx })
-            }
-            VirtualStream::Started => anyhow::bail!("Already started"),
-        }
-    }
-}
+// '+' / '-' move the RSSI floor of the device list by this much [dB]
+const RSSI_FLOOR_STEP: f32 = 5.;
+// the first '+' sets the floor here, '-' below it removes the floor [dBm]
+const RSSI_FLOOR_MIN: f32 = -100.;
 
 enum ExploitBuilderHandleResult {
     Catched,
@@ -172,17 +69,32 @@ struct App {
 
     pub censored: bool,
 
+    // AGC rssi -> dBm
+    rssi_cal: burst::RssiCal,
+
     // databases
     // packets: PacketDB,
-    packets: HashMap<Option<MacAddress>, Vec<bluetooth::Bluetooth>>,
+    packets: history::PacketHistory,
     addresses: Vec<Option<MacAddress>>,
     exploits: Vec<ExploitContainer>,
+    // connections set up by the CONNECT_INDs seen (labelled in the packets list)
+    connections: bluetooth::Connections,
 
     // indeces
     window_selected: Window,
 
     devices_focused: bool,
 
+    // hide the devices whose average RSSI is below [dBm] (None: show every device)
+    rssi_floor: Option<f32>,
+
+    // select the device with the highest recent RSSI on every refresh
+    follow_strongest: bool,
+
+    // AdvData search (see `parse_needle`), typed after '/' while `searching`
+    search: String,
+    searching: bool,
+
     // device_index: usize,
     device_state: ListState,
     // packet_index: usize,
@@ -209,14 +121,23 @@ impl App {
 
             censored: false,
 
-            packets: HashMap::new(),
+            rssi_cal: burst::RssiCal::default(),
+
+            packets: history::PacketHistory::default(),
             addresses: Vec::new(),
             exploits: Vec::new(),
+            connections: bluetooth::Connections::default(),
 
             window_selected: Window::Devices,
 
             devices_focused: false,
 
+            rssi_floor: None,
+            follow_strongest: false,
+
+            search: String::new(),
+            searching: false,
+
             device_state: ListState::default().with_selected(Some(0)),
             packet_state: ListState::default().with_selected(Some(0)),
             exploit_state: ListState::default().with_selected(Some(0)),
@@ -240,14 +161,23 @@ impl App {
 
             censored: false,
 
-            packets: HashMap::new(),
+            rssi_cal: burst::RssiCal::default(),
+
+            packets: history::PacketHistory::default(),
             addresses: Vec::new(),
             exploits: Vec::new(),
+            connections: bluetooth::Connections::default(),
 
             window_selected: Window::Devices,
 
             devices_focused: false,
 
+            rssi_floor: None,
+            follow_strongest: false,
+
+            search: String::new(),
+            searching: false,
+
             device_state: ListState::default().with_selected(Some(0)),
             packet_state: ListState::default().with_selected(Some(0)),
             exploit_state: ListState::default().with_selected(Some(0)),
@@ -257,24 +187,33 @@ impl App {
     }
 
     fn eat(&mut self) {
-        while let Ok(packet) = self.rx_monitor.source.try_recv() {
-            let address = if let crate::bluetooth::PacketInner::Advertisement(ref adv) =
-                packet.packet.inner
-            {
-                Some(adv.address.clone())
-            } else {
-                None
+        while let Some(packet) = self.rx_monitor.try_next() {
+            Self::follow_connection(&self.connections, &packet);
+
+            let address = match packet.packet.inner {
+                PacketInner::Advertisement(ref adv) => Some(adv.address.clone()),
+                PacketInner::ScanRequest(ref req) => Some(req.advertiser.clone()),
+                PacketInner::ConnectRequest(ref req) => Some(req.advertiser.clone()),
+                PacketInner::ExtendedAdvertisement(ref adv) => adv.address.clone(),
+                PacketInner::Data(_) | PacketInner::Unimplemented(_) => None,
             };
 
-            if self.packets.contains_key(&address) {
-                self.packets.get_mut(&address).unwrap().push(packet.clone());
-            } else {
-                self.packets.insert(address.clone(), vec![packet.clone()]);
+            // retransmissions on the other primary channels are only counted
+            if self.packets.push_collapsed(address.clone(), packet) {
                 self.addresses.push(address);
             }
         }
     }
 
+    // label the packets of the connection set up by a (valid) CONNECT_IND as DATA
+    fn follow_connection(connections: &bluetooth::Connections, packet: &bluetooth::Bluetooth) {
+        if let PacketInner::ConnectRequest(ref req) = packet.packet.inner {
+            if packet.packet.crc_valid {
+                connections.register(req.ll_data.access_address, req.ll_data.crc_init);
+            }
+        }
+    }
+
     fn get_color(&self, compare: Window) -> Color {
         if self.window_selected == compare {
             if self.exploit_selected {
@@ -300,19 +239,139 @@ impl App {
     }
 
     fn get_average_rssi(&self, address: &Option<MacAddress>) -> Option<f32> {
-        let packets = self.packets.get(address).unwrap();
-        let rssi = packets
+        let rssi = self.packets.get(address).unwrap().mean_rssi();
+
+        rssi.map(|x| burst::rssi_to_dbm(x, self.rssi_cal))
+    }
+
+    fn get_recent_rssi(&self, address: &Option<MacAddress>) -> Option<f32> {
+        let rssi = self
+            .packets
+            .get(address)
+            .unwrap()
+            .recent_rssi(chrono::Utc::now());
+
+        rssi.map(|x| burst::rssi_to_dbm(x, self.rssi_cal))
+    }
+
+    // devices without an RSSI are never hidden
+    fn above_rssi_floor(rssi: Option<f32>, floor: Option<f32>) -> bool {
+        match (rssi, floor) {
+            (Some(rssi), Some(floor)) => floor <= rssi,
+            _ => true,
+        }
+    }
+
+    // the device list after the RSSI floor, what the device pane indexes
+    fn visible_addresses(&self) -> Vec<Option<MacAddress>> {
+        let needle = Self::parse_needle(&self.search);
+
+        self.addresses
             .iter()
-            .map(|x| {
-                x.bytes_packet.as_ref().and_then(|x| {
-                    x.raw
-                        .as_ref()
-                        .and_then(|x| x.raw.as_ref().map(|x| x.rssi_average))
+            .filter(|address| {
+                Self::above_rssi_floor(self.get_average_rssi(address), self.rssi_floor)
+            })
+            .filter(|address| {
+                needle.as_ref().map_or(true, |needle| {
+                    self.packets
+                        .get(address)
+                        .is_some_and(|history| history.iter().any(|p| p.payload_contains(needle)))
                 })
             })
-            .try_fold(0., |v: f32, acc: Option<f32>| Some(v + acc?));
+            .cloned()
+            .collect()
+    }
+
+    // indices in `history` of the packets shown in the packet pane
+    fn visible_packets(&self, history: &history::AddressHistory) -> Vec<usize> {
+        let needle = Self::parse_needle(&self.search);
+
+        history
+            .iter()
+            .enumerate()
+            .filter(|(_, packet)| {
+                needle
+                    .as_ref()
+                    .map_or(true, |needle| packet.payload_contains(needle))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
 
-        rssi.map(|x| x / packets.len() as f32)
+    // "0x..." is hex (spaces are ignored), anything else ASCII.
+    // None for an empty query or invalid hex, which do not filter.
+    fn parse_needle(query: &str) -> Option<Vec<u8>> {
+        let Some(hex) = query.strip_prefix("0x") else {
+            return (!query.is_empty()).then(|| query.as_bytes().to_vec());
+        };
+
+        let digits = hex
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<Vec<_>>();
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return None;
+        }
+
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).ok())
+            .collect()
+    }
+
+    fn raise_rssi_floor(&mut self) {
+        self.rssi_floor = Some(
+            self.rssi_floor
+                .map_or(RSSI_FLOOR_MIN, |f| f + RSSI_FLOOR_STEP),
+        );
+    }
+
+    fn lower_rssi_floor(&mut self) {
+        self.rssi_floor = self
+            .rssi_floor
+            .map(|f| f - RSSI_FLOOR_STEP)
+            .filter(|f| RSSI_FLOOR_MIN <= *f);
+    }
+
+    fn devices_title(&self) -> String {
+        let mut title = "Devices".to_string();
+        if let Some(floor) = self.rssi_floor {
+            title.push_str(&format!(" (RSSI >= {:.0} dBm)", floor));
+        }
+        if self.follow_strongest {
+            title.push_str(" [following the strongest]");
+        }
+        if self.searching || !self.search.is_empty() {
+            title.push_str(&format!(" /{}", self.search));
+            if self.searching {
+                title.push('_');
+            } else if Self::parse_needle(&self.search).is_none() {
+                title.push_str(" (invalid)");
+            }
+        }
+
+        title
+    }
+
+    // index of the highest RSSI, the first one on a tie (None if no device has an RSSI)
+    fn strongest(rssi: impl IntoIterator<Item = Option<f32>>) -> Option<usize> {
+        rssi.into_iter()
+            .enumerate()
+            .filter_map(|(i, rssi)| Some((i, rssi?)))
+            .max_by(|(i, a), (j, b)| a.total_cmp(b).then(j.cmp(i)))
+            .map(|(i, _)| i)
+    }
+
+    fn select_strongest(&mut self) {
+        let strongest = Self::strongest(
+            self.visible_addresses()
+                .iter()
+                .map(|address| self.get_recent_rssi(address)),
+        );
+
+        if let Some(i) = strongest {
+            self.device_state.select(Some(i));
+        }
     }
 
     fn mac_to_span(censored: bool, mac: &Option<MacAddress>) -> Span {
@@ -349,8 +408,17 @@ impl App {
 
     fn layout_devices(&mut self, frame: &mut Frame, devices: layout::Rect) {
         let censor = self.censored;
-        let items: Vec<ListItem> = self
-            .addresses
+        let addresses = self.visible_addresses();
+
+        // keep the selection on the list after the floor was raised
+        if let Some(selected) = self.device_state.selected() {
+            if addresses.len() <= selected {
+                self.device_state
+                    .select(Some(addresses.len().saturating_sub(1)));
+            }
+        }
+
+        let items: Vec<ListItem> = addresses
             .iter()
             .enumerate()
             .map(|(i, k)| {
@@ -360,12 +428,16 @@ impl App {
 
                 span.push(Self::mac_to_span(censor, k));
 
-                if let Some(rssi) = self.get_average_rssi(k) {
-                    let mut rssi_content = Span::raw(format!("{:>7.2} dB", rssi));
+                if let Some(rssi) = self.get_recent_rssi(k) {
+                    let mut rssi_content = Span::raw(format!("{:>7.2} dBm", rssi));
 
-                    if (..-20.).contains(&rssi) {
+                    // thresholds on the raw AGC rssi
+                    let weak = burst::rssi_to_dbm(-20., self.rssi_cal);
+                    let fair = burst::rssi_to_dbm(-8., self.rssi_cal);
+
+                    if (..weak).contains(&rssi) {
                         rssi_content = rssi_content.fg(Color::Red);
-                    } else if (-20f32..-8.).contains(&rssi) {
+                    } else if (weak..fair).contains(&rssi) {
                         rssi_content = rssi_content.fg(Color::Yellow);
                     } else {
                         rssi_content = rssi_content.fg(Color::Green);
@@ -374,7 +446,7 @@ impl App {
                     span.push(rssi_content);
                 }
 
-                let num_packets = self.packets.get(k).unwrap().len();
+                let num_packets = self.packets.get(k).unwrap().total();
                 let num_content = Span::raw(format!("{:>4} packet(s) ", num_packets));
 
                 let num_content = match num_packets {
@@ -433,60 +505,19 @@ impl App {
                         Span::raw("█").fg(Color::Red),
                         Span::raw("█").fg(Color::Red),
                     ];
-                    let update_per = 5;
+                    let update_per = Duration::from_secs(5);
                     let graph_display_num = if self.devices_focused { 20 } else { 10 };
 
-                    let raw_packets: Vec<&burst::Packet> = self
+                    let buckets = self
                         .packets
                         .get(k)
                         .unwrap()
-                        .iter()
-                        .map(|x| {
-                            x.bytes_packet
-                                .as_ref()
-                                .unwrap()
-                                .raw
-                                .as_ref()
-                                .unwrap()
-                                .raw
-                                .as_ref()
-                                .unwrap()
-                        })
-                        .collect();
-                    // separate per 10 seconds
-
-                    let mut data_base = HashMap::new();
-                    let first = raw_packets.first().unwrap().timestamp;
-
-                    for p in raw_packets {
-                        let idx = p
-                            .timestamp
-                            .signed_duration_since(first)
-                            .num_seconds()
-                            .div_euclid(update_per);
-
-                        data_base.entry(idx).or_insert(Vec::new()).push(p);
-                    }
-
-                    let now_idx = chrono::Utc::now()
-                        .signed_duration_since(first)
-                        .num_seconds()
-                        .div_euclid(update_per);
-
-                    let mut rssi_ave_graph = vec![-30.; now_idx as usize + 1];
-                    let mut packet_count_graph = vec![0; now_idx as usize + 1];
+                        .rssi_buckets(update_per, chrono::Utc::now());
 
-                    for (idx, packets) in data_base {
-                        let rssi_ave = packets.iter().map(|x| x.rssi_average).sum::<f32>()
-                            / packets.len() as f32;
-                        rssi_ave_graph[idx as usize] = rssi_ave;
-                        packet_count_graph[idx as usize] = packets.len();
-                    }
-
-                    let rssi_ave_graph = rssi_ave_graph
+                    let rssi_ave_graph = buckets
                         .iter()
-                        .map(|x| {
-                            let mut idx = ((x + 30.) / 4.) as isize;
+                        .map(|b| {
+                            let mut idx = ((b.mean.unwrap_or(-30.) + 30.) / 4.) as isize;
                             idx = idx.clamp(0, 9);
 
                             graph_symbols[idx as usize].clone()
@@ -496,10 +527,10 @@ impl App {
                         .rev()
                         .collect::<Vec<Span>>();
 
-                    let packet_count_graph = packet_count_graph
+                    let packet_count_graph = buckets
                         .iter()
-                        .map(|x| {
-                            let mut idx = (*x as f32 / 2.) as usize;
+                        .map(|b| {
+                            let mut idx = (b.count as f32 / 2.) as usize;
                             idx = idx.clamp(0, 9);
 
                             graph_symbols[idx].clone()
@@ -555,7 +586,7 @@ impl App {
 
         let description = if self
             .packets
-            .get(self.addresses.first().unwrap())
+            .get(addresses.first().unwrap())
             .unwrap()
             .first()
             .unwrap()
@@ -597,7 +628,7 @@ impl App {
         // render bordered title
         frame.render_widget(
             Block::bordered()
-                .title("Devices")
+                .title(self.devices_title())
                 .style(Style::default().fg(self.get_color(Window::Devices))),
             devices,
         );
@@ -620,7 +651,7 @@ impl App {
     }
 
     fn layout_devices_verbose(&self, frame: &mut Frame, dev_verbose: layout::Rect) {
-        let target = self.addresses[self.device_state.selected().unwrap()].clone();
+        let target = self.selected_address();
 
         let mut content = match target {
             Some(ref mac) => {
@@ -651,6 +682,10 @@ impl App {
             ))));
         }
 
+        if let Some(rssi) = self.get_recent_rssi(&target) {
+            content.push(Line::from(Span::raw(format!("Recent RSSI: {:>7.2}", rssi))));
+        }
+
         let content = Paragraph::new(content)
             .block(Block::bordered().title("Device Verbose"))
             .wrap(Wrap { trim: true });
@@ -658,44 +693,35 @@ impl App {
         frame.render_widget(content, dev_verbose);
     }
 
-    fn selected_address(&self) -> &Option<MacAddress> {
+    fn selected_address(&self) -> Option<MacAddress> {
         let selected = self.device_state.selected().expect("No device selected");
-        self.addresses.get(selected).unwrap()
+        self.visible_addresses().swap_remove(selected)
     }
 
     fn layout_packets(&mut self, frame: &mut Frame, packets: layout::Rect) {
-        let items: Vec<ListItem> = self
-            .packets
-            .get(self.selected_address())
-            .unwrap_or(&Vec::new())
+        let address = self.selected_address();
+        let history = self.packets.get(&address);
+        let visible = history.map_or(Vec::new(), |history| self.visible_packets(history));
+        let items: Vec<ListItem> = visible
             .iter()
-            .enumerate()
+            .filter_map(|&i| Some((i, history?.get(i)?)))
             .map(|(i, packet)| {
                 let mut exploited = false;
-                let content = match &packet.packet.inner {
-                    bluetooth::PacketInner::Advertisement(adv) => {
-                        if !adv.data.is_empty() {
-                            if let Ok(s) = String::from_utf8(adv.data[0].data.clone()) {
-                                if s.starts_with("exploited") {
-                                    exploited = true;
-                                }
+                if let bluetooth::PacketInner::Advertisement(adv) = &packet.packet.inner {
+                    if !adv.data.is_empty() {
+                        if let Ok(s) = String::from_utf8(adv.data[0].data.clone()) {
+                            if s.starts_with("exploited") {
+                                exploited = true;
                             }
                         }
-
-                        let mut data = String::new();
-                        data.push_str(&format!(
-                            "{:>3} {}: {} packet(s)",
-                            i,
-                            adv.pdu_header,
-                            adv.data.len()
-                        ));
-
-                        data
-                    }
-                    bluetooth::PacketInner::Unimplemented(x) => {
-                        format!("{:>3} Unimplemented: 0x{:x}", i, x)
                     }
-                };
+                }
+
+                let mut content = Self::packet_line(i, packet, &self.connections);
+                let copies = history.map_or(1, |history| history.copies(i));
+                if copies > 1 {
+                    content.push_str(&format!(" (x{})", copies));
+                }
                 // .fg(if exploited { Color::Red } else { Color::Reset });
                 ListItem::new(content).style(if exploited {
                     Style::default().fg(Color::Red).bold()
@@ -715,12 +741,131 @@ impl App {
         frame.render_stateful_widget(items, packets, &mut self.packet_state);
     }
 
+    // "ADV", "DATA[aa]" for a tracked connection, or the bare AA if it is unknown
+    fn aa_label(aa: u32, connections: &bluetooth::Connections) -> String {
+        if aa == bluetooth::ADV_ACCESS_ADDRESS {
+            "ADV".to_string()
+        } else if connections.crc_init(aa).is_some() {
+            format!("DATA[0x{:08x}]", aa)
+        } else {
+            format!("0x{:08x}", aa)
+        }
+    }
+
+    // entry of the packets list
+    fn packet_line(
+        i: usize,
+        packet: &bluetooth::Bluetooth,
+        connections: &bluetooth::Connections,
+    ) -> String {
+        let adv = Self::aa_label(bluetooth::ADV_ACCESS_ADDRESS, connections);
+
+        match &packet.packet.inner {
+            bluetooth::PacketInner::Advertisement(adv_packet) => format!(
+                "{:>3} {} {}: {} packet(s)",
+                i,
+                adv,
+                adv_packet.pdu_header,
+                adv_packet.data.len()
+            ),
+            bluetooth::PacketInner::ScanRequest(req) => {
+                format!("{:>3} {} {}: from {}", i, adv, req.pdu_header, req.scanner)
+            }
+            bluetooth::PacketInner::ConnectRequest(req) => {
+                format!(
+                    "{:>3} {} {}: from {}",
+                    i, adv, req.pdu_header, req.initiator
+                )
+            }
+            bluetooth::PacketInner::ExtendedAdvertisement(ext) => match ext.aux_ptr {
+                Some(aux) => format!("{:>3} {} {}: aux ch{}", i, adv, ext.pdu_header, aux.channel),
+                None => format!("{:>3} {} {}", i, adv, ext.pdu_header),
+            },
+            bluetooth::PacketInner::Data(data) => format!(
+                "{:>3} {} {:?}",
+                i,
+                Self::aa_label(data.access_address, connections),
+                data.header.llid
+            ),
+            bluetooth::PacketInner::Unimplemented(aa) => {
+                format!("{:>3} {}", i, Self::aa_label(*aa, connections))
+            }
+        }
+    }
+
+    // one line per AD structure, named by its AD type (raw hex/ascii for an unknown type)
+    fn adv_data_lines(adv: &bluetooth::Advertisement) -> Vec<Line<'static>> {
+        adv.data
+            .iter()
+            .map(|adv_data| match adv_data.parsed() {
+                bluetooth::AdType::Unknown(..)
+                    if adv_data.data.iter().all(u8::is_ascii_alphanumeric) =>
+                {
+                    Line::from(adv_data.data.iter().map(|u| *u as char).collect::<String>())
+                }
+                bluetooth::AdType::Unknown(..) => Line::from(format!(
+                    "{:40}|{}",
+                    &adv_data
+                        .data
+                        .iter()
+                        .map(|x| format!("{:02x}", x))
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                    &adv_data
+                        .data
+                        .iter()
+                        .map(|x| {
+                            if x.is_ascii() && x.is_ascii_alphanumeric() {
+                                format!("{}", *x as char)
+                            } else {
+                                ".".to_string()
+                            }
+                        })
+                        .collect::<Vec<String>>()
+                        .join(""),
+                )),
+                parsed => Line::from(parsed.to_string()),
+            })
+            .collect()
+    }
+
+    // access address and CRC as received ("n/a" for synthetic packets)
+    fn link_layer_line(packet: &bluetooth::Bluetooth) -> Line<'static> {
+        let Some(ref byte_packet) = packet.bytes_packet else {
+            return Line::from("AA: n/a, CRC: n/a");
+        };
+
+        let crc = packet.packet.crc;
+        let line = Line::from(format!(
+            "AA: 0x{:08x}, CRC: {:02x}{:02x}{:02x} ({})",
+            byte_packet.aa,
+            crc[0],
+            crc[1],
+            crc[2],
+            if packet.packet.crc_valid {
+                "valid"
+            } else {
+                "invalid"
+            }
+        ));
+
+        if packet.packet.crc_valid {
+            line
+        } else {
+            line.fg(Color::Red)
+        }
+    }
+
     fn layout_packet_verbose(&self, frame: &mut Frame, packet_verbose: layout::Rect) {
         let target = self
             .packets
-            .get(self.selected_address())
-            .unwrap_or(&Vec::new())
-            .get(self.packet_state.selected().unwrap())
+            .get(&self.selected_address())
+            .and_then(|history| {
+                let i = *self
+                    .visible_packets(history)
+                    .get(self.packet_state.selected().unwrap())?;
+                history.get(i)
+            })
             .cloned()
             .unwrap();
 
@@ -747,6 +892,7 @@ impl App {
                 ))),
             ],
         };
+        content.push(Self::link_layer_line(&target));
 
         match target.packet.inner {
             PacketInner::Advertisement(ref adv) => {
@@ -754,40 +900,66 @@ impl App {
                     "PDU Header: {}, Length: {}",
                     adv.pdu_header, adv.length
                 )));
-                for adv_data in &adv.data {
-                    if adv_data.data.iter().all(u8::is_ascii_alphanumeric) {
-                        content.push(Line::from(adv_data.data.iter().map(|u| *u as char).fold(
-                            "".to_string(),
-                            |mut s, c| {
-                                s.push(c);
-                                s
-                            },
-                        )));
-                    } else {
-                        content.push(Line::from(format!(
-                            "{:40}|{}",
-                            &adv_data
-                                .data
-                                .iter()
-                                .map(|x| format!("{:02x}", x))
-                                .collect::<Vec<String>>()
-                                .join(" "),
-                            &adv_data
-                                .data
-                                .iter()
-                                .map(|x| {
-                                    if x.is_ascii() && x.is_ascii_alphanumeric() {
-                                        format!("{}", *x as char)
-                                    } else {
-                                        ".".to_string()
-                                    }
-                                })
-                                .collect::<Vec<String>>()
-                                .join(""),
-                        )));
-                    }
+                content.extend(Self::adv_data_lines(adv));
+            }
+            PacketInner::ScanRequest(ref req) => {
+                content.push(Line::from(format!(
+                    "PDU Header: {}, Length: {}",
+                    req.pdu_header, req.length
+                )));
+                content.push(Line::from(format!("Scanner: {}", req.scanner)));
+            }
+            PacketInner::ConnectRequest(ref req) => {
+                let ll = &req.ll_data;
+
+                content.push(Line::from(format!(
+                    "PDU Header: {}, Length: {}",
+                    req.pdu_header, req.length
+                )));
+                content.push(Line::from(format!("Initiator: {}", req.initiator)));
+                content.push(Line::from(format!(
+                    "AA: 0x{:08x}, CRC Init: 0x{:06x}",
+                    ll.access_address, ll.crc_init
+                )));
+                content.push(Line::from(format!(
+                    "Interval: {}, Latency: {}, Timeout: {}, Hop: {}",
+                    ll.interval, ll.latency, ll.timeout, ll.hop
+                )));
+            }
+            PacketInner::ExtendedAdvertisement(ref adv) => {
+                content.push(Line::from(format!(
+                    "PDU Header: {}, Length: {}, AdvMode: {}",
+                    adv.pdu_header, adv.length, adv.adv_mode
+                )));
+                if let Some(adi) = adv.adi {
+                    content.push(Line::from(format!(
+                        "DID: 0x{:03x}, SID: {}",
+                        adi.did, adi.sid
+                    )));
+                }
+                if let Some(aux) = adv.aux_ptr {
+                    content.push(Line::from(format!(
+                        "AuxPtr: channel {}, offset {} us, PHY {}",
+                        aux.channel,
+                        aux.offset_us(),
+                        aux.aux_phy
+                    )));
+                }
+                if let Some(tx_power) = adv.tx_power {
+                    content.push(Line::from(format!("TxPower: {} dBm", tx_power)));
                 }
             }
+            PacketInner::Data(ref data) => {
+                content.push(Line::from(format!(
+                    "AA: 0x{:08x}, LLID: {:?}, NESN: {}, SN: {}, MD: {}, Length: {}",
+                    data.access_address,
+                    data.header.llid,
+                    data.header.nesn as u8,
+                    data.header.sn as u8,
+                    data.header.md as u8,
+                    data.length
+                )));
+            }
             PacketInner::Unimplemented(x) => {
                 content.push(Line::from(format!("Unimplemented: 0x{:x}", x)));
                 if let Some(ref bytes) = target.bytes_packet {
@@ -901,7 +1073,7 @@ impl App {
             let area = popup_area(frame.area(), 70, 95);
             frame.render_widget(Clear, area);
 
-            let addr = self.selected_address().clone();
+            let addr = self.selected_address();
             let src = self.src.clone();
 
             let exploit = self
@@ -914,6 +1086,19 @@ impl App {
     }
 
     fn layout(&mut self, frame: &mut Frame) {
+        if self.visible_addresses().is_empty() {
+            let content =
+                Paragraph::new("No device above the RSSI floor ('-') or matching the search ('/')")
+                    .block(Block::bordered().title(self.devices_title()));
+            frame.render_widget(content, frame.area());
+
+            return;
+        }
+
+        if self.follow_strongest {
+            self.select_strongest();
+        }
+
         if self.devices_focused {
             self.layout_devices(frame, frame.area());
         } else {
@@ -933,13 +1118,30 @@ impl App {
         if event::poll(Duration::from_secs(0))? {
             if let Event::Key(key) = event::read()? {
                 if key.kind == event::KeyEventKind::Press {
+                    if self.searching {
+                        match key.code {
+                            KeyCode::Char(c) => self.search.push(c),
+                            KeyCode::Backspace => {
+                                self.search.pop();
+                            }
+                            KeyCode::Enter => self.searching = false,
+                            KeyCode::Esc => {
+                                self.search.clear();
+                                self.searching = false;
+                            }
+                            _ => {}
+                        }
+
+                        return Ok(false);
+                    }
+
                     if self.exploit_selected {
                         let e = self
                             .exploits
                             .get_mut(self.exploit_state.selected().unwrap())
                             .unwrap();
                         // if let Some(packet) = e.exploit.handle_events(key.code) {
-                        //     self.tx_monitor.sink.send(packet).unwrap();
+                        //     self.tx_monitor.send(packet).unwrap();
                         //     return Ok(false);
                         // }
                         let handle = e.exploit.handle_events(key.code);
@@ -948,12 +1150,19 @@ impl App {
                                 return Ok(false);
                             }
                             ExploitBuilderHandleResult::Packet(packet) => {
-                                self.tx_monitor.sink.send(*packet).unwrap();
+                                self.tx_monitor.send(*packet).unwrap();
                             }
                             ExploitBuilderHandleResult::Fallthrough => {}
                         }
                     }
 
+                    // moving in the device list stops following
+                    if self.window_selected == Window::Devices
+                        && matches!(key.code, KeyCode::Char('j' | 'k' | 'g' | 'G'))
+                    {
+                        self.follow_strongest = false;
+                    }
+
                     match key.code {
                         KeyCode::Char('q') => {
                             if self.exploit_selected {
@@ -977,6 +1186,14 @@ impl App {
                         KeyCode::Char('f') => {
                             self.devices_focused = !self.devices_focused;
                         }
+                        KeyCode::Char('/') => {
+                            self.searching = true;
+                        }
+                        KeyCode::Char('s') => {
+                            self.follow_strongest = !self.follow_strongest;
+                        }
+                        KeyCode::Char('+') => self.raise_rssi_floor(),
+                        KeyCode::Char('-') => self.lower_rssi_floor(),
                         KeyCode::Char('k') => {
                             self.get_selected_state().select_previous();
                         }
@@ -1039,19 +1256,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 freq_mhz: 2480,
                 // serial: "0000000000000000f77c60dc259132c3".to_string(),
                 serial: "0000000000000000436c63dc38276e63".to_string(),
+                rx_gain: None,
+                tx_gain: None,
+                worker_threads: None,
+                worker_priority: None,
+                stream_buffers: None,
+                aa_tolerance: None,
             }],
         })
         .unwrap();
         // Box::new(devices.pop().unwrap())
         let devices = devices.pop().unwrap();
-        App::from_dev_conf(
+        let rssi_cal = burst::RssiCal::hackrf(devices.config.rx_gain);
+
+        let mut app = App::from_dev_conf(
             Box::new(devices),
             "HackRF: Listening on 2427 MHz".to_string(),
             "HackRF: Transmitting on 2427 MHz".to_string(),
-        )
+        );
+        app.rssi_cal = rssi_cal;
+
+        app
     } else {
-        // Box::new(VirtualStream::new())
-        App::from_stream(Box::new(VirtualStream::new()))
+        App::from_stream(Box::new(LOOPBACK.stream()))
     };
 
     #[derive(Debug)]
@@ -1189,6 +1416,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }],
                 }),
                 crc: [0, 0, 0],
+                crc_valid: false,
             },
             remain: Vec::new(),
             freq: 2427,
@@ -1341,6 +1569,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         packet: bluetooth::BluetoothPacket {
                             inner: self.packet.clone(),
                             crc: [0, 0, 0],
+                            crc_valid: false,
                         },
                         remain: Vec::new(),
                         freq: 2427,
@@ -1358,8 +1587,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         exploit: Box::new(BrokenPacket::new()),
     });
 
-    // let mut alice = VirtualStream::new();
-    let mut bob = VirtualStream::new();
+    // let mut alice = LOOPBACK.stream();
+    let mut bob = LOOPBACK.stream();
 
     if !real_rf {
         /*
@@ -1376,7 +1605,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 for i in 0.. {
                     let packet =
                         demo_adv_packet(address.clone(), format!("Alice{}", i).into_bytes());
-                    tx.sink.send(packet).unwrap();
+                    tx.send(packet).unwrap();
 
                     thread::sleep(Duration::from_secs(1));
                 }
@@ -1394,19 +1623,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let rx = bob.start_rx().unwrap();
                 let tx = bob.start_tx().unwrap();
 
-                tx.sink
-                    .send(demo_adv_packet(address.clone(), b"Bob: Hello".to_vec()))
+                tx.send(demo_adv_packet(address.clone(), b"Bob: Hello".to_vec()))
                     .unwrap();
 
                 // echo server
-                for packet in rx.source.iter() {
+                for packet in rx {
                     if let bluetooth::PacketInner::Advertisement(adv) = packet.packet.inner {
                         if adv.data[0].len as usize != adv.data[0].data.len() {
                             let packet = demo_adv_packet(
                                 address.clone(),
                                 b"exploited:BUFFER_OVER_FLOW".to_vec(),
                             );
-                            tx.sink.send(packet).unwrap();
+                            tx.send(packet).unwrap();
                         } else {
                             let data = String::from_utf8_lossy(&adv.data[0].data).to_string();
 
@@ -1429,18 +1657,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 prefix.extend(stdout);
                                 let packet = demo_adv_packet(address.clone(), prefix);
 
-                                tx.sink.send(packet).unwrap();
+                                tx.send(packet).unwrap();
                             } else if data.starts_with("hello:") {
                                 let packet =
                                     demo_adv_packet(address.clone(), b"HelloWorld".to_vec());
-                                tx.sink.send(packet).unwrap();
+                                tx.send(packet).unwrap();
                             }
                         }
                     }
                 }
             });
-
-        spawn();
     }
 
     let mut terminal = ratatui::init();
@@ -1463,8 +1689,196 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     ratatui::restore();
 
-    drop(app);
-    thread::sleep(Duration::from_millis(100)); // wait for the thread to finish
+    let App { rx_monitor, .. } = app;
+    rx_monitor.shutdown()?;
 
     Ok(())
 }
+
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../test_support/packets.rs"]
+mod packets;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn link_layer_line() {
+        let mut packet = packets::adv_packet(&packets::ADV_PDU, 2426);
+
+        let text = line_text(&App::link_layer_line(&packet));
+        assert!(text.contains("AA: 0x8e89bed6"), "{}", text);
+        assert!(text.contains("(valid)"), "{}", text);
+
+        packet.bytes_packet = None;
+        assert_eq!(
+            line_text(&App::link_layer_line(&packet)),
+            "AA: n/a, CRC: n/a"
+        );
+    }
+
+    #[test]
+    fn adv_data_lines() {
+        let adv = bluetooth::Advertisement {
+            pdu_header: bluetooth::PDUHeader {
+                pdu_type: bluetooth::PDUType::AdvInd,
+                rfu: false,
+                ch_sel: false,
+                tx_add: false,
+                rx_add: false,
+            },
+            length: 20,
+            address: bluetooth::MacAddress {
+                address: [0x00, 0x01, 0x00, 0x56, 0x34, 0x12],
+            },
+            data: vec![
+                bluetooth::AdvData::new(0x01, &[0x06]),
+                bluetooth::AdvData::new(0x09, b"FooSensor"),
+            ],
+        };
+
+        assert_eq!(
+            App::adv_data_lines(&adv)
+                .iter()
+                .map(line_text)
+                .collect::<Vec<_>>(),
+            vec![
+                "Flags: LE General Discoverable, BR/EDR Not Supported",
+                "Complete Local Name: FooSensor",
+            ]
+        );
+    }
+
+    #[test]
+    fn demo_vendor_highlighted() {
+        let src = Some(MacAddress {
+            address: [0x00, 0x01, 0x00, 0x56, 0x34, 0x12],
+        });
+        assert_eq!(App::mac_to_span(false, &src).style.fg, Some(Color::Red));
+
+        let unknown = Some("02:00:00:00:00:01".parse().unwrap());
+        assert_eq!(
+            App::mac_to_span(false, &unknown).style.fg,
+            Some(Color::Reset)
+        );
+    }
+
+    #[test]
+    fn packet_line_labels() {
+        let aa = 0x5065_17a5;
+        let packet = bluetooth::Bluetooth {
+            bytes_packet: None,
+            packet: bluetooth::BluetoothPacket {
+                inner: bluetooth::PacketInner::Unimplemented(aa),
+                crc: [0; 3],
+                crc_valid: false,
+            },
+            remain: Vec::new(),
+            freq: 2404,
+        };
+
+        let connections = bluetooth::Connections::new();
+        assert_eq!(App::packet_line(3, &packet, &connections), "  3 0x506517a5");
+
+        connections.register(aa, 0x123456);
+        assert_eq!(
+            App::packet_line(3, &packet, &connections),
+            "  3 DATA[0x506517a5]"
+        );
+
+        assert_eq!(
+            App::aa_label(bluetooth::ADV_ACCESS_ADDRESS, &connections),
+            "ADV"
+        );
+
+        // registries are not shared between apps
+        assert_eq!(
+            App::packet_line(3, &packet, &bluetooth::Connections::new()),
+            "  3 0x506517a5"
+        );
+    }
+
+    #[test]
+    fn packet_line_follows_connect_ind() {
+        let pdu = [
+            0xc5, 0x22, // header (CONNECT_IND, TxAdd, RxAdd), length
+            0x9c, 0x2b, 0x51, 0x3e, 0x7a, 0x5d, // InitA
+            0x01, 0x00, 0x00, 0x56, 0x34, 0x12, // AdvA
+            0x4e, 0x9b, 0x9a, 0xaf, // AA
+            0x1d, 0x3c, 0x7b, // CRCInit
+            0x02, 0x0f, 0x00, 0x24, 0x00, 0x00, 0x00, 0xf4, 0x01, // WinSize .. Timeout
+            0xff, 0xff, 0xff, 0xff, 0x1f, 0x27, // ChM, Hop, SCA
+        ];
+        let connect_ind = packets::adv_packet(&pdu, 2402);
+
+        let data = bluetooth::Bluetooth {
+            bytes_packet: None,
+            packet: bluetooth::BluetoothPacket {
+                inner: bluetooth::PacketInner::Unimplemented(0xaf9a9b4e),
+                crc: [0; 3],
+                crc_valid: false,
+            },
+            remain: Vec::new(),
+            freq: 2404,
+        };
+
+        let connections = bluetooth::Connections::new();
+        assert_eq!(App::packet_line(0, &data, &connections), "  0 0xaf9a9b4e");
+
+        App::follow_connection(&connections, &connect_ind);
+        assert_eq!(
+            App::packet_line(0, &data, &connections),
+            "  0 DATA[0xaf9a9b4e]"
+        );
+    }
+
+    #[test]
+    fn rssi_floor() {
+        let devices = [("near", Some(-45.)), ("far", Some(-90.)), ("virtual", None)];
+        let visible = |floor| {
+            devices
+                .iter()
+                .filter(|(_, rssi)| App::above_rssi_floor(*rssi, floor))
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(visible(None), vec!["near", "far", "virtual"]);
+        assert_eq!(visible(Some(-90.)), vec!["near", "far", "virtual"]);
+        assert_eq!(visible(Some(-70.)), vec!["near", "virtual"]);
+        assert_eq!(visible(Some(-40.)), vec!["virtual"]);
+    }
+
+    #[test]
+    fn parse_needle() {
+        assert_eq!(App::parse_needle(""), None);
+        assert_eq!(App::parse_needle("hello"), Some(b"hello".to_vec()));
+        assert_eq!(App::parse_needle("0x4c00"), Some(vec![0x4c, 0x00]));
+        assert_eq!(
+            App::parse_needle("0xff 4C 00"),
+            Some(vec![0xff, 0x4c, 0x00])
+        );
+
+        // invalid hex does not filter
+        assert_eq!(App::parse_needle("0x4c0"), None);
+        assert_eq!(App::parse_needle("0xzz"), None);
+        assert_eq!(App::parse_needle("0x"), None);
+    }
+
+    #[test]
+    fn strongest() {
+        assert_eq!(App::strongest([]), None);
+        assert_eq!(App::strongest([None, None]), None);
+        assert_eq!(
+            App::strongest([Some(-80.), None, Some(-45.5), Some(-60.)]),
+            Some(2)
+        );
+        assert_eq!(App::strongest([None, Some(-50.), Some(-50.)]), Some(1));
+    }
+}
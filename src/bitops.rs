@@ -1,8 +1,13 @@
 mod bitparser;
+mod crc;
 mod lfsr;
 
+pub use bitparser::BitOrder;
+pub use crc::{crc24, ADV_CRC_INIT};
+
 use anyhow::{bail, Result};
 use bitparser::*;
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone)]
 pub struct BytePacket {
@@ -22,18 +27,194 @@ pub struct BytePacket {
 
     #[allow(unused)]
     pub remain_bits: Vec<u8>,
+
+    /// AA + dewhitened PDU + CRC as bits, LSB first (only by `bits_to_packet_with_bits`).
+    /// The AA bits are as received, before the `aa_tolerance` correction.
+    #[allow(unused)]
+    pub bits: Option<Vec<u8>>,
+
+    /// timestamp of the burst (None if there is no raw burst)
+    #[allow(unused)]
+    pub timestamp: Option<DateTime<Utc>>,
+
+    /// average RSSI of the burst (None if there is no raw burst)
+    #[allow(unused)]
+    pub rssi_average: Option<f32>,
 }
 
-pub fn fsk_to_packet(packet: crate::fsk::Packet, freq: usize) -> Result<BytePacket> {
-    let bits = bits_to_packet(&packet.bits, freq)?;
+/// `aa_tolerance`, `preamble_tolerance`: see `bits_to_packet_tolerant`
+pub fn fsk_to_packet(
+    packet: crate::fsk::Packet,
+    freq: usize,
+    aa_tolerance: u32,
+    preamble_tolerance: u32,
+) -> Result<BytePacket> {
+    let bits = bits_to_packet_tolerant(&packet.bits, freq, aa_tolerance, preamble_tolerance)?;
 
-    Ok(BytePacket {
+    Ok(with_raw(packet, bits))
+}
+
+/// `fsk_to_packet` with `bits_to_packet_detect_channel`
+pub fn fsk_to_packet_detect_channel(
+    packet: crate::fsk::Packet,
+    freq: usize,
+    aa_tolerance: u32,
+    preamble_tolerance: u32,
+) -> Result<BytePacket> {
+    let bits = bits_to_packet_detect_channel(&packet.bits, freq, aa_tolerance, preamble_tolerance)?;
+
+    Ok(with_raw(packet, bits))
+}
+
+fn with_raw(packet: crate::fsk::Packet, bits: BytePacket) -> BytePacket {
+    let timestamp = packet.raw.as_ref().map(|raw| raw.timestamp);
+    let rssi_average = packet.raw.as_ref().map(|raw| raw.rssi_average);
+
+    BytePacket {
         raw: Some(packet),
+        timestamp,
+        rssi_average,
         ..bits
+    }
+}
+
+/// `fsk_to_packet` over the results of `fsk::demodulate_corpus`
+pub fn fsk_corpus_to_packets(
+    packets: impl IntoIterator<Item = Result<crate::fsk::Packet>>,
+    freq: usize,
+    aa_tolerance: u32,
+    preamble_tolerance: u32,
+) -> impl Iterator<Item = Result<BytePacket>> {
+    packets.into_iter().map(move |packet| {
+        packet.and_then(|p| fsk_to_packet(p, freq, aa_tolerance, preamble_tolerance))
     })
 }
 
+/// maximum length of an advertising channel PDU payload
+const MAX_ADV_PDU_LENGTH: u8 = 37;
+
+// true if `aa` is the advertising AA with at most `tolerance` bit errors
+fn is_adv_aa(aa: u32, tolerance: u32) -> bool {
+    (aa ^ crate::bluetooth::ADV_ACCESS_ADDRESS).count_ones() <= tolerance
+}
+
+// AA + header + payload + CRC starting at `offset`,
+// None if the header is not plausible or the bits run out
+fn parse_at_offset(
+    bits: &[u8],
+    freq: usize,
+    offset: usize,
+    aa_tolerance: u32,
+) -> Option<(Vec<u8>, &[u8])> {
+    let mut bits = bits.get(offset..)?;
+
+    let mut whitening = lfsr::LFSR0221::from_freq(freq);
+    let mut bytes = Vec::new();
+
+    for _ in 0..4 {
+        let (remain, byte) = RawByte::parse(bits).ok()?;
+
+        bits = remain;
+        bytes.push(byte.byte);
+    }
+
+    // dewhiten the header first to reject a wrong offset early
+    for _ in 0..2 {
+        let (remain, WhitedByte { byte }) = WhitedByte::parse(bits, &mut whitening).ok()?;
+
+        bits = remain;
+        bytes.push(byte);
+    }
+
+    let aa = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    if is_adv_aa(aa, aa_tolerance) {
+        // correct the bit errors before parsing the PDU
+        bytes[0..4].copy_from_slice(&crate::bluetooth::ADV_ACCESS_ADDRESS.to_le_bytes());
+
+        if MAX_ADV_PDU_LENGTH < bytes[5] {
+            return None;
+        }
+    }
+
+    // payload + CRC
+    for _ in 0..bytes[5] as usize + 3 {
+        let (remain, WhitedByte { byte }) = WhitedByte::parse(bits, &mut whitening).ok()?;
+
+        bits = remain;
+        bytes.push(byte);
+    }
+
+    Some((bytes, bits))
+}
+
 pub fn bits_to_packet(bits: &[u8], freq: usize) -> Result<BytePacket> {
+    bits_to_packet_tolerant(bits, freq, 0, 0)
+}
+
+/// Same as `bits_to_packet`, but an access address within `aa_tolerance` bit errors
+/// of the advertising AA is accepted (and corrected) as advertising, and a preamble
+/// within `preamble_tolerance` bit errors is accepted
+pub fn bits_to_packet_tolerant(
+    bits: &[u8],
+    freq: usize,
+    aa_tolerance: u32,
+    preamble_tolerance: u32,
+) -> Result<BytePacket> {
+    parse_packet(bits, freq, aa_tolerance, preamble_tolerance, false)
+}
+
+/// Same as `bits_to_packet_tolerant`, but the dewhitened bits are kept in `BytePacket::bits`
+pub fn bits_to_packet_with_bits(
+    bits: &[u8],
+    freq: usize,
+    aa_tolerance: u32,
+    preamble_tolerance: u32,
+) -> Result<BytePacket> {
+    parse_packet(bits, freq, aa_tolerance, preamble_tolerance, true)
+}
+
+/// Same as `bits_to_packet_tolerant`, but if `freq` does not give an advertising packet
+/// with a valid CRC, the whitening of every BLE channel is tried (up to 40 times the cost).
+/// `BytePacket::freq` is the channel which matched.
+pub fn bits_to_packet_detect_channel(
+    bits: &[u8],
+    freq: usize,
+    aa_tolerance: u32,
+    preamble_tolerance: u32,
+) -> Result<BytePacket> {
+    let labeled = bits_to_packet_tolerant(bits, freq, aa_tolerance, preamble_tolerance);
+    if labeled.as_ref().is_ok_and(is_valid_adv) {
+        return labeled;
+    }
+
+    (0..=39)
+        .filter_map(crate::stream::BluetoothChannel::from_channel_index)
+        .map(|ch| ch.to_freq() as usize)
+        .filter(|f| *f != freq)
+        .find_map(|f| {
+            bits_to_packet_tolerant(bits, f, aa_tolerance, preamble_tolerance)
+                .ok()
+                .filter(is_valid_adv)
+        })
+        .map_or(labeled, Ok)
+}
+
+// advertising packet whose CRC matches
+fn is_valid_adv(packet: &BytePacket) -> bool {
+    let len = packet.bytes.len();
+
+    packet.aa == crate::bluetooth::ADV_ACCESS_ADDRESS
+        && 4 + 2 + 3 <= len
+        && crc24(&packet.bytes[4..len - 3], ADV_CRC_INIT) == packet.bytes[len - 3..]
+}
+
+fn parse_packet(
+    bits: &[u8],
+    freq: usize,
+    aa_tolerance: u32,
+    preamble_tolerance: u32,
+    keep_bits: bool,
+) -> Result<BytePacket> {
     use zerocopy::FromBytes;
 
     let bits_len = bits.len() as i64;
@@ -46,30 +227,15 @@ pub fn bits_to_packet(bits: &[u8], freq: usize) -> Result<BytePacket> {
         bail!("lap is not valid");
     }
 
-    let Ok((bits, _)) = Preamble::parse(bits) else {
+    let Ok((bits, _)) = Preamble::parse(bits, preamble_tolerance) else {
         bail!("failed to parse preamble");
     };
 
     let mut found_data = useful_number::updatable_num::UpdateToMinI64WithData::new();
     for offset in 0..3 {
-        let mut bits = &bits[offset..];
-
-        let mut whitening = lfsr::LFSR0221::from_freq(freq);
-        let mut bytes = Vec::new();
-
-        for _ in 0..4 {
-            let Ok((remain, byte)) = RawByte::parse(bits) else {
-                bail!("bit starvation");
-            };
-
-            bits = remain;
-            bytes.push(byte.byte);
-        }
-
-        while let Ok((remain, WhitedByte { byte })) = WhitedByte::parse(bits, &mut whitening) {
-            bits = remain;
-            bytes.push(byte);
-        }
+        let Some((bytes, bits)) = parse_at_offset(bits, freq, offset, aa_tolerance) else {
+            continue;
+        };
 
         let packet_length = 8 + 32 + 16 + bytes[5] as i64 * 8 + 24;
 
@@ -95,6 +261,18 @@ pub fn bits_to_packet(bits: &[u8], freq: usize) -> Result<BytePacket> {
 
     let aa = *aa;
 
+    let bits = keep_bits.then(|| {
+        let packet_bits = &bits[offset..offset + bytes.len() * 8];
+        let mut whitening = lfsr::LFSR0221::from_freq(freq);
+
+        // the AA is not whitened
+        packet_bits[..32]
+            .iter()
+            .copied()
+            .chain(packet_bits[32..].iter().map(|b| b ^ whitening.next_white()))
+            .collect()
+    });
+
     Ok(BytePacket {
         raw: None,
 
@@ -105,10 +283,66 @@ pub fn bits_to_packet(bits: &[u8], freq: usize) -> Result<BytePacket> {
         delta,
         freq,
         remain_bits: remain_bits.to_vec(),
+        bits,
+
+        timestamp: None,
+        rssi_average: None,
     })
 }
 
+/// Whiten `bytes` in place (LSB first) for the BLE channel index `channel` (0..=39,
+/// 37..=39 are the advertising channels)
+///
+/// # Panics
+/// if `channel` is not a BLE channel index
+pub fn whiten(bytes: &mut [u8], channel: u8) {
+    assert!(channel <= 39, "invalid channel index: {}", channel);
+
+    let mut whitening = lfsr::LFSR0221::from_ch(channel);
+
+    for byte in bytes {
+        for i in 0..8 {
+            *byte ^= whitening.next_white() << i;
+        }
+    }
+}
+
+/// Inverse of `whiten` (whitening is an XOR, so it is the same operation)
+pub fn dewhiten(bytes: &mut [u8], channel: u8) {
+    whiten(bytes, channel)
+}
+
+/// Pack `bits` (one bit per element, as demodulated) into bytes in `order`,
+/// with the bits left over after the last whole byte
+pub fn pack_bits(bits: &[u8], order: BitOrder) -> (Vec<u8>, &[u8]) {
+    let mut bits = bits;
+    let mut bytes = Vec::with_capacity(bits.len() / 8);
+
+    while let Ok((remain, byte)) = RawByte::parse_with(bits, order) {
+        bits = remain;
+        bytes.push(byte.byte);
+    }
+
+    (bytes, bits)
+}
+
 pub fn packet_to_bits(bytes: &[u8], freq: usize, aa: u32) -> Vec<u8> {
+    let header_padding = 0;
+    let length = bytes.len() as u8;
+
+    let mut pdu = vec![header_padding, length];
+    pdu.extend_from_slice(bytes);
+
+    pdu_to_bits(&pdu, freq, aa)
+}
+
+/// Same as `packet_to_bits`, but `pdu` starts with its own 16-bit header (see `Advertisement::encode`)
+pub fn pdu_to_bits(pdu: &[u8], freq: usize, aa: u32) -> Vec<u8> {
+    pdu_to_bits_with_crc(pdu, freq, aa, ADV_CRC_INIT)
+}
+
+/// Same as `pdu_to_bits`, with the CRC init of a connection (see `DataPacket::encode`)
+pub fn pdu_to_bits_with_crc(pdu: &[u8], freq: usize, aa: u32, crc_init: u32) -> Vec<u8> {
     let mut bits = Vec::new();
 
     Preamble::encode(&mut bits);
@@ -123,22 +357,13 @@ pub fn packet_to_bits(bytes: &[u8], freq: usize, aa: u32) -> Vec<u8> {
 
     let mut whitening = lfsr::LFSR0221::from_freq(freq);
 
-    let header_padding = 0;
-    let length = bytes.len() as u8;
-
-    WhitedByte {
-        byte: header_padding,
-    }
-    .encode(&mut bits, &mut whitening);
-    WhitedByte { byte: length }.encode(&mut bits, &mut whitening);
-
-    for b in bytes {
+    for b in pdu {
         WhitedByte { byte: *b }.encode(&mut bits, &mut whitening);
     }
 
     // add CRC
-    for _i in 0..3 {
-        WhitedByte { byte: 0 }.encode(&mut bits, &mut whitening); // FIXME
+    for b in crc24(pdu, crc_init) {
+        WhitedByte { byte: b }.encode(&mut bits, &mut whitening);
     }
 
     // add some garbages
@@ -179,6 +404,74 @@ mod test {
         assert_eq!(byte_packet.remain_bits.len(), byte_packet.delta as usize);
     }
 
+    #[test]
+    fn whiten_round_trip() {
+        let original = b"hello world!".to_vec();
+
+        for channel in [0, 1, 10, 36, 37, 38, 39] {
+            let mut bytes = original.clone();
+
+            super::whiten(&mut bytes, channel);
+            assert_ne!(bytes, original);
+
+            super::dewhiten(&mut bytes, channel);
+            assert_eq!(bytes, original);
+        }
+
+        // the first 8 bits of the channel 0 sequence
+        let mut zero = [0u8];
+        super::whiten(&mut zero, 0);
+        assert_eq!(zero, [0b0100_0000]);
+    }
+
+    #[test]
+    fn dewhiten_matches_packet_path() {
+        // 2426 MHz is channel index 38
+        let payload = [0x12, 0x34, 0x56, 0x00, 0x01, 0x00];
+        let bits = super::packet_to_bits(&payload, 2426, 0x8e89bed6);
+
+        // preamble + offset + AA, then the whitened header + payload + CRC
+        let mut whitened = bits[6 + 2 + 32..]
+            .chunks_exact(8)
+            .map(|c| c.iter().enumerate().fold(0, |byte, (i, b)| byte | b << i))
+            .collect::<Vec<u8>>();
+        super::dewhiten(&mut whitened, 38);
+
+        let byte_packet = super::bits_to_packet(&bits, 2426).unwrap();
+        assert_eq!(whitened, byte_packet.bytes[4..]);
+    }
+
+    #[test]
+    fn keep_bits() {
+        let bits = super::packet_to_bits(b"hello world!", 2426, 0x8e89bed6);
+
+        let byte_packet = super::bits_to_packet_with_bits(&bits, 2426, 0, 0).unwrap();
+        let kept = byte_packet.bits.unwrap();
+        assert_eq!(kept.len(), byte_packet.bytes.len() * 8);
+
+        let repacked = kept
+            .chunks_exact(8)
+            .map(|c| c.iter().enumerate().fold(0, |byte, (i, b)| byte | b << i))
+            .collect::<Vec<u8>>();
+        assert_eq!(repacked, byte_packet.bytes);
+
+        assert!(super::bits_to_packet(&bits, 2426).unwrap().bits.is_none());
+    }
+
+    #[test]
+    fn reject_absurd_length() {
+        let bits = super::packet_to_bits(&[0; 200], 2426, 0x8e89bed6);
+
+        assert!(super::bits_to_packet(&bits, 2426).is_err());
+
+        // skip the preamble
+        assert!(super::parse_at_offset(&bits[6..], 2426, 2, 0).is_none());
+
+        let bits = super::packet_to_bits(&[0; 37], 2426, 0x8e89bed6);
+        let (bytes, _) = super::parse_at_offset(&bits[6..], 2426, 2, 0).unwrap();
+        assert_eq!(bytes.len(), 4 + 2 + 37 + 3);
+    }
+
     #[test]
     fn uptest_bytes() {
         let bytes = b"hello world!";
@@ -193,4 +486,158 @@ mod test {
         assert_eq!(byte_packet.delta, 4);
         assert_eq!(byte_packet.remain_bits.len(), 4);
     }
+
+    #[test]
+    fn pack_bits() {
+        use super::BitOrder;
+
+        let aa = crate::bluetooth::ADV_ACCESS_ADDRESS;
+        let bits = super::packet_to_bits(b"hi", 2426, aa);
+
+        // preamble and the 2 bit offset
+        let aa_bits = &bits[8..8 + 32];
+
+        let (lsb, remain) = super::pack_bits(aa_bits, BitOrder::Lsb);
+        assert_eq!(lsb, aa.to_le_bytes());
+        assert!(remain.is_empty());
+
+        let (msb, _) = super::pack_bits(aa_bits, BitOrder::Msb);
+        assert_eq!(
+            msb,
+            aa.to_le_bytes().map(u8::reverse_bits),
+            "same bits, reversed in each byte"
+        );
+
+        let (bytes, remain) = super::pack_bits(&aa_bits[..12], BitOrder::Msb);
+        assert_eq!(bytes.len(), 1);
+        assert_eq!(remain, &aa_bits[8..12]);
+
+        // BLE parsing is LSB first
+        assert_eq!(super::bits_to_packet(&bits, 2426).unwrap().aa, aa);
+    }
+
+    #[test]
+    fn detect_channel() {
+        let payload = [0x00, 0x01, 0x00, 0x56, 0x34, 0x12, 0x02, 0x01, 0x06];
+        let aa = crate::bluetooth::ADV_ACCESS_ADDRESS;
+
+        // whitened for channel 37 (2402 MHz), labeled 2426 MHz
+        let bits = super::packet_to_bits(&payload, 2402, aa);
+
+        let labeled = super::bits_to_packet(&bits, 2426);
+        assert!(labeled.map_or(true, |p| !super::is_valid_adv(&p)));
+
+        let detected = super::bits_to_packet_detect_channel(&bits, 2426, 0, 0).unwrap();
+        assert_eq!(detected.freq, 2402);
+        assert_eq!(detected.bytes[6..6 + payload.len()], payload);
+        assert!(super::is_valid_adv(&detected));
+
+        // the label is kept when it is right
+        let bits = super::packet_to_bits(&payload, 2426, aa);
+        assert_eq!(
+            super::bits_to_packet_detect_channel(&bits, 2426, 0, 0)
+                .unwrap()
+                .freq,
+            2426
+        );
+    }
+
+    #[test]
+    fn channel_freq() {
+        let freqs = (0..=39)
+            .map(|i| {
+                crate::stream::BluetoothChannel::from_channel_index(i)
+                    .unwrap()
+                    .to_freq() as usize
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(freqs[..3], [2404, 2406, 2408]);
+        assert_eq!(freqs[10..13], [2424, 2428, 2430]);
+        assert_eq!(freqs[36..], [2478, 2402, 2426, 2480]);
+
+        // consistent with the whitening seeds of from_freq
+        for (channel, freq) in freqs.iter().enumerate() {
+            let mut by_freq = super::lfsr::LFSR0221::from_freq(*freq);
+            let mut by_ch = super::lfsr::LFSR0221::from_ch(channel as u8);
+            assert!((0..7).all(|_| by_freq.next_white() == by_ch.next_white()));
+        }
+    }
+
+    #[test]
+    fn aa_tolerance() {
+        let aa = crate::bluetooth::ADV_ACCESS_ADDRESS ^ (1 << 9);
+        let bits = super::packet_to_bits(b"hello world!", 2426, aa);
+
+        let byte_packet = super::bits_to_packet_tolerant(&bits, 2426, 0, 0).unwrap();
+        assert_eq!(byte_packet.aa, aa);
+
+        let bt = crate::bluetooth::Bluetooth::from_bytes(byte_packet, 2426).unwrap();
+        assert!(matches!(
+            bt.packet.inner,
+            crate::bluetooth::PacketInner::Unimplemented(_)
+        ));
+
+        let byte_packet = super::bits_to_packet_tolerant(&bits, 2426, 1, 0).unwrap();
+        assert_eq!(byte_packet.aa, crate::bluetooth::ADV_ACCESS_ADDRESS);
+
+        let bt = crate::bluetooth::Bluetooth::from_bytes(byte_packet, 2426).unwrap();
+        assert!(bt.packet.crc_valid);
+        assert!(matches!(
+            bt.packet.inner,
+            crate::bluetooth::PacketInner::Advertisement(_)
+        ));
+
+        // two bit errors are not corrected
+        let aa = crate::bluetooth::ADV_ACCESS_ADDRESS ^ 0b11;
+        let bits = super::packet_to_bits(b"hello world!", 2426, aa);
+        let byte_packet = super::bits_to_packet_tolerant(&bits, 2426, 1, 0).unwrap();
+        assert_eq!(byte_packet.aa, aa);
+    }
+
+    #[test]
+    fn preamble_tolerance() {
+        let mut bits = super::packet_to_bits(b"hello world!", 2426, 0x8e89bed6);
+        assert!(super::bits_to_packet(&bits, 2426).is_ok());
+
+        // the preamble is the first 6 bits
+        bits[1] ^= 1;
+        assert!(super::bits_to_packet(&bits, 2426).is_err());
+        assert!(super::bits_to_packet_tolerant(&bits, 2426, 0, 1).is_ok());
+
+        bits[3] ^= 1;
+        assert!(super::bits_to_packet_tolerant(&bits, 2426, 0, 1).is_err());
+    }
+
+    #[test]
+    fn fsk_to_packet_burst_info() {
+        let bits = super::packet_to_bits(b"hello world!", 2426, 0x8e89bed6);
+        let timestamp = chrono::Utc::now();
+
+        let fsk_packet = crate::fsk::Packet {
+            raw: Some(crate::burst::Packet {
+                data: Vec::new(),
+                timestamp,
+                rssi_average: -12.5,
+            }),
+            bits,
+            demod: Vec::new(),
+            cfo: 0.,
+            deviation: 0.,
+            snr: 0.,
+            symbol_start: 0,
+            sample_per_symbol: 1.,
+        };
+
+        let byte_packet = super::fsk_to_packet(fsk_packet, 2426, 0, 0).unwrap();
+
+        assert_eq!(byte_packet.timestamp, Some(timestamp));
+        assert_eq!(byte_packet.rssi_average, Some(-12.5));
+
+        let bits = super::packet_to_bits(b"hello world!", 2426, 0x8e89bed6);
+        let byte_packet = super::bits_to_packet(&bits, 2426).unwrap();
+
+        assert_eq!(byte_packet.timestamp, None);
+        assert_eq!(byte_packet.rssi_average, None);
+    }
 }
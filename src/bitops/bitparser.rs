@@ -53,17 +53,18 @@ impl Lap {
 pub struct Preamble {}
 
 impl Preamble {
-    pub fn parse(input: &[u8]) -> nom::IResult<&[u8], Self> {
+    /// Alternating pattern (either polarity) with at most `tolerance` flipped bits
+    pub fn parse(input: &[u8], tolerance: u32) -> nom::IResult<&[u8], Self> {
         let (remain, took) = take(6u8)(input)?;
 
-        let mut fail = false;
-
-        fail |= took[0] != took[2]; // fail
-        fail |= took[1] != took[3]; // fail
-        fail |= took[2] != took[4]; // fail
-        fail |= took[3] != took[5]; // fail
+        // distance to 0,1,0,1,0,1 (and 6 - errors to 1,0,1,0,1,0)
+        let errors = took
+            .iter()
+            .enumerate()
+            .filter(|(i, b)| **b != (i % 2) as u8)
+            .count() as u32;
 
-        if fail {
+        if tolerance < errors.min(6 - errors) {
             return Err(nom::Err::Error(nom::error::Error::new(
                 input,
                 ErrorKind::Fail,
@@ -83,18 +84,35 @@ impl Preamble {
     }
 }
 
+/// Order of the bits of a byte on air
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    /// least significant bit first (BLE)
+    Lsb,
+    /// most significant bit first
+    Msb,
+}
+
 #[derive(Debug)]
 pub struct RawByte {
     pub byte: u8,
 }
 
 impl RawByte {
+    /// LSB first, as BLE sends it
     pub fn parse(input: &[u8]) -> nom::IResult<&[u8], Self> {
+        Self::parse_with(input, BitOrder::Lsb)
+    }
+
+    pub fn parse_with(input: &[u8], order: BitOrder) -> nom::IResult<&[u8], Self> {
         let (remain, raw_bits) = take(8u8)(input)?;
 
         let mut byte = 0;
         for (i, b) in raw_bits.iter().enumerate() {
-            byte |= b << i;
+            byte |= match order {
+                BitOrder::Lsb => b << i,
+                BitOrder::Msb => b << (7 - i),
+            };
         }
 
         Ok((remain, Self { byte }))
@@ -137,7 +155,7 @@ mod test {
     #[test]
     fn preamble_ok() {
         let input = [0, 1, 0, 1, 0, 1];
-        let (remain, _) = Preamble::parse(&input).expect("parse failed");
+        let (remain, _) = Preamble::parse(&input, 0).expect("parse failed");
 
         assert_eq!(remain.len(), 0);
     }
@@ -145,7 +163,21 @@ mod test {
     #[test]
     fn preamble_fail() {
         let input = [0, 1, 0, 1, 0, 0];
-        Preamble::parse(&input).expect_err("parse ok");
+        Preamble::parse(&input, 0).expect_err("parse ok");
+    }
+
+    #[test]
+    fn preamble_tolerance() {
+        let exact = [1, 0, 1, 0, 1, 0];
+        Preamble::parse(&exact, 1).expect("parse failed");
+
+        let one_flip = [0, 1, 1, 1, 0, 1];
+        Preamble::parse(&one_flip, 0).expect_err("parse ok");
+        Preamble::parse(&one_flip, 1).expect("parse failed");
+
+        let two_flips = [0, 0, 0, 1, 1, 1];
+        Preamble::parse(&two_flips, 1).expect_err("parse ok");
+        Preamble::parse(&two_flips, 2).expect("parse failed");
     }
 
     #[test]
@@ -157,6 +189,19 @@ mod test {
         assert_eq!(raw_byte.byte, 0b10101010);
     }
 
+    #[test]
+    fn raw_byte_msb() {
+        let input = [1, 1, 0, 1, 0, 0, 0, 0, 1];
+
+        let (remain, lsb) = RawByte::parse(&input).expect("parse failed");
+        let (_, msb) = RawByte::parse_with(&input, BitOrder::Msb).expect("parse failed");
+
+        assert_eq!(remain, [1]);
+        assert_eq!(lsb.byte, 0b0000_1011);
+        assert_eq!(msb.byte, 0b1101_0000);
+        assert_eq!(msb.byte, lsb.byte.reverse_bits());
+    }
+
     #[test]
     fn uptest_raw_byte() {
         let raw_byte = RawByte { byte: 0b10101010 };
@@ -190,7 +235,7 @@ mod test {
 
         Preamble::encode(&mut encoded);
 
-        let (remain, _) = Preamble::parse(&encoded).expect("parse failed");
+        let (remain, _) = Preamble::parse(&encoded, 0).expect("parse failed");
 
         assert_eq!(remain.len(), 0);
     }
@@ -1,12 +1,17 @@
 // use ice9_bindings::*;
 
-use std::{collections::HashMap, sync::LazyLock};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, LazyLock, RwLock};
 
 use nom::{bytes::complete::take, number::complete::le_u32, IResult};
 
 use crate::bitops::BytePacket;
 
+/// access address of the advertising channel
+pub const ADV_ACCESS_ADDRESS: u32 = 0x8E89BED6;
+
 // TODO: いい感じに実装する
+// serialized with the RF fields of bytes_packet flattened (see Serialize impl)
 #[derive(Debug, Clone)]
 pub struct Bluetooth {
     pub bytes_packet: Option<BytePacket>,
@@ -21,29 +26,44 @@ pub struct Bluetooth {
     pub freq: usize,
 }
 
+#[derive(Debug)]
 pub enum DecodeError {
     #[allow(unused)]
     FoundClassic(u32),
 
     #[allow(unused)]
     PacketNotFound,
+
+    /// the packet is too short for its layout
+    #[allow(unused)]
+    Truncated,
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub struct BluetoothPacket {
     pub inner: PacketInner,
 
     #[allow(unused)]
     pub crc: [u8; 3],
+
+    /// CRC matches the PDU, computed with the CRC init of the access address: the advertising
+    /// one for `ADV_ACCESS_ADDRESS`, the one of its CONNECT_IND for a connection followed in
+    /// `Connections` (an unknown access address falls back to the advertising one)
+    #[allow(unused)]
+    pub crc_valid: bool,
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub enum PacketInner {
     Advertisement(Advertisement),
+    ScanRequest(ScanRequest),
+    ConnectRequest(ConnectRequest),
+    ExtendedAdvertisement(ExtendedAdvertisement),
+    Data(DataPacket),
     Unimplemented(u32),
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub struct Advertisement {
     pub pdu_header: PDUHeader,
     pub length: u8,
@@ -51,12 +71,122 @@ pub struct Advertisement {
     pub data: Vec<AdvData>,
 }
 
+/// SCAN_REQ: ScanA + AdvA
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct ScanRequest {
+    pub pdu_header: PDUHeader,
+    pub length: u8,
+    pub scanner: MacAddress,
+    pub advertiser: MacAddress,
+}
+
+/// CONNECT_IND: InitA + AdvA + LLData
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct ConnectRequest {
+    pub pdu_header: PDUHeader,
+    pub length: u8,
+    pub initiator: MacAddress,
+    pub advertiser: MacAddress,
+    pub ll_data: LLData,
+}
+
+/// ADV_EXT_IND: common extended advertising payload
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct ExtendedAdvertisement {
+    pub pdu_header: PDUHeader,
+    pub length: u8,
+    /// 0: non-connectable and non-scannable, 1: connectable, 2: scannable
+    pub adv_mode: u8,
+    pub address: Option<MacAddress>,
+    pub target_address: Option<MacAddress>,
+    pub adi: Option<Adi>,
+    pub aux_ptr: Option<AuxPtr>,
+    pub tx_power: Option<i8>,
+    /// AdvData (usually empty on the primary channel)
+    pub data: Vec<u8>,
+}
+
+/// AdvDataInfo of an extended advertisement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct Adi {
+    pub did: u16,
+    pub sid: u8,
+}
+
+/// where the auxiliary packet (AUX_ADV_IND) is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct AuxPtr {
+    pub channel: u8,
+    pub ca: bool,
+    /// false: 30 us, true: 300 us
+    pub offset_units: bool,
+    pub aux_offset: u16,
+    pub aux_phy: u8,
+}
+
+/// connection parameters carried by CONNECT_IND
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct LLData {
+    pub access_address: u32,
+    pub crc_init: u32,
+    pub win_size: u8,
+    pub win_offset: u16,
+    pub interval: u16,
+    pub latency: u16,
+    pub timeout: u16,
+    pub channel_map: [u8; 5],
+    pub hop: u8,
+    pub sca: u8,
+}
+
+/// longest payload of a data channel PDU [bytes]
+pub const MAX_DATA_PAYLOAD: usize = 251;
+
+/// LL data channel PDU of a registered connection
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct DataPacket {
+    pub access_address: u32,
+    /// CRC init of the connection
+    pub crc_init: u32,
+    pub header: DataHeader,
+    pub length: u8,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct DataHeader {
+    pub llid: LLID,
+    pub nesn: bool,
+    pub sn: bool,
+    pub md: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum LLID {
+    Reserved,
+    /// continuation fragment or empty PDU
+    DataContinuation,
+    DataStart,
+    Control,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MacAddress {
     pub address: [u8; 6],
 }
 
-#[derive(Debug, Clone, Hash)]
+/// BLE device address type (Core Spec Vol 6, Part B, 1.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddrType {
+    Public,
+    RandomStatic,
+    ResolvablePrivate,
+    NonResolvablePrivate,
+    /// random address with the reserved `0b10` sub-type
+    Reserved,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub enum PDUType {
     AdvInd,
     AdvDirectInd,
@@ -65,10 +195,11 @@ pub enum PDUType {
     ScanRsp,
     ConnectReq,
     AdvScanInd,
+    AdvExtInd,
     Unknown(u8),
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub struct PDUHeader {
     pub pdu_type: PDUType,
     pub rfu: bool,
@@ -77,34 +208,143 @@ pub struct PDUHeader {
     pub rx_add: bool,
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub struct AdvData {
     pub len: u8,
     pub data: Vec<u8>,
 }
 
+/// GAP AD Type (Assigned Numbers 2.3)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AdType {
+    Flags(u8),
+    IncompleteList16(Vec<u16>),
+    CompleteList16(Vec<u16>),
+    IncompleteList128(Vec<u128>),
+    CompleteList128(Vec<u128>),
+    ShortenedLocalName(String),
+    CompleteLocalName(String),
+    TxPowerLevel(i8),
+    ServiceData16 { uuid: u16, data: Vec<u8> },
+    Appearance(u16),
+    ManufacturerData { company: u16, data: Vec<u8> },
+    Unknown(u8, Vec<u8>),
+}
+
+impl serde::Serialize for Bluetooth {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let rssi = self.bytes_packet.as_ref().and_then(|b| b.rssi_average);
+        let timestamp = self
+            .bytes_packet
+            .as_ref()
+            .and_then(|b| b.timestamp)
+            .map(|t| t.to_rfc3339());
+
+        let mut s = serializer.serialize_struct("Bluetooth", 5)?;
+        s.serialize_field("freq", &self.freq)?;
+        s.serialize_field("rssi", &rssi)?;
+        s.serialize_field("timestamp", &timestamp)?;
+        s.serialize_field("packet", &self.packet)?;
+        s.serialize_field("remain", &self.remain)?;
+        s.end()
+    }
+}
+
 impl Bluetooth {
-    pub fn from_bytes(mut byte_packet: BytePacket, freq: usize) -> Result<Self, DecodeError> {
+    /// One-line JSON (e.g. for piping into jq)
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize packet")
+    }
+
+    /// Decode without following any connection (data channel PDUs are `Unimplemented`)
+    pub fn from_bytes(byte_packet: BytePacket, freq: usize) -> Result<Self, DecodeError> {
+        Self::from_bytes_with(byte_packet, freq, &Connections::default())
+    }
+
+    /// Decode the data channel PDUs of `connections`, and register the connections set up
+    /// by the (valid) CONNECT_INDs in it
+    pub fn from_bytes_with(
+        mut byte_packet: BytePacket,
+        freq: usize,
+        connections: &Connections,
+    ) -> Result<Self, DecodeError> {
         let len = byte_packet.bytes.len();
+        if len < 3 {
+            return Err(DecodeError::Truncated);
+        }
+
         let mut crc = [0, 0, 0];
         for (i, b) in byte_packet.bytes.drain(len - 3..).enumerate() {
             crc[i] = b;
         }
 
         // println!("crc: {:02x}{:02x}{:02x}", crc[0], crc[1], crc[2]);
-        let (remain, packet_inner) = PacketInner::from_bytes(byte_packet.bytes.as_ref()).unwrap();
-        // FIXME: unwrap will panic if slice is too short
+        let crc_init = byte_packet
+            .bytes
+            .get(..4)
+            .and_then(|aa| connections.crc_init(u32::from_le_bytes(aa.try_into().unwrap())))
+            .unwrap_or(crate::bitops::ADV_CRC_INIT);
+
+        let crc_valid = byte_packet
+            .bytes
+            .get(4..)
+            .is_some_and(|pdu| crate::bitops::crc24(pdu, crc_init) == crc);
+
+        let (remain, packet_inner) =
+            PacketInner::from_bytes(byte_packet.bytes.as_ref(), connections)
+                .map_err(|_| DecodeError::Truncated)?;
+
+        // follow the connection set up by a (valid) CONNECT_IND
+        if let PacketInner::ConnectRequest(ref req) = packet_inner {
+            if crc_valid {
+                connections.register(req.ll_data.access_address, req.ll_data.crc_init);
+            }
+        }
 
         Ok(Self {
             bytes_packet: Some(byte_packet.clone()),
             packet: BluetoothPacket {
                 inner: packet_inner,
                 crc,
+                crc_valid,
             },
             remain: remain.to_vec(),
             freq,
         })
     }
+
+    /// Hash of the dewhitened AA + PDU (without the CRC), shared by the retransmissions of
+    /// an advertisement on the primary channels. Packets without bytes hash their contents.
+    pub fn content_id(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        match self.bytes_packet {
+            // the CRC was split off into `packet.crc` by from_bytes
+            Some(ref byte_packet) => byte_packet.bytes.hash(&mut hasher),
+            None => self.packet.inner.hash(&mut hasher),
+        }
+
+        hasher.finish()
+    }
+
+    /// True if an AD structure (AD type + payload) of an advertisement contains `needle`.
+    /// Packets without AdvData never match, an empty `needle` always does.
+    pub fn payload_contains(&self, needle: &[u8]) -> bool {
+        if needle.is_empty() {
+            return true;
+        }
+
+        let contains = |data: &[u8]| data.windows(needle.len()).any(|w| w == needle);
+
+        match self.packet.inner {
+            PacketInner::Advertisement(ref adv) => adv.data.iter().any(|ad| contains(&ad.data)),
+            PacketInner::ExtendedAdvertisement(ref adv) => contains(&adv.data),
+            _ => false,
+        }
+    }
 }
 
 impl PDUHeader {
@@ -117,6 +357,7 @@ impl PDUHeader {
             0b0100 => Some(PDUType::ScanRsp),
             0b0101 => Some(PDUType::ConnectReq),
             0b0110 => Some(PDUType::AdvScanInd),
+            0b0111 => Some(PDUType::AdvExtInd),
             x => Some(PDUType::Unknown(x)),
         };
 
@@ -140,38 +381,283 @@ impl PDUHeader {
             rx_add,
         })
     }
+
+    /// Parse the 16-bit advertising PDU header (flags + length)
+    pub fn from_bytes(input: &[u8]) -> IResult<&[u8], (Self, u8)> {
+        let (input, header) = take(2u8)(input)?;
+
+        let pdu_header = Self::from_byte(header[0]).ok_or(nom::Err::Error(
+            nom::error::Error::new(input, nom::error::ErrorKind::Verify),
+        ))?;
+
+        Ok((input, (pdu_header, header[1])))
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        let pdu_type = match self.pdu_type {
+            PDUType::AdvInd => 0b0000,
+            PDUType::AdvDirectInd => 0b0001,
+            PDUType::AdvNonconnInd => 0b0010,
+            PDUType::ScanReq => 0b0011,
+            PDUType::ScanRsp => 0b0100,
+            PDUType::ConnectReq => 0b0101,
+            PDUType::AdvScanInd => 0b0110,
+            PDUType::AdvExtInd => 0b0111,
+            PDUType::Unknown(x) => x & 0b1111,
+        };
+
+        pdu_type
+            | (self.rfu as u8) << 4
+            | (self.ch_sel as u8) << 5
+            | (self.tx_add as u8) << 6
+            | (self.rx_add as u8) << 7
+    }
+}
+
+/// connections followed by default (see `Connections::with_capacity`)
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// Access address -> CRC init of the connections whose packets are decoded as data channel
+/// PDUs, e.g. learned from the CONNECT_INDs. The oldest connection is forgotten once
+/// `capacity` are followed. Clones share the same table (e.g. between the rx threads).
+#[derive(Debug, Clone)]
+pub struct Connections {
+    table: Arc<RwLock<ConnectionTable>>,
+}
+
+#[derive(Debug)]
+struct ConnectionTable {
+    crc_inits: HashMap<u32, u32>,
+    // access addresses, oldest registration first
+    order: VecDeque<u32>,
+    capacity: usize,
+}
+
+impl Default for Connections {
+    fn default() -> Self {
+        Self::with_capacity(MAX_CONNECTIONS)
+    }
+}
+
+impl Connections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow at most `capacity` connections
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            table: Arc::new(RwLock::new(ConnectionTable {
+                crc_inits: HashMap::new(),
+                order: VecDeque::new(),
+                capacity,
+            })),
+        }
+    }
+
+    /// Decode packets with `aa` as data channel PDUs from now on
+    pub fn register(&self, aa: u32, crc_init: u32) {
+        let mut table = self.table.write().expect("failed to lock");
+
+        if table.crc_inits.insert(aa, crc_init).is_some() {
+            table.order.retain(|a| *a != aa);
+        }
+        table.order.push_back(aa);
+
+        while table.order.len() > table.capacity {
+            let Some(oldest) = table.order.pop_front() else {
+                break;
+            };
+            table.crc_inits.remove(&oldest);
+        }
+    }
+
+    /// CRC init of the access address (None if it is unknown)
+    pub fn crc_init(&self, aa: u32) -> Option<u32> {
+        if aa == ADV_ACCESS_ADDRESS {
+            return Some(crate::bitops::ADV_CRC_INIT);
+        }
+
+        self.table
+            .read()
+            .expect("failed to lock")
+            .crc_inits
+            .get(&aa)
+            .copied()
+    }
+
+    /// Number of connections followed
+    pub fn len(&self) -> usize {
+        self.table.read().expect("failed to lock").order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl PacketInner {
-    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+    /// Bits on air (preamble to CRC) on `freq` [MHz]
+    ///
+    /// Advertisements and data channel PDUs (with the CRC init they carry) can be encoded.
+    pub fn to_bits(&self, freq: usize) -> anyhow::Result<Vec<u8>> {
+        match self {
+            PacketInner::Advertisement(adv) => Ok(crate::bitops::pdu_to_bits(
+                &adv.encode(),
+                freq,
+                ADV_ACCESS_ADDRESS,
+            )),
+            PacketInner::Data(data) => Ok(crate::bitops::pdu_to_bits_with_crc(
+                &data.encode(),
+                freq,
+                data.access_address,
+                data.crc_init,
+            )),
+            _ => anyhow::bail!("only advertisements and data channel PDUs can be encoded"),
+        }
+    }
+
+    fn from_bytes<'a>(input: &'a [u8], connections: &Connections) -> IResult<&'a [u8], Self> {
         let (input, access_address) = le_u32(input)?;
 
         match access_address {
-            0x8E89BED6 => {
-                let (input, adv) = Advertisement::from_bytes(input)?;
-                Ok((input, PacketInner::Advertisement(adv)))
+            ADV_ACCESS_ADDRESS => {
+                // peek the header to select the PDU layout
+                let (_, (header, _)) = PDUHeader::from_bytes(input)?;
+
+                match header.pdu_type {
+                    PDUType::ScanReq => {
+                        let (input, req) = ScanRequest::from_bytes(input)?;
+                        Ok((input, PacketInner::ScanRequest(req)))
+                    }
+                    PDUType::ConnectReq => {
+                        let (input, req) = ConnectRequest::from_bytes(input)?;
+                        Ok((input, PacketInner::ConnectRequest(req)))
+                    }
+                    PDUType::AdvExtInd => {
+                        let (input, adv) = ExtendedAdvertisement::from_bytes(input)?;
+                        Ok((input, PacketInner::ExtendedAdvertisement(adv)))
+                    }
+                    _ => {
+                        let (input, adv) = Advertisement::from_bytes(input)?;
+                        Ok((input, PacketInner::Advertisement(adv)))
+                    }
+                }
             }
-            other => Ok((input, PacketInner::Unimplemented(other))),
+            other => match connections.crc_init(other) {
+                Some(crc_init) => {
+                    let (input, data) = DataPacket::from_bytes(other, crc_init, input)?;
+                    Ok((input, PacketInner::Data(data)))
+                }
+                None => Ok((input, PacketInner::Unimplemented(other))),
+            },
         }
     }
 }
 
-impl Advertisement {
-    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
-        let (input, pdu_type) = take(1u8)(input)?;
-        let pdu_type = PDUHeader::from_byte(pdu_type[0]).unwrap();
+impl DataPacket {
+    /// Data channel PDU of the connection with `access_address` and `crc_init`
+    /// (the length is taken from `payload`, at most `MAX_DATA_PAYLOAD` bytes)
+    pub fn new(
+        access_address: u32,
+        crc_init: u32,
+        header: DataHeader,
+        payload: Vec<u8>,
+    ) -> anyhow::Result<Self> {
+        if payload.len() > MAX_DATA_PAYLOAD {
+            anyhow::bail!(
+                "data channel payload is too long: {} > {} bytes",
+                payload.len(),
+                MAX_DATA_PAYLOAD
+            );
+        }
+
+        Ok(DataPacket {
+            access_address,
+            crc_init,
+            header,
+            length: payload.len() as u8,
+            payload,
+        })
+    }
+
+    /// PDU header + payload, as passed to `bitops::pdu_to_bits_with_crc`
+    ///
+    /// The length is recomputed from the payload (`self.length` is ignored).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut pdu = vec![self.header.to_byte(), self.payload.len() as u8];
+        pdu.extend_from_slice(&self.payload);
+
+        pdu
+    }
+
+    fn from_bytes(access_address: u32, crc_init: u32, input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, header) = take(1u8)(input)?;
+        let header = DataHeader::from_byte(header[0]);
 
         let (input, length) = take(1u8)(input)?;
         let length = length[0];
 
-        let (input, address) = MacAddress::from_bytes(input)?;
+        let (input, payload) = take(length)(input)?;
+
+        Ok((
+            input,
+            DataPacket {
+                access_address,
+                crc_init,
+                header,
+                length,
+                payload: payload.to_vec(),
+            },
+        ))
+    }
+}
+
+impl DataHeader {
+    pub fn new(llid: LLID, nesn: bool, sn: bool, md: bool) -> Self {
+        DataHeader { llid, nesn, sn, md }
+    }
+
+    pub fn from_byte(byte: u8) -> Self {
+        let llid = match byte & 0b11 {
+            0b01 => LLID::DataContinuation,
+            0b10 => LLID::DataStart,
+            0b11 => LLID::Control,
+            _ => LLID::Reserved,
+        };
+
+        DataHeader {
+            llid,
+            nesn: byte & 0b100 != 0,
+            sn: byte & 0b1000 != 0,
+            md: byte & 0b10000 != 0,
+        }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        let llid = match self.llid {
+            LLID::Reserved => 0b00,
+            LLID::DataContinuation => 0b01,
+            LLID::DataStart => 0b10,
+            LLID::Control => 0b11,
+        };
+
+        llid | (self.nesn as u8) << 2 | (self.sn as u8) << 3 | (self.md as u8) << 4
+    }
+}
+
+impl Advertisement {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (pdu_type, length)) = PDUHeader::from_bytes(input)?;
+        let (input, payload) = take(length)(input)?;
+
+        let (mut payload, address) = MacAddress::from_bytes(payload)?;
 
         let mut data = Vec::new();
-        let mut input = input;
 
-        while let Ok((remain, adv_data)) = AdvData::from_bytes(input) {
+        while let Ok((remain, adv_data)) = AdvData::from_bytes(payload) {
             data.push(adv_data);
-            input = remain;
+            payload = remain;
         }
 
         Ok((
@@ -186,6 +672,184 @@ impl Advertisement {
     }
 }
 
+impl Advertisement {
+    /// AdvA + AdvData (without the PDU header), as passed to `bitops::packet_to_bits`
+    pub fn payload(&self) -> Vec<u8> {
+        let mut payload = self.address.address.to_vec();
+
+        for adv_data in &self.data {
+            payload.extend(adv_data.encode());
+        }
+
+        payload
+    }
+
+    /// PDU header + payload, as passed to `bitops::pdu_to_bits`
+    ///
+    /// The length is recomputed from the payload (`self.length` is ignored).
+    pub fn encode(&self) -> Vec<u8> {
+        let payload = self.payload();
+
+        let mut pdu = vec![self.pdu_header.to_byte(), payload.len() as u8];
+        pdu.extend(payload);
+
+        pdu
+    }
+}
+
+impl ScanRequest {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (pdu_type, length)) = PDUHeader::from_bytes(input)?;
+        let (input, payload) = take(length)(input)?;
+
+        let (payload, scanner) = MacAddress::from_bytes(payload)?;
+        let (_, advertiser) = MacAddress::from_bytes(payload)?;
+
+        Ok((
+            input,
+            ScanRequest {
+                pdu_header: pdu_type,
+                length,
+                scanner,
+                advertiser,
+            },
+        ))
+    }
+}
+
+impl ConnectRequest {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (pdu_type, length)) = PDUHeader::from_bytes(input)?;
+        let (input, payload) = take(length)(input)?;
+
+        let (payload, initiator) = MacAddress::from_bytes(payload)?;
+        let (payload, advertiser) = MacAddress::from_bytes(payload)?;
+        let (_, ll_data) = LLData::from_bytes(payload)?;
+
+        Ok((
+            input,
+            ConnectRequest {
+                pdu_header: pdu_type,
+                length,
+                initiator,
+                advertiser,
+                ll_data,
+            },
+        ))
+    }
+}
+
+impl ExtendedAdvertisement {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        use nom::combinator::cond;
+        use nom::number::complete::{le_u16, le_u24, u8 as le_u8};
+
+        let (input, (pdu_type, length)) = PDUHeader::from_bytes(input)?;
+        let (input, payload) = take(length)(input)?;
+
+        // extended header length (6 bits) + AdvMode (2 bits)
+        let (payload, ext_header_length) = le_u8(payload)?;
+        let adv_mode = ext_header_length >> 6;
+        let (data, ext_header) = take(ext_header_length & 0b111111)(payload)?;
+
+        let mut ret = ExtendedAdvertisement {
+            pdu_header: pdu_type,
+            length,
+            adv_mode,
+            address: None,
+            target_address: None,
+            adi: None,
+            aux_ptr: None,
+            tx_power: None,
+            data: data.to_vec(),
+        };
+
+        if ext_header.is_empty() {
+            return Ok((input, ret));
+        }
+
+        let (ext_header, flags) = le_u8(ext_header)?;
+        let has = |bit: u8| flags & (1 << bit) != 0;
+
+        let (ext_header, address) = cond(has(0), MacAddress::from_bytes)(ext_header)?;
+        let (ext_header, target_address) = cond(has(1), MacAddress::from_bytes)(ext_header)?;
+        let (ext_header, _cte_info) = cond(has(2), le_u8)(ext_header)?;
+        let (ext_header, adi) = cond(has(3), le_u16)(ext_header)?;
+        let (ext_header, aux_ptr) = cond(has(4), le_u24)(ext_header)?;
+        let (ext_header, _sync_info) = cond(has(5), take(18u8))(ext_header)?;
+        let (_acad, tx_power) = cond(has(6), le_u8)(ext_header)?;
+
+        ret.address = address;
+        ret.target_address = target_address;
+        ret.adi = adi.map(|adi| Adi {
+            did: adi & 0x0fff,
+            sid: (adi >> 12) as u8,
+        });
+        ret.aux_ptr = aux_ptr.map(|aux| AuxPtr {
+            channel: (aux & 0b111111) as u8,
+            ca: aux & (1 << 6) != 0,
+            offset_units: aux & (1 << 7) != 0,
+            aux_offset: ((aux >> 8) & 0x1fff) as u16,
+            aux_phy: (aux >> 21) as u8,
+        });
+        ret.tx_power = tx_power.map(|p| p as i8);
+
+        Ok((input, ret))
+    }
+}
+
+impl AuxPtr {
+    /// time from the start of this packet to the auxiliary packet [us]
+    pub fn offset_us(&self) -> u32 {
+        let unit = if self.offset_units { 300 } else { 30 };
+        self.aux_offset as u32 * unit
+    }
+}
+
+impl LLData {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        use nom::number::complete::{le_u16, le_u24, u8 as le_u8};
+
+        let (input, access_address) = le_u32(input)?;
+        let (input, crc_init) = le_u24(input)?;
+        let (input, win_size) = le_u8(input)?;
+        let (input, win_offset) = le_u16(input)?;
+        let (input, interval) = le_u16(input)?;
+        let (input, latency) = le_u16(input)?;
+        let (input, timeout) = le_u16(input)?;
+        let (input, channel_map) = take(5u8)(input)?;
+        let (input, hop_sca) = le_u8(input)?;
+
+        Ok((
+            input,
+            LLData {
+                access_address,
+                crc_init,
+                win_size,
+                win_offset,
+                interval,
+                latency,
+                timeout,
+                channel_map: [
+                    channel_map[0],
+                    channel_map[1],
+                    channel_map[2],
+                    channel_map[3],
+                    channel_map[4],
+                ],
+                hop: hop_sca & 0b11111,
+                sca: hop_sca >> 5,
+            },
+        ))
+    }
+}
+
+/// OUI vendor export (`mac-vendors-export.csv`)
+static OUI_CSV: &str = include_str!("../mac-vendors-export.csv");
+
+/// OUI of the demo's own address, listed as "DemoVendor" although it is locally administered
+pub const DEMO_OUI: [u8; 3] = [0x12, 0x34, 0x56];
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Hash, serde::Deserialize)]
 pub struct CsvRecord {
     #[serde(rename = "Mac Prefix")]
@@ -200,6 +864,12 @@ pub struct CsvRecord {
     pub last_update: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OuiInfo {
+    pub vendor: String,
+    pub block_type: String,
+}
+
 impl MacAddress {
     fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
         let (input, address) = take(6u8)(input)?;
@@ -214,10 +884,30 @@ impl MacAddress {
         ))
     }
 
-    pub fn database(&self) -> Option<CsvRecord> {
-        static DATABASE: LazyLock<HashMap<[u8; 3], CsvRecord>> = LazyLock::new(|| {
-            let mut reader = csv::Reader::from_path("./mac-vendors-export.csv").unwrap();
-            let mut map = HashMap::new();
+    /// Classify the address using the TxAdd/RxAdd bit of the PDU header
+    pub fn address_type(&self, tx_add: bool) -> AddrType {
+        if !tx_add {
+            return AddrType::Public;
+        }
+
+        // two most significant bits of the address
+        match self.address[5] >> 6 {
+            0b11 => AddrType::RandomStatic,
+            0b01 => AddrType::ResolvablePrivate,
+            0b00 => AddrType::NonResolvablePrivate,
+            _ => AddrType::Reserved,
+        }
+    }
+
+    /// Look up the vendor of the OUI (first three bytes)
+    ///
+    /// Locally administered (random) addresses have no vendor and return `None`, except for
+    /// `DEMO_OUI`.
+    pub fn database(&self) -> Option<OuiInfo> {
+        // sorted by prefix, built from the embedded IEEE export
+        static DATABASE: LazyLock<Vec<([u8; 3], OuiInfo)>> = LazyLock::new(|| {
+            let mut reader = csv::Reader::from_reader(OUI_CSV.as_bytes());
+            let mut table = Vec::new();
 
             for record in reader.deserialize() {
                 let record: CsvRecord = record.unwrap();
@@ -227,30 +917,68 @@ impl MacAddress {
                     .map(|x| u8::from_str_radix(x, 16).unwrap())
                     .collect::<Vec<_>>();
 
-                map.insert([prefix[0], prefix[1], prefix[2]], record);
+                // MA-M / MA-S blocks are narrower than a three-byte prefix
+                if prefix.len() != 3 {
+                    continue;
+                }
+
+                table.push((
+                    [prefix[0], prefix[1], prefix[2]],
+                    OuiInfo {
+                        vendor: record.vendor,
+                        block_type: record.block_type,
+                    },
+                ));
             }
 
-            map.insert(
-                [0x12, 0x34, 0x56],
-                CsvRecord {
-                    prefix: "12:34:56".to_string(),
+            table.push((
+                DEMO_OUI,
+                OuiInfo {
                     vendor: "DemoVendor".to_string(),
-                    private: false,
                     block_type: "MA-L".to_string(),
-                    last_update: "2025-01-17".to_string(),
                 },
-            );
+            ));
 
-            map
+            table.sort_by_key(|(prefix, _)| *prefix);
+            table.dedup_by_key(|(prefix, _)| *prefix);
+
+            table
         });
 
-        (*DATABASE)
-            .get(&[self.address[5], self.address[4], self.address[3]])
-            .cloned()
+        let prefix = [self.address[5], self.address[4], self.address[3]];
+
+        // U/L bit of the first octet
+        if self.address[5] & 0b10 != 0 && prefix != DEMO_OUI {
+            return None;
+        }
+
+        DATABASE
+            .binary_search_by_key(&prefix, |(prefix, _)| *prefix)
+            .ok()
+            .map(|idx| DATABASE[idx].1.clone())
     }
 }
 
 impl AdvData {
+    /// AD structure of `ad_type` (see `AdType`) with a consistent length
+    pub fn new(ad_type: u8, payload: &[u8]) -> Self {
+        let mut data = vec![ad_type];
+        data.extend_from_slice(payload);
+
+        AdvData {
+            len: data.len() as u8,
+            data,
+        }
+    }
+
+    /// length + AD type + payload (`len` is written as is, even if it does not match)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![self.len];
+        bytes.extend_from_slice(&self.data);
+
+        bytes
+    }
+
     fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
         let (input, len) = take(1u8)(input)?;
         let len = len[0];
@@ -265,6 +993,48 @@ impl AdvData {
             },
         ))
     }
+
+    /// Parse the first byte as the AD Type and the rest as its payload
+    pub fn parsed(&self) -> AdType {
+        let Some((&ad_type, payload)) = self.data.split_first() else {
+            return AdType::Unknown(0, Vec::new());
+        };
+
+        fn list16(payload: &[u8]) -> Vec<u16> {
+            payload
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect()
+        }
+
+        fn list128(payload: &[u8]) -> Vec<u128> {
+            payload
+                .chunks_exact(16)
+                .map(|c| u128::from_le_bytes(c.try_into().unwrap()))
+                .collect()
+        }
+
+        match (ad_type, payload) {
+            (0x01, [flags]) => AdType::Flags(*flags),
+            (0x02, _) => AdType::IncompleteList16(list16(payload)),
+            (0x03, _) => AdType::CompleteList16(list16(payload)),
+            (0x06, _) => AdType::IncompleteList128(list128(payload)),
+            (0x07, _) => AdType::CompleteList128(list128(payload)),
+            (0x08, _) => AdType::ShortenedLocalName(String::from_utf8_lossy(payload).to_string()),
+            (0x09, _) => AdType::CompleteLocalName(String::from_utf8_lossy(payload).to_string()),
+            (0x0a, [power]) => AdType::TxPowerLevel(*power as i8),
+            (0x16, [lo, hi, data @ ..]) => AdType::ServiceData16 {
+                uuid: u16::from_le_bytes([*lo, *hi]),
+                data: data.to_vec(),
+            },
+            (0x19, [lo, hi]) => AdType::Appearance(u16::from_le_bytes([*lo, *hi])),
+            (0xff, [lo, hi, data @ ..]) => AdType::ManufacturerData {
+                company: u16::from_le_bytes([*lo, *hi]),
+                data: data.to_vec(),
+            },
+            (other, _) => AdType::Unknown(other, payload.to_vec()),
+        }
+    }
 }
 
 impl core::fmt::Display for MacAddress {
@@ -282,6 +1052,45 @@ impl core::fmt::Display for MacAddress {
     }
 }
 
+impl serde::Serialize for MacAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl core::str::FromStr for MacAddress {
+    type Err = anyhow::Error;
+
+    /// Parse `aa:bb:cc:dd:ee:ff` (or `aa-bb-cc-dd-ee-ff`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let octets = s.split([':', '-']).collect::<Vec<_>>();
+
+        if octets.len() != 6 {
+            anyhow::bail!("invalid MAC address {:?}: expected 6 octets", s);
+        }
+
+        let mut address = [0; 6];
+        for (i, octet) in octets.iter().enumerate() {
+            if octet.len() != 2 || !octet.bytes().all(|c| c.is_ascii_hexdigit()) {
+                anyhow::bail!("invalid MAC address {:?}: bad octet {:?}", s, octet);
+            }
+
+            // stored in the over-the-air (little-endian) order
+            address[5 - i] = u8::from_str_radix(octet, 16)?;
+        }
+
+        Ok(MacAddress { address })
+    }
+}
+
+impl TryFrom<&str> for MacAddress {
+    type Error = anyhow::Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 impl core::fmt::Display for PDUHeader {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self.pdu_type {
@@ -292,6 +1101,7 @@ impl core::fmt::Display for PDUHeader {
             PDUType::ScanRsp => write!(f, "SCAN_RSP"),
             PDUType::ConnectReq => write!(f, "CONNECT_REQ"),
             PDUType::AdvScanInd => write!(f, "ADV_SCAN_IND"),
+            PDUType::AdvExtInd => write!(f, "ADV_EXT_IND"),
             PDUType::Unknown(x) => write!(f, "Unknown(0x{:x})", x),
         }?;
 
@@ -352,25 +1162,859 @@ impl core::fmt::Display for Advertisement {
     }
 }
 
+impl core::fmt::Display for ScanRequest {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        writeln!(
+            f,
+            "header={:<30} len={}\tscanner={}\tadvertiser={}",
+            format!("{}", self.pdu_header),
+            self.length,
+            self.scanner,
+            self.advertiser,
+        )
+    }
+}
+
+impl core::fmt::Display for ConnectRequest {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        writeln!(
+            f,
+            "header={:<30} len={}\tinitiator={}\tadvertiser={}",
+            format!("{}", self.pdu_header),
+            self.length,
+            self.initiator,
+            self.advertiser,
+        )?;
+
+        writeln!(f, "{}", self.ll_data)
+    }
+}
+
+impl core::fmt::Display for ExtendedAdvertisement {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "header={:<30} len={}\tmode={}",
+            format!("{}", self.pdu_header),
+            self.length,
+            self.adv_mode,
+        )?;
+
+        if let Some(ref address) = self.address {
+            write!(f, "\taddr={}", address)?;
+        }
+        if let Some(ref adi) = self.adi {
+            write!(f, " did={:03x} sid={}", adi.did, adi.sid)?;
+        }
+        if let Some(ref aux) = self.aux_ptr {
+            write!(f, " aux=ch{}+{}us", aux.channel, aux.offset_us())?;
+        }
+
+        writeln!(f)
+    }
+}
+
+impl core::fmt::Display for LLData {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "aa={:08x} crc_init={:06x} win_size={} win_offset={} interval={} latency={} timeout={} chm={:02x?} hop={} sca={}",
+            self.access_address,
+            self.crc_init,
+            self.win_size,
+            self.win_offset,
+            self.interval,
+            self.latency,
+            self.timeout,
+            self.channel_map,
+            self.hop,
+            self.sca,
+        )
+    }
+}
+
 impl core::fmt::Display for PacketInner {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
             PacketInner::Advertisement(adv) => write!(f, "{}", adv),
+            PacketInner::ScanRequest(req) => write!(f, "{}", req),
+            PacketInner::ConnectRequest(req) => write!(f, "{}", req),
+            PacketInner::ExtendedAdvertisement(adv) => write!(f, "{}", adv),
+            PacketInner::Data(data) => write!(f, "{}", data),
             PacketInner::Unimplemented(other) => write!(f, "Unimplemented({:x})", other),
         }
     }
 }
 
+impl core::fmt::Display for DataPacket {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        writeln!(
+            f,
+            "aa={:08x} llid={:?} nesn={} sn={} md={} len={}\tpayload={:02x?}",
+            self.access_address,
+            self.header.llid,
+            self.header.nesn as u8,
+            self.header.sn as u8,
+            self.header.md as u8,
+            self.length,
+            self.payload,
+        )
+    }
+}
+
+impl core::fmt::Display for BluetoothPacket {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        writeln!(
+            f,
+            "crc={:02x}{:02x}{:02x} ({})",
+            self.crc[0],
+            self.crc[1],
+            self.crc[2],
+            if self.crc_valid { "ok" } else { "bad" },
+        )?;
+
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl core::fmt::Display for Bluetooth {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "freq={}MHz", self.freq)?;
+
+        if let Some(ref bytes_packet) = self.bytes_packet {
+            write!(f, " aa={:08x}", bytes_packet.aa)?;
+        }
+
+        if !self.remain.is_empty() {
+            write!(f, " remain={:02x?}", self.remain)?;
+        }
+
+        write!(f, " {}", self.packet)
+    }
+}
+
 impl core::fmt::Display for AdvData {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(f, "len={} data={:02x?}", self.len, self.data)
     }
 }
 
+// a few common Company Identifiers (Assigned Numbers 7.1)
+fn company_name(company: u16) -> Option<&'static str> {
+    match company {
+        0x0006 => Some("Microsoft"),
+        0x004c => Some("Apple"),
+        0x0059 => Some("Nordic Semiconductor"),
+        0x0075 => Some("Samsung"),
+        0x00e0 => Some("Google"),
+        _ => None,
+    }
+}
+
+impl core::fmt::Display for AdType {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fn hex(data: &[u8]) -> String {
+            data.iter()
+                .map(|x| format!("{:02x}", x))
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+
+        fn list<T>(values: &[T], fmt: impl Fn(&T) -> String) -> String {
+            values.iter().map(fmt).collect::<Vec<_>>().join(", ")
+        }
+
+        let uuid16 = |uuid: &u16| format!("0x{:04x}", uuid);
+        let uuid128 = |uuid: &u128| {
+            let s = format!("{:032x}", uuid);
+            format!(
+                "{}-{}-{}-{}-{}",
+                &s[..8],
+                &s[8..12],
+                &s[12..16],
+                &s[16..20],
+                &s[20..]
+            )
+        };
+
+        match self {
+            AdType::Flags(flags) => {
+                const NAMES: [&str; 5] = [
+                    "LE Limited Discoverable",
+                    "LE General Discoverable",
+                    "BR/EDR Not Supported",
+                    "LE and BR/EDR Controller",
+                    "LE and BR/EDR Host",
+                ];
+
+                let names = NAMES
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| flags & (1 << i) != 0)
+                    .map(|(_, name)| *name)
+                    .collect::<Vec<_>>();
+
+                write!(f, "Flags: {}", names.join(", "))
+            }
+            AdType::IncompleteList16(uuids) => {
+                write!(f, "Incomplete 16-bit UUIDs: {}", list(uuids, uuid16))
+            }
+            AdType::CompleteList16(uuids) => {
+                write!(f, "Complete 16-bit UUIDs: {}", list(uuids, uuid16))
+            }
+            AdType::IncompleteList128(uuids) => {
+                write!(f, "Incomplete 128-bit UUIDs: {}", list(uuids, uuid128))
+            }
+            AdType::CompleteList128(uuids) => {
+                write!(f, "Complete 128-bit UUIDs: {}", list(uuids, uuid128))
+            }
+            AdType::ShortenedLocalName(name) => write!(f, "Shortened Local Name: {}", name),
+            AdType::CompleteLocalName(name) => write!(f, "Complete Local Name: {}", name),
+            AdType::TxPowerLevel(power) => write!(f, "Tx Power Level: {} dBm", power),
+            AdType::ServiceData16 { uuid, data } => {
+                write!(f, "Service Data: {} {}", uuid16(uuid), hex(data))
+            }
+            AdType::Appearance(appearance) => write!(f, "Appearance: 0x{:04x}", appearance),
+            AdType::ManufacturerData { company, data } => match company_name(*company) {
+                Some(name) => write!(
+                    f,
+                    "Manufacturer: {} (0x{:04X}) {}",
+                    name,
+                    company,
+                    hex(data)
+                ),
+                None => write!(f, "Manufacturer: 0x{:04X} {}", company, hex(data)),
+            },
+            AdType::Unknown(ad_type, data) => {
+                write!(f, "AD Type 0x{:02x}: {}", ad_type, hex(data))
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // use libbtbb_sys::*;
 
+    use crate::test_support::packets::{adv_bytes, byte_packet};
+
+    #[test]
+    fn truncated() {
+        for bytes in [vec![], vec![0xd6, 0xbe]] {
+            let ret = super::Bluetooth::from_bytes(byte_packet(bytes), 2426);
+            assert!(matches!(ret, Err(super::DecodeError::Truncated)));
+        }
+
+        // access address and header only
+        let ret = super::Bluetooth::from_bytes(
+            byte_packet(vec![0xd6, 0xbe, 0x89, 0x8e, 0x00, 0x06, 0x00, 0x00, 0x00]),
+            2426,
+        );
+        assert!(matches!(ret, Err(super::DecodeError::Truncated)));
+    }
+
+    #[test]
+    fn content_id() {
+        let pdu = crate::test_support::packets::ADV_PDU;
+        let packet = crate::test_support::packets::adv_packet;
+
+        let copies = [2402, 2426, 2480].map(|freq| packet(&pdu, freq));
+        assert!(copies
+            .iter()
+            .all(|p| p.content_id() == copies[0].content_id()));
+
+        let mut modified = pdu;
+        modified[2] = 0x01;
+        assert_ne!(packet(&modified, 2402).content_id(), copies[0].content_id());
+
+        // copies which differ only in the CRC (e.g. a corrupted one)
+        let mut bytes = super::ADV_ACCESS_ADDRESS.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&pdu);
+        bytes.extend_from_slice(&[0x00, 0x00, 0x00]);
+        let corrupted = super::Bluetooth::from_bytes(byte_packet(bytes), 2426).unwrap();
+        assert!(!corrupted.packet.crc_valid);
+        assert_eq!(corrupted.content_id(), copies[0].content_id());
+    }
+
+    #[test]
+    fn payload_contains() {
+        let adv = |data| super::Bluetooth {
+            bytes_packet: None,
+            packet: super::BluetoothPacket {
+                inner: super::PacketInner::Advertisement(super::Advertisement {
+                    pdu_header: super::PDUHeader {
+                        pdu_type: super::PDUType::AdvInd,
+                        rfu: false,
+                        ch_sel: false,
+                        tx_add: false,
+                        rx_add: false,
+                    },
+                    length: 6,
+                    address: super::MacAddress { address: [0; 6] },
+                    data,
+                }),
+                crc: [0; 3],
+                crc_valid: true,
+            },
+            remain: Vec::new(),
+            freq: 2402,
+        };
+
+        let packet = adv(vec![
+            super::AdvData::new(0x09, b"hello:World"),
+            super::AdvData::new(0xff, &[0x4c, 0x00, 0x12, 0x19]),
+        ]);
+
+        assert!(packet.payload_contains(b"hello"));
+        assert!(packet.payload_contains(b"o:W"));
+        assert!(!packet.payload_contains(b"HELLO"));
+
+        // hex, including the AD type
+        assert!(packet.payload_contains(&[0x4c, 0x00]));
+        assert!(packet.payload_contains(&[0xff, 0x4c]));
+        assert!(!packet.payload_contains(&[0x00, 0x4c]));
+
+        // not across two AD structures
+        assert!(!packet.payload_contains(b"World\xff"));
+
+        assert!(packet.payload_contains(b""));
+        assert!(!adv(Vec::new()).payload_contains(b"hello"));
+    }
+
+    #[test]
+    fn pdu_header_flags() {
+        let header = super::PDUHeader::from_byte(0x10).unwrap();
+        assert!(header.rfu && !header.ch_sel && !header.tx_add && !header.rx_add);
+
+        let header = super::PDUHeader::from_byte(0x20).unwrap();
+        assert!(!header.rfu && header.ch_sel && !header.tx_add && !header.rx_add);
+
+        let header = super::PDUHeader::from_byte(0x40).unwrap();
+        assert!(!header.rfu && !header.ch_sel && header.tx_add && !header.rx_add);
+
+        let header = super::PDUHeader::from_byte(0x80).unwrap();
+        assert!(!header.rfu && !header.ch_sel && !header.tx_add && header.rx_add);
+
+        for byte in [0x00, 0x10, 0x20, 0x40, 0x80, 0xf5, 0x63] {
+            assert_eq!(super::PDUHeader::from_byte(byte).unwrap().to_byte(), byte);
+        }
+    }
+
+    #[test]
+    fn pdu_header_length() {
+        let (remain, (header, length)) = super::PDUHeader::from_bytes(&[0x42, 0x09, 0xaa]).unwrap();
+
+        assert!(matches!(header.pdu_type, super::PDUType::AdvNonconnInd));
+        assert!(header.tx_add && !header.rx_add);
+        assert_eq!(length, 9);
+        assert_eq!(remain, &[0xaa]);
+
+        assert!(super::PDUHeader::from_bytes(&[0x42]).is_err());
+    }
+
+    #[test]
+    fn adv_length_from_header() {
+        // ADV_IND with 9 bytes of payload followed by 2 bytes beyond the length
+        let ret = super::Bluetooth::from_bytes(
+            byte_packet(vec![
+                0xd6, 0xbe, 0x89, 0x8e, 0x00, 0x09, 0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x02, 0x01,
+                0x06, 0x02, 0x01, 0x00, 0x00, 0x00,
+            ]),
+            2426,
+        )
+        .unwrap();
+
+        let super::PacketInner::Advertisement(adv) = ret.packet.inner else {
+            panic!("not an advertisement");
+        };
+        assert_eq!(adv.length, 9);
+        assert_eq!(adv.data.len(), 1);
+        assert_eq!(ret.remain, vec![0x02, 0x01]);
+
+        // the length does not cover the AdvA
+        let ret = super::Bluetooth::from_bytes(
+            byte_packet(vec![
+                0xd6, 0xbe, 0x89, 0x8e, 0x00, 0x03, 0x12, 0x34, 0x56, 0x00, 0x00, 0x00,
+            ]),
+            2426,
+        );
+        assert!(matches!(ret, Err(super::DecodeError::Truncated)));
+    }
+
+    #[test]
+    fn identical_advertisements_are_equal() {
+        let bytes = vec![
+            0xd6, 0xbe, 0x89, 0x8e, 0x40, 0x09, 0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x02, 0x01,
+            0x06, 0x00, 0x00, 0x00,
+        ];
+
+        let a = super::Bluetooth::from_bytes(byte_packet(bytes.clone()), 2426).unwrap();
+        let b = super::Bluetooth::from_bytes(byte_packet(bytes), 2402).unwrap();
+
+        assert_eq!(a.packet.inner, b.packet.inner);
+
+        let mut seen = std::collections::HashSet::new();
+        assert!(seen.insert(a.packet.inner));
+        assert!(!seen.insert(b.packet.inner));
+
+        // differs only in TxAdd
+        let c = super::Bluetooth::from_bytes(
+            byte_packet(vec![
+                0xd6, 0xbe, 0x89, 0x8e, 0x00, 0x09, 0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x02, 0x01,
+                0x06, 0x00, 0x00, 0x00,
+            ]),
+            2426,
+        )
+        .unwrap();
+        assert!(seen.insert(c.packet.inner));
+    }
+
+    #[test]
+    fn extended_advertisement() {
+        let bytes = [
+            0xd6, 0xbe, 0x89, 0x8e, // access address
+            0x47, 0x0e, // ADV_EXT_IND, TxAdd
+            0x0d, // AdvMode = 0, extended header length = 13
+            0x59, // AdvA | ADI | AuxPtr | TxPower
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, // AdvA
+            0x23, 0x51, // DID = 0x123, SID = 5
+            0x54, 0x64, 0x20, // channel 20, CA, 30 us x 100, LE 2M
+            0xf6, // -10 dBm
+        ];
+
+        let (remain, inner) =
+            super::PacketInner::from_bytes(&bytes, &super::Connections::new()).unwrap();
+        assert!(remain.is_empty());
+
+        let super::PacketInner::ExtendedAdvertisement(adv) = inner else {
+            panic!("not an extended advertisement");
+        };
+
+        assert!(matches!(adv.pdu_header.pdu_type, super::PDUType::AdvExtInd));
+        assert!(adv.pdu_header.tx_add);
+        assert_eq!(adv.adv_mode, 0);
+        assert_eq!(
+            adv.address.unwrap().address,
+            [0x11, 0x22, 0x33, 0x44, 0x55, 0x66]
+        );
+        assert!(adv.target_address.is_none());
+        assert_eq!(adv.adi, Some(super::Adi { did: 0x123, sid: 5 }));
+
+        let aux = adv.aux_ptr.unwrap();
+        assert_eq!(aux.channel, 20);
+        assert!(aux.ca);
+        assert!(!aux.offset_units);
+        assert_eq!(aux.aux_offset, 100);
+        assert_eq!(aux.offset_us(), 3000);
+        assert_eq!(aux.aux_phy, 1);
+
+        assert_eq!(adv.tx_power, Some(-10));
+        assert!(adv.data.is_empty());
+    }
+
+    #[test]
+    fn advertisement_round_trip() {
+        let adv = super::Advertisement {
+            pdu_header: super::PDUHeader {
+                pdu_type: super::PDUType::AdvInd,
+                rfu: false,
+                ch_sel: false,
+                tx_add: true,
+                rx_add: false,
+            },
+            length: 6 + 3 + 8,
+            address: "c0:ff:ee:00:00:01".parse().unwrap(),
+            data: vec![
+                super::AdvData::new(0x01, &[0x06]),
+                super::AdvData::new(0x09, b"raptor"),
+            ],
+        };
+
+        let pdu = adv.encode();
+        assert_eq!(pdu[..2], [0x40, 17]);
+        assert_eq!(adv.data[1].encode(), b"\x07\x09raptor");
+
+        let bits = crate::bitops::pdu_to_bits(&pdu, 2426, super::ADV_ACCESS_ADDRESS);
+        let byte_packet = crate::bitops::bits_to_packet(&bits, 2426).unwrap();
+        let bt = super::Bluetooth::from_bytes(byte_packet, 2426).unwrap();
+
+        assert!(bt.packet.crc_valid);
+
+        let super::PacketInner::Advertisement(decoded) = bt.packet.inner else {
+            panic!("not an advertisement");
+        };
+        assert_eq!(decoded, adv);
+
+        assert_eq!(decoded.data[0].parsed(), super::AdType::Flags(0x06));
+        assert_eq!(
+            decoded.data[1].parsed(),
+            super::AdType::CompleteLocalName("raptor".to_string())
+        );
+    }
+
+    #[test]
+    fn crc_valid() {
+        let bytes = [0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x02, 0x01, 0x06];
+        let bits = crate::bitops::packet_to_bits(&bytes, 2426, 0x8e89bed6);
+
+        let byte_packet = crate::bitops::bits_to_packet(&bits, 2426).unwrap();
+        let Ok(bt) = super::Bluetooth::from_bytes(byte_packet, 2426) else {
+            panic!("from_bytes failed");
+        };
+
+        assert!(bt.packet.crc_valid);
+    }
+
+    #[test]
+    fn mac_address_from_str() {
+        let mac: super::MacAddress = "18:09:d4:00:81:fb".parse().unwrap();
+
+        assert_eq!(mac.address, [0xfb, 0x81, 0x00, 0xd4, 0x09, 0x18]);
+        assert_eq!(mac.to_string(), "18:09:d4:00:81:fb");
+
+        let mac = super::MacAddress::try_from("18-09-D4-00-81-FB").unwrap();
+        assert_eq!(mac.to_string(), "18:09:d4:00:81:fb");
+    }
+
+    #[test]
+    fn mac_address_from_str_malformed() {
+        for s in [
+            "",
+            "18:09:d4:00:81",
+            "18:09:d4:00:81:fb:00",
+            "18:09:d4:00:81:f",
+            "18:09:d4:00:81:fbb",
+            "18:09:d4:00:81:zz",
+            "+1:09:d4:00:81:fb",
+        ] {
+            let err = s.parse::<super::MacAddress>().unwrap_err();
+            assert!(err.to_string().starts_with("invalid MAC address"));
+        }
+    }
+
+    #[test]
+    fn oui_lookup() {
+        let apple: super::MacAddress = "00:03:93:12:34:56".parse().unwrap();
+        let info = apple.database().expect("apple prefix not found");
+
+        assert_eq!(info.vendor, "Apple, Inc.");
+        assert_eq!(info.block_type, "MA-L");
+
+        // same prefix with the locally administered bit set
+        let random: super::MacAddress = "02:03:93:12:34:56".parse().unwrap();
+        assert!(random.database().is_none());
+
+        // the demo advertises from a locally administered address of its own vendor
+        let demo: super::MacAddress = "12:34:56:00:01:00".parse().unwrap();
+        assert_eq!(
+            demo.database().expect("demo prefix not found").vendor,
+            "DemoVendor"
+        );
+    }
+
+    #[test]
+    fn address_type() {
+        use super::AddrType;
+
+        let mac: super::MacAddress = "c4:00:00:00:00:01".parse().unwrap();
+        assert_eq!(mac.address_type(false), AddrType::Public);
+        assert_eq!(mac.address_type(true), AddrType::RandomStatic);
+
+        let mac: super::MacAddress = "44:00:00:00:00:01".parse().unwrap();
+        assert_eq!(mac.address_type(true), AddrType::ResolvablePrivate);
+
+        let mac: super::MacAddress = "04:00:00:00:00:01".parse().unwrap();
+        assert_eq!(mac.address_type(true), AddrType::NonResolvablePrivate);
+
+        let mac: super::MacAddress = "84:00:00:00:00:01".parse().unwrap();
+        assert_eq!(mac.address_type(true), AddrType::Reserved);
+    }
+
+    #[test]
+    fn connect_req() {
+        let bytes = [
+            0xd6, 0xbe, 0x89, 0x8e, // access address
+            0xc5, 0x22, // header (CONNECT_IND, TxAdd, RxAdd), length
+            0x9c, 0x2b, 0x51, 0x3e, 0x7a, 0x5d, // InitA
+            0x01, 0x00, 0x00, 0x56, 0x34, 0x12, // AdvA
+            0x4e, 0x9b, 0x9a, 0xaf, // AA
+            0x1d, 0x3c, 0x7b, // CRCInit
+            0x02, // WinSize
+            0x0f, 0x00, // WinOffset
+            0x24, 0x00, // Interval
+            0x00, 0x00, // Latency
+            0xf4, 0x01, // Timeout
+            0xff, 0xff, 0xff, 0xff, 0x1f, // ChM
+            0x27, // Hop, SCA
+        ];
+
+        let (remain, inner) =
+            super::PacketInner::from_bytes(&bytes, &super::Connections::new()).unwrap();
+        assert!(remain.is_empty());
+
+        let super::PacketInner::ConnectRequest(req) = inner else {
+            panic!("not a CONNECT_IND: {:?}", inner);
+        };
+
+        assert_eq!(req.initiator.to_string(), "5d:7a:3e:51:2b:9c");
+        assert_eq!(req.advertiser.to_string(), "12:34:56:00:00:01");
+        assert_eq!(req.ll_data.access_address, 0xaf9a9b4e);
+        assert_eq!(req.ll_data.crc_init, 0x7b3c1d);
+        assert_eq!(req.ll_data.win_size, 2);
+        assert_eq!(req.ll_data.win_offset, 15);
+        assert_eq!(req.ll_data.interval, 36);
+        assert_eq!(req.ll_data.timeout, 500);
+        assert_eq!(req.ll_data.channel_map, [0xff, 0xff, 0xff, 0xff, 0x1f]);
+        assert_eq!(req.ll_data.hop, 7);
+        assert_eq!(req.ll_data.sca, 1);
+    }
+
+    #[test]
+    fn scan_req() {
+        let bytes = [
+            0xd6, 0xbe, 0x89, 0x8e, // access address
+            0x43, 0x0c, // header (SCAN_REQ, TxAdd), length
+            0x9c, 0x2b, 0x51, 0x3e, 0x7a, 0x5d, // ScanA
+            0x01, 0x00, 0x00, 0x56, 0x34, 0x12, // AdvA
+        ];
+
+        let (_, inner) =
+            super::PacketInner::from_bytes(&bytes, &super::Connections::new()).unwrap();
+
+        let super::PacketInner::ScanRequest(req) = inner else {
+            panic!("not a SCAN_REQ: {:?}", inner);
+        };
+
+        assert_eq!(req.scanner.to_string(), "5d:7a:3e:51:2b:9c");
+        assert_eq!(req.advertiser.to_string(), "12:34:56:00:00:01");
+    }
+
+    #[test]
+    fn ad_type_flags() {
+        let adv_data = super::AdvData {
+            len: 2,
+            data: vec![0x01, 0x06],
+        };
+
+        assert_eq!(adv_data.parsed(), super::AdType::Flags(0x06));
+    }
+
+    #[test]
+    fn ad_type_manufacturer_data() {
+        let adv_data = super::AdvData {
+            len: 6,
+            data: vec![0xff, 0x4c, 0x00, 0x10, 0x05, 0x01],
+        };
+
+        assert_eq!(
+            adv_data.parsed(),
+            super::AdType::ManufacturerData {
+                company: 0x004c,
+                data: vec![0x10, 0x05, 0x01],
+            }
+        );
+    }
+
+    #[test]
+    fn ad_type_display() {
+        let display = |data: &[u8]| {
+            super::AdvData {
+                len: data.len() as u8,
+                data: data.to_vec(),
+            }
+            .parsed()
+            .to_string()
+        };
+
+        assert_eq!(
+            display(&[0x01, 0x06]),
+            "Flags: LE General Discoverable, BR/EDR Not Supported"
+        );
+        assert_eq!(
+            display(&[0xff, 0x4c, 0x00, 0x10, 0x05]),
+            "Manufacturer: Apple (0x004C) 10 05"
+        );
+        assert_eq!(
+            display(&[0x03, 0x0f, 0x18]),
+            "Complete 16-bit UUIDs: 0x180f"
+        );
+        assert_eq!(display(&[0x0a, 0xfc]), "Tx Power Level: -4 dBm");
+        assert_eq!(display(&[0x42, 0x01]), "AD Type 0x42: 01");
+    }
+
+    #[test]
+    fn ad_type_local_name() {
+        let mut data = vec![0x09];
+        data.extend_from_slice("Apple Pencil ✏".as_bytes());
+
+        let adv_data = super::AdvData {
+            len: data.len() as u8,
+            data,
+        };
+
+        assert_eq!(
+            adv_data.parsed(),
+            super::AdType::CompleteLocalName("Apple Pencil ✏".to_string())
+        );
+    }
+
+    #[test]
+    fn data_empty_pdu() {
+        const AA: u32 = 0x50654c13;
+        const CRC_INIT: u32 = 0x123456;
+
+        let mut bytes = AA.to_le_bytes().to_vec();
+        let pdu = [0b0000_0101, 0x00]; // LLID=01 NESN=1, empty
+        bytes.extend_from_slice(&pdu);
+        bytes.extend_from_slice(&crate::bitops::crc24(&pdu, CRC_INIT));
+
+        let packet = super::Bluetooth::from_bytes(byte_packet(bytes.clone()), 2404).unwrap();
+        assert!(matches!(
+            packet.packet.inner,
+            super::PacketInner::Unimplemented(AA)
+        ));
+
+        let connections = super::Connections::new();
+        connections.register(AA, CRC_INIT);
+
+        let packet =
+            super::Bluetooth::from_bytes_with(byte_packet(bytes), 2404, &connections).unwrap();
+        assert!(packet.packet.crc_valid);
+
+        let super::PacketInner::Data(ref data) = packet.packet.inner else {
+            panic!("unexpected packet: {:?}", packet.packet.inner);
+        };
+        assert_eq!(data.access_address, AA);
+        assert_eq!(data.header.llid, super::LLID::DataContinuation);
+        assert!(data.header.nesn);
+        assert!(!data.header.sn);
+        assert!(!data.header.md);
+        assert_eq!(data.length, 0);
+        assert!(data.payload.is_empty());
+    }
+
+    #[test]
+    fn connections_evict_oldest() {
+        let connections = super::Connections::with_capacity(2);
+        connections.register(1, 0x111111);
+        connections.register(2, 0x222222);
+        connections.register(1, 0x111111); // 2 is the oldest now
+        connections.register(3, 0x333333);
+
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections.crc_init(1), Some(0x111111));
+        assert_eq!(connections.crc_init(2), None);
+        assert_eq!(connections.crc_init(3), Some(0x333333));
+
+        // clones share the table, other registries do not
+        connections.clone().register(4, 0x444444);
+        assert_eq!(connections.crc_init(4), Some(0x444444));
+        assert_eq!(super::Connections::new().crc_init(4), None);
+    }
+
+    #[test]
+    fn connect_ind_registers_connection() {
+        let mut bytes = vec![0xd6, 0xbe, 0x89, 0x8e];
+        let pdu = [
+            0xc5, 0x22, // header (CONNECT_IND, TxAdd, RxAdd), length
+            0x9c, 0x2b, 0x51, 0x3e, 0x7a, 0x5d, // InitA
+            0x01, 0x00, 0x00, 0x56, 0x34, 0x12, // AdvA
+            0x4e, 0x9b, 0x9a, 0xaf, // AA
+            0x1d, 0x3c, 0x7b, // CRCInit
+            0x02, // WinSize
+            0x0f, 0x00, // WinOffset
+            0x24, 0x00, // Interval
+            0x00, 0x00, // Latency
+            0xf4, 0x01, // Timeout
+            0xff, 0xff, 0xff, 0xff, 0x1f, // ChM
+            0x27, // Hop, SCA
+        ];
+        bytes.extend_from_slice(&pdu);
+        let crc = crate::bitops::crc24(&pdu, crate::bitops::ADV_CRC_INIT);
+
+        // a corrupted CONNECT_IND is not followed
+        let connections = super::Connections::new();
+        let mut corrupted = bytes.clone();
+        corrupted.extend_from_slice(&[crc[0] ^ 1, crc[1], crc[2]]);
+        super::Bluetooth::from_bytes_with(byte_packet(corrupted), 2402, &connections).unwrap();
+        assert!(connections.is_empty());
+
+        bytes.extend_from_slice(&crc);
+        super::Bluetooth::from_bytes_with(byte_packet(bytes), 2402, &connections).unwrap();
+        assert_eq!(connections.crc_init(0xaf9a9b4e), Some(0x7b3c1d));
+    }
+
+    #[test]
+    fn data_round_trip() {
+        const AA: u32 = 0x71764129;
+        const CRC_INIT: u32 = 0x5a5a5a;
+
+        let header = super::DataHeader::new(super::LLID::DataStart, false, true, false);
+        let data = super::DataPacket::new(
+            AA,
+            CRC_INIT,
+            header.clone(),
+            vec![0x03, 0x00, 0x04, 0x00, 0x0a, 0x01],
+        )
+        .unwrap();
+        let inner = super::PacketInner::Data(data.clone());
+
+        // data channel 0 is whitened with its own channel index
+        let bits = inner.to_bits(2404).unwrap();
+
+        let connections = super::Connections::new();
+        connections.register(AA, CRC_INIT);
+
+        let byte_packet = crate::bitops::bits_to_packet(&bits, 2404).unwrap();
+        assert_eq!(byte_packet.aa, AA);
+
+        let packet = super::Bluetooth::from_bytes_with(byte_packet, 2404, &connections).unwrap();
+        assert!(packet.packet.crc_valid);
+        assert_eq!(packet.packet.inner, inner);
+        assert_eq!(
+            super::DataHeader::from_byte(data.header.to_byte()),
+            data.header
+        );
+
+        assert!(super::DataPacket::new(AA, CRC_INIT, header.clone(), vec![0; 251]).is_ok());
+        assert!(super::DataPacket::new(AA, CRC_INIT, header, vec![0; 252]).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let packet =
+            crate::test_support::packets::adv_packet(&crate::test_support::packets::ADV_PDU, 2426);
+        let rendered = format!("{}", packet);
+
+        assert!(rendered.contains("freq=2426MHz"));
+        assert!(rendered.contains("aa=8e89bed6"));
+        assert!(rendered.contains("(ok)"));
+        assert!(rendered.contains("12:34:56:00:01:00"));
+    }
+
+    #[test]
+    fn to_json() {
+        let pdu = [
+            0x00, 0x09, 0x00, 0x01, 0x00, 0x56, 0x34, 0x12, 0x02, 0x01, 0x06,
+        ];
+
+        let mut byte_packet = byte_packet(adv_bytes(&pdu));
+        byte_packet.rssi_average = Some(-40.5);
+
+        let packet = super::Bluetooth::from_bytes(byte_packet, 2426).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&packet.to_json()).unwrap();
+
+        assert_eq!(json["freq"], 2426);
+        assert_eq!(json["rssi"], -40.5);
+        assert_eq!(json["timestamp"], serde_json::Value::Null);
+        assert_eq!(json["packet"]["crc_valid"], true);
+
+        let adv = &json["packet"]["inner"]["Advertisement"];
+        assert_eq!(adv["address"], "12:34:56:00:01:00");
+        assert_eq!(adv["pdu_header"]["pdu_type"], "AdvInd");
+        assert_eq!(adv["data"][0]["data"], serde_json::json!([0x01, 0x06]));
+    }
+
     /*
     use super::*;
 
@@ -0,0 +1,122 @@
+use std::io::Write;
+
+use anyhow::Context;
+
+const BTSNOOP_MAGIC: &[u8; 8] = b"btsnoop\0";
+const BTSNOOP_VERSION: u32 = 1;
+
+/// Datalink type: Un-encapsulated HCI (H1)
+pub const DATALINK_UNENCAPSULATED: u32 = 1001;
+
+// microseconds from 0000-01-01 to 1970-01-01
+const EPOCH_DELTA_US: i64 = 0x00dc_ddb3_0f2f_8000;
+
+// record flags
+const FLAG_RECEIVED: u32 = 0x1;
+
+pub struct BtSnoopWriter {
+    out: std::io::BufWriter<std::fs::File>,
+}
+
+impl BtSnoopWriter {
+    pub fn new(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(path.as_ref())
+            .with_context(|| format!("failed to create {}", path.as_ref().display()))?;
+
+        let mut out = std::io::BufWriter::new(file);
+
+        out.write_all(BTSNOOP_MAGIC)?;
+        out.write_all(&BTSNOOP_VERSION.to_be_bytes())?;
+        out.write_all(&DATALINK_UNENCAPSULATED.to_be_bytes())?;
+
+        Ok(Self { out })
+    }
+
+    /// Write the LL bytes (access address + PDU + CRC) as a received packet
+    pub fn write_packet(&mut self, pkt: &crate::bluetooth::Bluetooth) -> anyhow::Result<()> {
+        let byte_packet = pkt
+            .bytes_packet
+            .as_ref()
+            .context("packet has no bytes_packet")?;
+
+        let mut data = byte_packet.bytes.clone();
+        data.extend_from_slice(&pkt.packet.crc);
+
+        let timestamp = byte_packet.timestamp.unwrap_or_else(chrono::Utc::now);
+
+        self.out.write_all(&(data.len() as u32).to_be_bytes())?; // original length
+        self.out.write_all(&(data.len() as u32).to_be_bytes())?; // included length
+        self.out.write_all(&FLAG_RECEIVED.to_be_bytes())?;
+        self.out.write_all(&0u32.to_be_bytes())?; // cumulative drops
+        self.out
+            .write_all(&(timestamp.timestamp_micros() + EPOCH_DELTA_US).to_be_bytes())?;
+        self.out.write_all(&data)?;
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::packets;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rfraptor-{}-{}.btsnoop", std::process::id(), name))
+    }
+
+    #[test]
+    fn header() {
+        let path = temp_path("header");
+
+        let mut writer = BtSnoopWriter::new(&path).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(data.len(), 16);
+        assert_eq!(&data[..8], BTSNOOP_MAGIC);
+        assert_eq!(data[8..12], 1u32.to_be_bytes());
+        assert_eq!(data[12..16], DATALINK_UNENCAPSULATED.to_be_bytes());
+    }
+
+    #[test]
+    fn record() {
+        let path = temp_path("record");
+
+        let bytes = packets::adv_bytes(&packets::ADV_PDU);
+        let byte_packet = crate::bitops::BytePacket {
+            freq: 2402,
+            timestamp: Some(chrono::DateTime::from_timestamp(0, 0).unwrap()),
+            ..packets::byte_packet(bytes.clone())
+        };
+        let packet = crate::bluetooth::Bluetooth::from_bytes(byte_packet, 2402).unwrap();
+
+        let mut writer = BtSnoopWriter::new(&path).unwrap();
+        writer.write_packet(&packet).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let record = &data[16..];
+        let u32_at = |i: usize| u32::from_be_bytes(record[i..i + 4].try_into().unwrap());
+
+        assert_eq!(u32_at(0) as usize, bytes.len());
+        assert_eq!(u32_at(4) as usize, bytes.len());
+        assert_eq!(u32_at(8), FLAG_RECEIVED);
+        assert_eq!(
+            i64::from_be_bytes(record[16..24].try_into().unwrap()),
+            EPOCH_DELTA_US
+        );
+        assert_eq!(&record[24..], &bytes[..]);
+    }
+}
@@ -2,6 +2,7 @@ use liquid_dsp_sys::agc_crcf_get_rssi;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
+use anyhow::Context;
 use num_complex::Complex;
 
 use crate::liquid::{liquid_do_int, liquid_get_pointer};
@@ -11,35 +12,67 @@ pub struct Agc {
     crcf_s: std::ptr::NonNull<liquid_dsp_sys::agc_crcf_s>,
 }
 
-impl Agc {
-    pub fn new() -> Self {
-        let agc_threshold = std::env::var("AGC_THRESHOLD")
+/// Squelch parameters of the AGC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BurstParams {
+    /// squelch threshold [dB]
+    pub threshold_db: f32,
+    /// samples below the threshold before the burst is closed
+    pub timeout: u32,
+    /// AGC loop bandwidth
+    pub bandwidth: f32,
+    /// a burst longer than this is closed by force (e.g. a CW interferer)
+    pub max_burst_samples: usize,
+}
+
+impl Default for BurstParams {
+    fn default() -> Self {
+        let threshold_db = std::env::var("AGC_THRESHOLD")
             .unwrap_or_else(|_| "-27".to_string())
             .parse()
             .expect("AGC_THRESHOLD");
 
-        // log::info!("AGC_THRESHOLD: {}", agc_threshold);
+        Self {
+            threshold_db,
+            timeout: 100,
+            bandwidth: 0.25,
+            // longest LE 1M packet (2128 us) at 2 samples per symbol, with margin
+            max_burst_samples: 8192,
+        }
+    }
+}
+
+impl Agc {
+    pub fn new() -> anyhow::Result<Self> {
+        Self::with_params(BurstParams::default())
+    }
+
+    pub fn with_params(params: BurstParams) -> anyhow::Result<Self> {
+        // log::info!("AGC_THRESHOLD: {}", params.threshold_db);
 
         use liquid_dsp_sys::*;
-        let crcf = unsafe {
-            let obj = liquid_get_pointer(|| agc_crcf_create()).expect("agc_crcf_create");
-            liquid_do_int(|| agc_crcf_set_bandwidth(obj.as_ptr(), 0.25))
-                .expect("agc_crcf_set_bandwidth");
-            liquid_do_int(|| agc_crcf_set_signal_level(obj.as_ptr(), 1e-3))
-                .expect("agc_crcf_set_signal_level");
-
-            liquid_do_int(|| agc_crcf_squelch_enable(obj.as_ptr()))
-                .expect("agc_crcf_squelch_enable");
-            liquid_do_int(|| agc_crcf_squelch_set_threshold(obj.as_ptr(), agc_threshold))
-                .expect("agc_crcf_squelch_set_threshold");
-
-            liquid_do_int(|| agc_crcf_squelch_set_timeout(obj.as_ptr(), 100))
-                .expect("agc_crcf_squelch_set_timeout");
-
-            obj
+        // destroyed by Drop if the configuration fails
+        let agc = Self {
+            crcf_s: liquid_get_pointer(|| unsafe { agc_crcf_create() })
+                .context("agc_crcf_create")?,
         };
 
-        Self { crcf_s: crcf }
+        unsafe {
+            liquid_do_int(|| agc_crcf_set_bandwidth(agc.crcf(), params.bandwidth))
+                .context("agc_crcf_set_bandwidth")?;
+            liquid_do_int(|| agc_crcf_set_signal_level(agc.crcf(), 1e-3))
+                .context("agc_crcf_set_signal_level")?;
+
+            liquid_do_int(|| agc_crcf_squelch_enable(agc.crcf()))
+                .context("agc_crcf_squelch_enable")?;
+            liquid_do_int(|| agc_crcf_squelch_set_threshold(agc.crcf(), params.threshold_db))
+                .context("agc_crcf_squelch_set_threshold")?;
+
+            liquid_do_int(|| agc_crcf_squelch_set_timeout(agc.crcf(), params.timeout))
+                .context("agc_crcf_squelch_set_timeout")?;
+        }
+
+        Ok(agc)
     }
 
     fn crcf(&self) -> *mut liquid_dsp_sys::agc_crcf_s {
@@ -65,12 +98,6 @@ impl Agc {
     }
 }
 
-impl Default for Agc {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl Drop for Agc {
     fn drop(&mut self) {
         liquid_do_int(|| unsafe { liquid_dsp_sys::agc_crcf_destroy(self.crcf()) })
@@ -84,6 +111,26 @@ pub struct Burst {
     pub in_burst: bool,
     rssi_average: f32,
     burst: Vec<Complex<f32>>,
+
+    max_burst_samples: usize,
+    // the current burst was closed by force, skip it until the squelch closes
+    overflowed: bool,
+
+    // timestamps the closed bursts
+    clock: Box<dyn Clock>,
+
+    // see `Burst::record`
+    margin: Option<RecordMargin>,
+    // the last `margin.pre` samples while the squelch is closed
+    history: std::collections::VecDeque<Complex<f32>>,
+    // margin before the current burst
+    pre: Vec<Complex<f32>>,
+    // samples since the last sample of the current burst
+    tail: Vec<Complex<f32>>,
+    // closed burst still collecting its `margin.post` samples
+    recording: Option<Recording>,
+    // see `Burst::take_recording`
+    recorded: std::collections::VecDeque<Recording>,
 }
 
 #[derive(FromPrimitive, Clone, Copy, Debug)]
@@ -100,6 +147,37 @@ pub enum SquelchStatus {
 
 use chrono::prelude::*;
 
+/// Linear map from the AGC rssi [dB] to dBm at the antenna
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RssiCal {
+    pub slope: f32,
+    pub offset: f32,
+}
+
+impl RssiCal {
+    /// HackRF with the total rx gain [dB] (full scale is about -10 dBm without gain)
+    pub fn hackrf(rx_gain: f64) -> Self {
+        Self {
+            slope: 1.0,
+            offset: -10.0 - rx_gain as f32,
+        }
+    }
+}
+
+impl Default for RssiCal {
+    // raw rssi as is
+    fn default() -> Self {
+        Self {
+            slope: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+pub fn rssi_to_dbm(raw: f32, cal: RssiCal) -> f32 {
+    cal.slope * raw + cal.offset
+}
+
 #[derive(Debug, Clone)]
 pub struct Packet {
     pub data: Vec<Complex<f32>>,
@@ -111,38 +189,176 @@ pub struct Packet {
     pub rssi_average: f32,
 }
 
-impl Burst {
-    pub fn new() -> Self {
+/// timestamp and samples of a burst (see `Device::dump_failed_bursts`)
+pub type RawBurst = (DateTime<Utc>, Vec<Complex<f32>>);
+
+/// Samples recorded around each burst (see `Burst::record`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordMargin {
+    /// samples before the squelch opened
+    pub pre: usize,
+    /// samples after the last sample of the burst
+    pub post: usize,
+}
+
+/// A caught burst with its margins
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub timestamp: DateTime<Utc>,
+    /// length of the margin before the burst (shorter than requested at the start of the stream)
+    pub pre: usize,
+    /// length of the burst, `data[pre..pre + len]` is `Packet::data`
+    pub len: usize,
+    pub data: Vec<Complex<f32>>,
+}
+
+/// Source of the burst timestamps
+pub trait Clock: std::fmt::Debug + Send {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Wall clock (the default)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock which only moves when told to, for deterministic timestamps in tests.
+/// Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: std::sync::Arc<std::sync::Mutex<DateTime<Utc>>>,
+}
+
+impl MockClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
         Self {
-            crcf: Agc::new(),
+            now: std::sync::Arc::new(std::sync::Mutex::new(start)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("failed to lock") = now;
+    }
+
+    pub fn advance(&self, delta: chrono::TimeDelta) {
+        *self.now.lock().expect("failed to lock") += delta;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("failed to lock")
+    }
+}
+
+impl Burst {
+    pub fn new() -> anyhow::Result<Self> {
+        Self::with_params(BurstParams::default())
+    }
+
+    pub fn with_params(params: BurstParams) -> anyhow::Result<Self> {
+        Self::with_clock(params, SystemClock)
+    }
+
+    /// Timestamp the bursts with `clock` instead of the wall clock
+    pub fn with_clock(params: BurstParams, clock: impl Clock + 'static) -> anyhow::Result<Self> {
+        Ok(Self {
+            crcf: Agc::with_params(params)?,
             in_burst: false,
             rssi_average: 0.0,
             burst: Vec::new(),
+            max_burst_samples: params.max_burst_samples,
+            overflowed: false,
+            clock: Box::new(clock),
+            margin: None,
+            history: std::collections::VecDeque::new(),
+            pre: Vec::new(),
+            tail: Vec::new(),
+            recording: None,
+            recorded: std::collections::VecDeque::new(),
+        })
+    }
+
+    /// Also record each burst with `margin` samples around it (see `Burst::take_recording`)
+    pub fn record(&mut self, margin: RecordMargin) {
+        self.margin = Some(margin);
+    }
+
+    /// Oldest recording whose post margin is complete
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.recorded.pop_front()
+    }
+
+    /// Complete the pending recording with the post margin received so far (e.g. at the end of the stream)
+    pub fn finish_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            self.recorded.push_back(recording);
+        }
+    }
+
+    // keep the samples around the bursts, `status` is the squelch status of `signal`
+    fn track(&mut self, signal: Complex<f32>, status: SquelchStatus) {
+        let Some(margin) = self.margin else {
+            return;
+        };
+
+        if let Some(ref mut recording) = self.recording {
+            recording.data.push(signal);
+            if recording.data.len() >= recording.pre + recording.len + margin.post {
+                self.finish_recording();
+            }
+        }
+
+        if self.in_burst {
+            match status {
+                SquelchStatus::SignalHi => self.tail.clear(),
+                _ => self.tail.push(signal),
+            }
+        } else if margin.pre > 0 {
+            if self.history.len() == margin.pre {
+                self.history.pop_front();
+            }
+            self.history.push_back(signal);
         }
     }
 
     #[allow(unused)]
     pub fn catcher(&mut self, signal: Complex<f32>) -> Option<Packet> {
         let (signal, status, rssi) = self.crcf.execute(signal);
+        self.track(signal, status);
 
         match status {
             SquelchStatus::Rise => {
                 self.in_burst = true;
+                self.overflowed = false;
                 self.burst.clear();
                 self.rssi_average = 0.;
+
+                self.pre = self.history.drain(..).collect();
+                self.tail.clear();
             }
+            SquelchStatus::SignalHi if self.overflowed => {}
             SquelchStatus::SignalHi => {
                 self.burst.push(signal);
                 self.rssi_average += rssi;
+
+                if self.burst.len() >= self.max_burst_samples {
+                    log::trace!("burst exceeds {} samples", self.max_burst_samples);
+
+                    self.overflowed = true;
+                    return Some(self.close());
+                }
+            }
+            SquelchStatus::Timeout if self.overflowed => {
+                self.overflowed = false;
             }
             SquelchStatus::Timeout => {
-                self.in_burst = false;
-
-                return Some(Packet {
-                    rssi_average: self.rssi_average / self.burst.len() as f32,
-                    data: self.burst.clone(),
-                    timestamp: Utc::now(),
-                });
+                return Some(self.close());
             }
             _x => {
                 // println!("other: {:?}", x);
@@ -153,8 +369,182 @@ impl Burst {
     }
 }
 
-impl Default for Burst {
-    fn default() -> Self {
-        Self::new()
+impl Burst {
+    fn close(&mut self) -> Packet {
+        self.in_burst = false;
+
+        let packet = Packet {
+            rssi_average: self.rssi_average / self.burst.len() as f32,
+            data: std::mem::take(&mut self.burst),
+            timestamp: self.clock.now(),
+        };
+
+        if let Some(margin) = self.margin {
+            // only one burst waits for its post margin at a time
+            self.finish_recording();
+
+            let pre = std::mem::take(&mut self.pre);
+            let tail = std::mem::take(&mut self.tail);
+
+            let complete = tail.len() >= margin.post;
+
+            let pre_len = pre.len();
+            let mut data = pre;
+            data.extend_from_slice(&packet.data);
+            data.extend(tail.into_iter().take(margin.post));
+
+            self.recording = Some(Recording {
+                timestamp: packet.timestamp,
+                pre: pre_len,
+                len: packet.data.len(),
+                data,
+            });
+            if complete {
+                self.finish_recording();
+            }
+        }
+
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // -50 dB floor, a 2000 samples tone, then the floor again
+    fn run(burst: &mut Burst, amplitude: f32) -> Vec<Packet> {
+        (0..5000)
+            .map(|i| {
+                if (1000..3000).contains(&i) {
+                    Complex::from_polar(amplitude, i as f32 * 0.3)
+                } else {
+                    Complex::from_polar(3e-3, i as f32 * 1.7)
+                }
+            })
+            .filter_map(|s| burst.catcher(s))
+            .collect()
+    }
+
+    #[test]
+    fn squelch_threshold() {
+        let params = BurstParams {
+            threshold_db: -20.,
+            timeout: 100,
+            bandwidth: 0.25,
+            max_burst_samples: 8192,
+        };
+
+        // -40 dB
+        assert!(run(&mut Burst::with_params(params).unwrap(), 0.01).is_empty());
+
+        // -6 dB
+        let packets = run(&mut Burst::with_params(params).unwrap(), 0.5);
+        assert_eq!(packets.len(), 1);
+        assert!(packets[0].data.len() >= 1900);
+    }
+
+    #[test]
+    fn burst_is_capped() {
+        let params = BurstParams {
+            threshold_db: -20.,
+            timeout: 100,
+            bandwidth: 0.25,
+            max_burst_samples: 1000,
+        };
+        let mut burst = Burst::with_params(params).unwrap();
+
+        let mut packets = vec![];
+        for i in 0..100_000 {
+            let s = if i < 1000 {
+                Complex::from_polar(3e-3, i as f32 * 1.7)
+            } else {
+                // never ending carrier
+                Complex::from_polar(0.5, i as f32 * 0.3)
+            };
+
+            packets.extend(burst.catcher(s));
+            assert!(burst.burst.len() < params.max_burst_samples);
+        }
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].data.len(), params.max_burst_samples);
+        assert!(!burst.in_burst);
+    }
+
+    #[test]
+    fn record_margin() {
+        for post in [50, 500] {
+            let mut burst = Burst::new().unwrap();
+            burst.record(RecordMargin { pre: 200, post });
+
+            let packets = run(&mut burst, 0.5);
+            assert_eq!(packets.len(), 1);
+
+            let recording = burst.take_recording().expect("no recording");
+            assert!(burst.take_recording().is_none());
+
+            assert_eq!(recording.timestamp, packets[0].timestamp);
+            assert_eq!(recording.pre, 200);
+            assert_eq!(recording.len, packets[0].data.len());
+            assert_eq!(recording.data.len(), 200 + recording.len + post);
+            assert_eq!(
+                recording.data[recording.pre..recording.pre + recording.len],
+                packets[0].data[..]
+            );
+        }
+
+        // nothing is recorded without a margin
+        let mut burst = Burst::new().unwrap();
+        assert_eq!(run(&mut burst, 0.5).len(), 1);
+        assert!(burst.take_recording().is_none());
+    }
+
+    #[test]
+    fn agc_config_error() {
+        // liquid rejects a negative loop bandwidth
+        let params = BurstParams {
+            bandwidth: -1.,
+            ..Default::default()
+        };
+
+        let e = Burst::with_params(params).unwrap_err();
+        assert!(
+            format!("{:#}", e).contains("agc_crcf_set_bandwidth"),
+            "{:#}",
+            e
+        );
+    }
+
+    #[test]
+    fn mock_clock_timestamps() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let clock = MockClock::new(start);
+        let mut burst = Burst::with_clock(BurstParams::default(), clock.clone()).unwrap();
+
+        let first = run(&mut burst, 0.5);
+
+        clock.advance(chrono::TimeDelta::milliseconds(1500));
+        let second = run(&mut burst, 0.5);
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+        assert_eq!(first[0].timestamp, start);
+        assert_eq!(
+            second[0].timestamp,
+            start + chrono::TimeDelta::milliseconds(1500)
+        );
+    }
+
+    #[test]
+    fn rssi_calibration() {
+        assert_eq!(rssi_to_dbm(-20.0, RssiCal::hackrf(64.)), -94.0);
+        assert_eq!(rssi_to_dbm(-20.0, RssiCal::default()), -20.0);
+
+        let cal = RssiCal {
+            slope: 0.5,
+            offset: -60.0,
+        };
+        assert_eq!(rssi_to_dbm(-10.0, cal), -65.0);
     }
 }
@@ -33,6 +33,22 @@ pub struct Synthesizer {
     // len(working_buffer) = num_channels
 }
 
+/// Single channel alternative to `Channelizer`: mix the channel down to DC and decimate
+pub struct Downconverter {
+    decimation: usize,
+
+    nco: core::ptr::NonNull<liquid_dsp_sys::nco_crcf_s>,
+    decimator: core::ptr::NonNull<liquid_dsp_sys::firdecim_crcf_s>,
+
+    #[doc(hidden)]
+    mixed: Vec<Complex<f32>>,
+
+    #[doc(hidden)]
+    working_buffer: Vec<Complex<f32>>,
+}
+
+unsafe impl Send for Downconverter {}
+
 impl Channelizer {
     pub fn new(num_channels: usize) -> Self {
         let analyzer = liquid_get_pointer(|| unsafe {
@@ -54,6 +70,8 @@ impl Channelizer {
         }
     }
 
+    /// `input` is `num_channels / 2` CF32 samples as read from the SDR
+    /// (SoapySDR converts the native CS8 of the HackRF to CF32 in [-1, 1))
     pub fn channelize(&mut self, input: &[Complex<f32>]) -> &[Complex<f32>] {
         debug_assert_eq!(input.len(), self.channel_half);
         debug_assert_eq!(self.working_buffer.len(), self.num_channels);
@@ -69,6 +87,12 @@ impl Channelizer {
 
         &self.working_buffer
     }
+
+    /// Clear the filter state (e.g. after samples were lost)
+    pub fn reset(&mut self) {
+        liquid_do_int(|| unsafe { liquid_dsp_sys::firpfbch2_crcf_reset(self.analyzer.as_ptr()) })
+            .expect("firpfbch2_crcf_reset failed");
+    }
 }
 
 impl Synthesizer {
@@ -108,6 +132,92 @@ impl Synthesizer {
     }
 }
 
+impl Downconverter {
+    /// # Arguments
+    /// * `offset` - frequency of the channel relative to the center [cycles/sample]
+    /// * `decimation` - ratio of the input to the output sample rate
+    pub fn new(offset: f32, decimation: usize) -> Self {
+        let nco = liquid_get_pointer(|| unsafe {
+            liquid_dsp_sys::nco_crcf_create(liquid_dsp_sys::liquid_ncotype_LIQUID_NCO)
+        })
+        .expect("nco_crcf_create failed");
+
+        liquid_do_int(|| unsafe {
+            liquid_dsp_sys::nco_crcf_set_frequency(
+                nco.as_ptr(),
+                2.0 * core::f32::consts::PI * offset,
+            )
+        })
+        .expect("nco_crcf_set_frequency failed");
+
+        let decimator = liquid_get_pointer(|| unsafe {
+            liquid_dsp_sys::firdecim_crcf_create_kaiser(decimation as u32, SYMBOL_DELAY, 60.0)
+        })
+        .expect("firdecim_crcf_create_kaiser failed");
+
+        // unity gain in the passband (the prototype filter sums to `decimation`)
+        liquid_do_int(|| unsafe {
+            liquid_dsp_sys::firdecim_crcf_set_scale(decimator.as_ptr(), 1.0 / decimation as f32)
+        })
+        .expect("firdecim_crcf_set_scale failed");
+
+        Self {
+            decimation,
+            nco,
+            decimator,
+            mixed: Vec::new(),
+            working_buffer: Vec::new(),
+        }
+    }
+
+    /// `input.len()` must be a multiple of the decimation
+    pub fn downconvert(&mut self, input: &[Complex<f32>]) -> &[Complex<f32>] {
+        debug_assert_eq!(input.len() % self.decimation, 0);
+
+        let out_len = input.len() / self.decimation;
+
+        self.mixed.resize(input.len(), Complex::new(0.0, 0.0));
+        self.working_buffer.resize(out_len, Complex::new(0.0, 0.0));
+
+        liquid_do_int(|| unsafe {
+            liquid_dsp_sys::nco_crcf_mix_block_down(
+                self.nco.as_ptr(),
+                input.as_ptr() as *mut _,
+                self.mixed.as_mut_ptr(),
+                input.len() as u32,
+            )
+        })
+        .expect("nco_crcf_mix_block_down failed");
+
+        liquid_do_int(|| unsafe {
+            liquid_dsp_sys::firdecim_crcf_execute_block(
+                self.decimator.as_ptr(),
+                self.mixed.as_mut_ptr(),
+                out_len as u32,
+                self.working_buffer.as_mut_ptr(),
+            )
+        })
+        .expect("firdecim_crcf_execute_block failed");
+
+        &self.working_buffer
+    }
+
+    /// Clear the decimator state (the NCO keeps running)
+    pub fn reset(&mut self) {
+        liquid_do_int(|| unsafe { liquid_dsp_sys::firdecim_crcf_reset(self.decimator.as_ptr()) })
+            .expect("firdecim_crcf_reset failed");
+    }
+}
+
+impl Drop for Downconverter {
+    fn drop(&mut self) {
+        liquid_do_int(|| unsafe { liquid_dsp_sys::nco_crcf_destroy(self.nco.as_ptr()) })
+            .expect("nco_crcf_destroy failed");
+        liquid_do_int(|| unsafe { liquid_dsp_sys::firdecim_crcf_destroy(self.decimator.as_ptr()) })
+            .expect("firdecim_crcf_destroy failed");
+    }
+}
+
 impl Drop for Channelizer {
     fn drop(&mut self) {
         liquid_do_int(|| unsafe { liquid_dsp_sys::firpfbch2_crcf_destroy(self.analyzer.as_ptr()) })
@@ -196,4 +306,194 @@ mod tests {
         println!("RMES: {}", rmes);
         assert!(rmes < 1e-3);
     }
+
+    include!("./def_test_data/synthesizer.rs");
+
+    // random input for every channel per block and its synthesized output
+    #[cfg(feature = "gen_test_data")]
+    fn synthesizer_test_vector(
+        seed: u64,
+        num_channels: usize,
+        blocks: usize,
+    ) -> (Vec<Complex<f32>>, Vec<Complex<f32>>) {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut synthesizer = Synthesizer::new(num_channels);
+
+        let input = (0..num_channels * blocks)
+            .map(|_| Complex::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)))
+            .collect::<Vec<_>>();
+        let output = input
+            .chunks(num_channels)
+            .flat_map(|block| synthesizer.synthesize(block).to_vec())
+            .collect::<Vec<_>>();
+
+        (input, output)
+    }
+
+    // cargo test --features gen_test_data generate_synthesizer_test_data
+    #[cfg(feature = "gen_test_data")]
+    #[test]
+    fn generate_synthesizer_test_data() {
+        const BLOCKS: usize = 16;
+
+        let (input, output) =
+            synthesizer_test_vector(SYNTHESIZER_SEED, SYNTHESIZER_NUM_CHANNELS, BLOCKS);
+
+        let array = |name: &str, values: &[Complex<f32>]| {
+            let mut s = format!("const {}: [Complex<f32>; {}] = [\n", name, values.len());
+            for v in values {
+                s.push_str(&format!(
+                    "    Complex {{\n        re: {:?},\n        im: {:?},\n    }},\n",
+                    v.re, v.im
+                ));
+            }
+            s.push_str("];\n");
+            s
+        };
+
+        let data = [
+            "// generated by `cargo test --features gen_test_data generate_synthesizer_test_data`\n".to_string(),
+            "use num_complex::Complex;\n".to_string(),
+            // only read back by the generator
+            format!(
+                "#[allow(dead_code)]\nconst SYNTHESIZER_SEED: u64 = {};",
+                SYNTHESIZER_SEED
+            ),
+            format!("const SYNTHESIZER_NUM_CHANNELS: usize = {};\n", SYNTHESIZER_NUM_CHANNELS),
+            array("EXPECT_SYNTHESIZER_INPUT", &input),
+            array("EXPECT_SYNTHESIZER_OUTPUT", &output),
+        ]
+        .join("\n");
+
+        std::fs::write(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/def_test_data/synthesizer.rs"
+            ),
+            data,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn synthesizer_test_data() {
+        // the committed input is the fixture (SmallRng streams are not portable)
+        let mut synthesizer = Synthesizer::new(SYNTHESIZER_NUM_CHANNELS);
+        let output = EXPECT_SYNTHESIZER_INPUT
+            .chunks(SYNTHESIZER_NUM_CHANNELS)
+            .flat_map(|block| synthesizer.synthesize(block).to_vec())
+            .collect::<Vec<_>>();
+
+        assert_eq!(output.len(), EXPECT_SYNTHESIZER_OUTPUT.len());
+        for (i, (a, b)) in output.iter().zip(&EXPECT_SYNTHESIZER_OUTPUT).enumerate() {
+            assert!((a - b).norm() < 1e-5, "{}: {} != {}", i, a, b);
+        }
+    }
+
+    #[test]
+    fn channelizer_reset() {
+        let num_channels = 8;
+
+        let chunk = (0..num_channels / 2)
+            .map(|i| Complex::new(i as f32 * 0.1, -0.2))
+            .collect::<Vec<_>>();
+
+        let mut fresh = Channelizer::new(num_channels);
+        let expected = fresh.channelize(&chunk).to_vec();
+
+        let mut channelizer = Channelizer::new(num_channels);
+        for _ in 0..10 {
+            channelizer.channelize(&[Complex::new(0.5, 0.5); 4]);
+        }
+        assert_ne!(channelizer.channelize(&chunk), &expected[..]);
+
+        channelizer.reset();
+        assert_eq!(channelizer.channelize(&chunk), &expected[..]);
+    }
+
+    #[test]
+    fn channelize_input_type() {
+        let num_channels = 16;
+        let samples = num_channels * 256;
+
+        // tone in the channel at +3 MHz (16 MHz sampling)
+        let freq = 3.0 / num_channels as f32;
+        let tone = (0..samples)
+            .map(|i| Complex::from_polar(0.9, 2.0 * core::f32::consts::PI * freq * i as f32))
+            .collect::<Vec<_>>();
+
+        // CS8 from the HackRF as converted by SoapySDR (x / 128, in [-1, 1))
+        let to_cs8 = |x: f32| (x * 128.).round().clamp(-128., 127.) as i8;
+        let cs8 = tone
+            .iter()
+            .map(|s| Complex::new(to_cs8(s.re), to_cs8(s.im)))
+            .collect::<Vec<Complex<i8>>>();
+        let converted = cs8
+            .iter()
+            .map(|s| Complex::new(s.re as f32 / 128.0, s.im as f32 / 128.0))
+            .collect::<Vec<Complex<f32>>>();
+
+        let channelize = |input: &[Complex<f32>]| {
+            let mut channelizer = Channelizer::new(num_channels);
+            input
+                .chunks(num_channels / 2)
+                .map(|chunk| channelizer.channelize(chunk).to_vec())
+                .collect::<Vec<_>>()
+        };
+        let expected = channelize(&tone);
+        let output = channelize(&converted);
+
+        // skip the filter delay
+        let settled = 4 * SYMBOL_DELAY as usize;
+        for (out, exp) in output[settled..].iter().zip(&expected[settled..]) {
+            // the level of the CF32 input in its channel, half of it in the overlapping neighbors
+            assert!((out[3].norm() - 0.9).abs() < 0.01);
+            assert!((out[3] - exp[3]).norm() < 0.004);
+            assert!((out[2].norm() - 0.45).abs() < 0.01);
+            assert!((out[4].norm() - 0.45).abs() < 0.01);
+            for (i, o) in out.iter().enumerate().filter(|(i, _)| !(2..=4).contains(i)) {
+                assert!(o.norm() < 0.01, "channel {}: {}", i, o.norm());
+            }
+        }
+    }
+
+    #[test]
+    fn downconvert_matches_channelizer() {
+        let num_channels = 16;
+        let samples = num_channels * 512;
+
+        // tone 0.2 MHz above the channel at +3 MHz (16 MHz sampling)
+        let freq = (3.0 + 0.2) / num_channels as f32;
+        let data = (0..samples)
+            .map(|i| Complex::from_polar(0.5, 2.0 * core::f32::consts::PI * freq * i as f32))
+            .collect::<Vec<_>>();
+
+        let mut channelizer = Channelizer::new(num_channels);
+        let channelized = data
+            .chunks(num_channels / 2)
+            .map(|chunk| channelizer.channelize(chunk)[3])
+            .collect::<Vec<_>>();
+
+        let mut downconverter = Downconverter::new(3.0 / num_channels as f32, num_channels / 2);
+        let downconverted = data
+            .chunks(1024)
+            .flat_map(|chunk| downconverter.downconvert(chunk).to_vec())
+            .collect::<Vec<_>>();
+
+        assert_eq!(downconverted.len(), channelized.len());
+
+        // skip the filter delay
+        let settled = 4 * SYMBOL_DELAY as usize;
+        for (a, b) in channelized[settled..].iter().zip(&downconverted[settled..]) {
+            assert!((a.norm() - b.norm()).abs() < 0.01);
+        }
+
+        // same level and the tone at +0.2 MHz (output at 2 MHz)
+        for w in downconverted[settled..].windows(2) {
+            assert!((w[0].norm() - 0.5).abs() < 0.01);
+
+            let rotation = (w[1] * w[0].conj()).arg();
+            assert!((rotation - 2.0 * core::f32::consts::PI * 0.1).abs() < 0.01);
+        }
+    }
 }
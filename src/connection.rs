@@ -0,0 +1,146 @@
+use crate::bluetooth::{ConnectRequest, LLData};
+use crate::stream::BluetoothChannel;
+
+/// Number of data channels
+const NUM_DATA_CHANNELS: u8 = 37;
+
+/// Follows the data channel of a connection from its CONNECT_IND
+/// (Channel Selection Algorithm #1)
+#[derive(Debug, Clone)]
+pub struct ConnectionTracker {
+    pub access_address: u32,
+    pub crc_init: u32,
+
+    hop: u8,
+    // used data channels in ascending order
+    used_channels: Vec<u8>,
+
+    event_counter: u16,
+    unmapped_channel: u8,
+    channel: u8,
+}
+
+impl ConnectionTracker {
+    pub fn new(ll_data: &LLData) -> anyhow::Result<Self> {
+        if !(5..=16).contains(&ll_data.hop) {
+            anyhow::bail!("invalid hop increment: {}", ll_data.hop);
+        }
+
+        let used_channels = (0..NUM_DATA_CHANNELS)
+            .filter(|ch| ll_data.channel_map[*ch as usize / 8] & (1 << (ch % 8)) != 0)
+            .collect::<Vec<_>>();
+
+        if used_channels.is_empty() {
+            anyhow::bail!("channel map has no used channel");
+        }
+
+        let mut ret = Self {
+            access_address: ll_data.access_address,
+            crc_init: ll_data.crc_init,
+            hop: ll_data.hop,
+            used_channels,
+            event_counter: 0,
+            unmapped_channel: 0,
+            channel: 0,
+        };
+        ret.hop_once();
+
+        Ok(ret)
+    }
+
+    pub fn from_connect_request(req: &ConnectRequest) -> anyhow::Result<Self> {
+        Self::new(&req.ll_data)
+    }
+
+    fn hop_once(&mut self) {
+        self.unmapped_channel = (self.unmapped_channel + self.hop) % NUM_DATA_CHANNELS;
+
+        self.channel = if self.used_channels.contains(&self.unmapped_channel) {
+            self.unmapped_channel
+        } else {
+            let remapping_index = self.unmapped_channel as usize % self.used_channels.len();
+            self.used_channels[remapping_index]
+        };
+    }
+
+    /// Data channel index of the current connection event
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// RF channel of the current connection event
+    pub fn rf_channel(&self) -> BluetoothChannel {
+        BluetoothChannel::from_data_channel(self.channel).expect("data channel is out of range")
+    }
+
+    pub fn event_counter(&self) -> u16 {
+        self.event_counter
+    }
+
+    /// Move to the next connection event and return its data channel index
+    pub fn advance(&mut self) -> u8 {
+        self.event_counter = self.event_counter.wrapping_add(1);
+        self.hop_once();
+
+        self.channel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ll_data(channel_map: [u8; 5], hop: u8) -> LLData {
+        LLData {
+            access_address: 0x50654c31,
+            crc_init: 0x123456,
+            win_size: 2,
+            win_offset: 0,
+            interval: 24,
+            latency: 0,
+            timeout: 72,
+            channel_map,
+            hop,
+            sca: 0,
+        }
+    }
+
+    fn sequence(tracker: &mut ConnectionTracker, len: usize) -> Vec<u8> {
+        let mut ret = vec![tracker.channel()];
+        ret.extend((1..len).map(|_| tracker.advance()));
+        ret
+    }
+
+    #[test]
+    fn all_channels_used() {
+        let mut tracker =
+            ConnectionTracker::new(&ll_data([0xff, 0xff, 0xff, 0xff, 0x1f], 7)).unwrap();
+
+        // (n * hop) mod 37
+        assert_eq!(
+            sequence(&mut tracker, 8),
+            vec![7, 14, 21, 28, 35, 5, 12, 19]
+        );
+        assert_eq!(tracker.event_counter(), 7);
+        assert_eq!(tracker.rf_channel().to_freq(), 2444);
+    }
+
+    #[test]
+    fn remapped_channels() {
+        // only data channels 0..=9 are used
+        let mut tracker =
+            ConnectionTracker::new(&ll_data([0xff, 0x03, 0x00, 0x00, 0x00], 5)).unwrap();
+
+        // unmapped: 5, 10, 15, 20, 25, 30, 35, 3, 8, 13
+        assert_eq!(
+            sequence(&mut tracker, 10),
+            vec![5, 0, 5, 0, 5, 0, 5, 3, 8, 3]
+        );
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(ConnectionTracker::new(&ll_data([0xff; 5], 4)).is_err());
+        assert!(ConnectionTracker::new(&ll_data([0x00; 5], 7)).is_err());
+    }
+}
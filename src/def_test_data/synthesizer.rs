@@ -0,0 +1,781 @@
+// generated by `cargo test --features gen_test_data generate_synthesizer_test_data`
+
+use num_complex::Complex;
+
+#[allow(dead_code)]
+const SYNTHESIZER_SEED: u64 = 0;
+const SYNTHESIZER_NUM_CHANNELS: usize = 8;
+
+const EXPECT_SYNTHESIZER_INPUT: [Complex<f32>; 128] = [
+    Complex {
+        re: -0.10535002,
+        im: -0.1217196,
+    },
+    Complex {
+        re: 0.9597604,
+        im: -0.07566571,
+    },
+    Complex {
+        re: 0.794158,
+        im: 0.88589954,
+    },
+    Complex {
+        re: 0.1762948,
+        im: -0.08725619,
+    },
+    Complex {
+        re: -0.20971179,
+        im: 0.63770175,
+    },
+    Complex {
+        re: -0.5181854,
+        im: 0.9527638,
+    },
+    Complex {
+        re: 0.2890334,
+        im: -0.8907819,
+    },
+    Complex {
+        re: 0.8431442,
+        im: -0.54978514,
+    },
+    Complex {
+        re: -0.5358088,
+        im: -0.4060514,
+    },
+    Complex {
+        re: 0.57567644,
+        im: 0.44858408,
+    },
+    Complex {
+        re: 0.16428661,
+        im: -0.62716794,
+    },
+    Complex {
+        re: -0.49214816,
+        im: 0.7291753,
+    },
+    Complex {
+        re: -0.21932697,
+        im: 0.8011129,
+    },
+    Complex {
+        re: 0.9424355,
+        im: -0.92946196,
+    },
+    Complex {
+        re: 0.6354873,
+        im: -0.12899733,
+    },
+    Complex {
+        re: 0.6971493,
+        im: -0.9715042,
+    },
+    Complex {
+        re: -0.3079965,
+        im: 0.864907,
+    },
+    Complex {
+        re: -0.41003466,
+        im: -0.27711892,
+    },
+    Complex {
+        re: -0.4313178,
+        im: 0.37430072,
+    },
+    Complex {
+        re: -0.18019986,
+        im: -0.9571159,
+    },
+    Complex {
+        re: 0.34133816,
+        im: 0.5718429,
+    },
+    Complex {
+        re: -0.31206036,
+        im: -0.7239728,
+    },
+    Complex {
+        re: -0.098911285,
+        im: 0.38463497,
+    },
+    Complex {
+        re: -0.28640008,
+        im: -0.34267616,
+    },
+    Complex {
+        re: -0.15025854,
+        im: 0.634398,
+    },
+    Complex {
+        re: 0.25811028,
+        im: -0.7969806,
+    },
+    Complex {
+        re: -0.813612,
+        im: 0.85825706,
+    },
+    Complex {
+        re: -0.62096095,
+        im: -0.7105911,
+    },
+    Complex {
+        re: 0.49548244,
+        im: 0.48366976,
+    },
+    Complex {
+        re: -0.6380842,
+        im: -0.7151439,
+    },
+    Complex {
+        re: -0.6472702,
+        im: 0.9211955,
+    },
+    Complex {
+        re: 0.010998011,
+        im: 0.2172401,
+    },
+    Complex {
+        re: 0.2409656,
+        im: -0.020775557,
+    },
+    Complex {
+        re: -0.9253745,
+        im: -0.77140665,
+    },
+    Complex {
+        re: -0.20805883,
+        im: 0.29369378,
+    },
+    Complex {
+        re: -0.890363,
+        im: 0.7055874,
+    },
+    Complex {
+        re: 0.64987946,
+        im: -0.19788241,
+    },
+    Complex {
+        re: -0.13624096,
+        im: 0.71255517,
+    },
+    Complex {
+        re: 0.566447,
+        im: 0.8925166,
+    },
+    Complex {
+        re: 0.901001,
+        im: 0.22645187,
+    },
+    Complex {
+        re: 0.9978218,
+        im: 0.5104706,
+    },
+    Complex {
+        re: 0.071501255,
+        im: -0.17925048,
+    },
+    Complex {
+        re: -0.67980194,
+        im: 0.009615898,
+    },
+    Complex {
+        re: -0.49967456,
+        im: 0.96229696,
+    },
+    Complex {
+        re: -0.51206636,
+        im: 0.47942543,
+    },
+    Complex {
+        re: 0.73518467,
+        im: 0.5380182,
+    },
+    Complex {
+        re: -0.25993395,
+        im: -0.30963063,
+    },
+    Complex {
+        re: 0.65010047,
+        im: -0.29705262,
+    },
+    Complex {
+        re: -0.46727085,
+        im: 0.35564423,
+    },
+    Complex {
+        re: 0.80336046,
+        im: 0.5713496,
+    },
+    Complex {
+        re: 0.38088322,
+        im: 0.45771766,
+    },
+    Complex {
+        re: -0.22969818,
+        im: 0.1985128,
+    },
+    Complex {
+        re: -0.8127494,
+        im: -0.08735752,
+    },
+    Complex {
+        re: -0.71565366,
+        im: -0.55913234,
+    },
+    Complex {
+        re: 0.09161973,
+        im: -0.38503432,
+    },
+    Complex {
+        re: -0.7349639,
+        im: 0.969924,
+    },
+    Complex {
+        re: 0.5610852,
+        im: 0.7464597,
+    },
+    Complex {
+        re: 0.037427187,
+        im: -0.6875329,
+    },
+    Complex {
+        re: -0.572572,
+        im: 0.25742102,
+    },
+    Complex {
+        re: 0.8852012,
+        im: -0.011838675,
+    },
+    Complex {
+        re: 0.8932986,
+        im: 0.017549038,
+    },
+    Complex {
+        re: -0.823704,
+        im: 0.3078928,
+    },
+    Complex {
+        re: -0.2009356,
+        im: 0.44057798,
+    },
+    Complex {
+        re: 0.8342662,
+        im: 0.84824586,
+    },
+    Complex {
+        re: -0.58207226,
+        im: -0.92802787,
+    },
+    Complex {
+        re: -0.64292216,
+        im: -0.81081104,
+    },
+    Complex {
+        re: 0.7543111,
+        im: 0.4273355,
+    },
+    Complex {
+        re: -0.8775487,
+        im: 0.3877535,
+    },
+    Complex {
+        re: 0.7488065,
+        im: -0.2588508,
+    },
+    Complex {
+        re: 0.54733825,
+        im: -0.016036034,
+    },
+    Complex {
+        re: 0.93243337,
+        im: 0.8171904,
+    },
+    Complex {
+        re: 0.59423184,
+        im: -0.75426626,
+    },
+    Complex {
+        re: -0.46734166,
+        im: 0.12575364,
+    },
+    Complex {
+        re: 0.929106,
+        im: 0.068208694,
+    },
+    Complex {
+        re: 0.8649001,
+        im: -0.45990777,
+    },
+    Complex {
+        re: -0.9465294,
+        im: -0.23001313,
+    },
+    Complex {
+        re: 0.8030355,
+        im: 0.3559289,
+    },
+    Complex {
+        re: 0.58341455,
+        im: -0.7581339,
+    },
+    Complex {
+        re: 0.11772919,
+        im: -0.025768042,
+    },
+    Complex {
+        re: 0.028824091,
+        im: 0.784328,
+    },
+    Complex {
+        re: 0.47613335,
+        im: -0.06581259,
+    },
+    Complex {
+        re: 0.29826188,
+        im: 0.4638629,
+    },
+    Complex {
+        re: -0.46450925,
+        im: 0.42287636,
+    },
+    Complex {
+        re: -0.42599797,
+        im: -0.6697602,
+    },
+    Complex {
+        re: -0.81165934,
+        im: -0.5746639,
+    },
+    Complex {
+        re: -0.29032803,
+        im: -0.507746,
+    },
+    Complex {
+        re: 0.4457221,
+        im: 0.22499657,
+    },
+    Complex {
+        re: 0.77614546,
+        im: 0.9860995,
+    },
+    Complex {
+        re: 0.15541697,
+        im: -0.11283827,
+    },
+    Complex {
+        re: 0.60841346,
+        im: -0.70612884,
+    },
+    Complex {
+        re: 0.93594885,
+        im: -0.22480416,
+    },
+    Complex {
+        re: -0.32075095,
+        im: 0.3650446,
+    },
+    Complex {
+        re: 0.49426317,
+        im: 0.39549398,
+    },
+    Complex {
+        re: 0.6836796,
+        im: 0.7379019,
+    },
+    Complex {
+        re: -0.46814895,
+        im: -0.6820786,
+    },
+    Complex {
+        re: -0.41384315,
+        im: 0.53702545,
+    },
+    Complex {
+        re: 0.35911298,
+        im: 0.35175014,
+    },
+    Complex {
+        re: -0.8633592,
+        im: 0.9873228,
+    },
+    Complex {
+        re: 0.31791425,
+        im: -0.46841764,
+    },
+    Complex {
+        re: -0.019665956,
+        im: 0.45238638,
+    },
+    Complex {
+        re: -0.22848487,
+        im: 0.31515074,
+    },
+    Complex {
+        re: 0.030344963,
+        im: -0.55374265,
+    },
+    Complex {
+        re: -0.7411649,
+        im: -0.031815052,
+    },
+    Complex {
+        re: 0.21488857,
+        im: 0.06254578,
+    },
+    Complex {
+        re: -0.5910206,
+        im: 0.73836136,
+    },
+    Complex {
+        re: 0.38010693,
+        im: -0.22739697,
+    },
+    Complex {
+        re: -0.5037744,
+        im: 0.6560097,
+    },
+    Complex {
+        re: 0.8254094,
+        im: 0.8554902,
+    },
+    Complex {
+        re: 0.94765186,
+        im: -0.79264665,
+    },
+    Complex {
+        re: -0.70032644,
+        im: -0.023725271,
+    },
+    Complex {
+        re: -0.272568,
+        im: 0.6700647,
+    },
+    Complex {
+        re: -0.8709016,
+        im: 0.81677055,
+    },
+    Complex {
+        re: 0.3387518,
+        im: 0.5932772,
+    },
+    Complex {
+        re: -0.43362546,
+        im: -0.99813604,
+    },
+    Complex {
+        re: -0.2162013,
+        im: 0.6788995,
+    },
+    Complex {
+        re: 0.5267868,
+        im: -0.9926491,
+    },
+    Complex {
+        re: 0.15072227,
+        im: -0.31351614,
+    },
+    Complex {
+        re: 0.21180868,
+        im: 0.102206945,
+    },
+    Complex {
+        re: -0.28019428,
+        im: 0.13346577,
+    },
+    Complex {
+        re: -0.27983403,
+        im: 0.48109555,
+    },
+    Complex {
+        re: -0.4009061,
+        im: 0.2672224,
+    },
+    Complex {
+        re: -0.14604211,
+        im: 0.7559147,
+    },
+    Complex {
+        re: -0.75395775,
+        im: 0.35474277,
+    },
+    Complex {
+        re: 0.03263688,
+        im: 0.18193793,
+    },
+    Complex {
+        re: 0.7252755,
+        im: 0.52066875,
+    },
+    Complex {
+        re: -0.26309395,
+        im: -0.42242908,
+    },
+    Complex {
+        re: -0.9798713,
+        im: -0.5075712,
+    },
+    Complex {
+        re: -0.42955232,
+        im: -0.2897756,
+    },
+];
+
+const EXPECT_SYNTHESIZER_OUTPUT: [Complex<f32>; 64] = [
+    Complex {
+        re: 6.324436e-10,
+        im: 2.131151e-10,
+    },
+    Complex {
+        re: -0.00012184806,
+        im: 0.00036640995,
+    },
+    Complex {
+        re: 0.0039713,
+        im: 7.7723686e-5,
+    },
+    Complex {
+        re: -0.0019137253,
+        im: -0.0009091936,
+    },
+    Complex {
+        re: 0.002540791,
+        im: -0.0009939086,
+    },
+    Complex {
+        re: 0.01511565,
+        im: -0.00083511294,
+    },
+    Complex {
+        re: 0.0019365742,
+        im: -0.0046047773,
+    },
+    Complex {
+        re: -0.013871156,
+        im: 0.008348787,
+    },
+    Complex {
+        re: -0.006482304,
+        im: 0.003976142,
+    },
+    Complex {
+        re: 0.007758151,
+        im: 0.009668185,
+    },
+    Complex {
+        re: -0.02466812,
+        im: -0.011987896,
+    },
+    Complex {
+        re: 0.024159614,
+        im: 0.011035539,
+    },
+    Complex {
+        re: -0.017103685,
+        im: -0.010790549,
+    },
+    Complex {
+        re: -0.07060332,
+        im: 0.007554373,
+    },
+    Complex {
+        re: -0.020979634,
+        im: 0.018288033,
+    },
+    Complex {
+        re: 0.07479463,
+        im: -0.04189876,
+    },
+    Complex {
+        re: 0.044885468,
+        im: -0.026067084,
+    },
+    Complex {
+        re: -0.04621881,
+        im: -0.054324627,
+    },
+    Complex {
+        re: 0.07298189,
+        im: 0.06306623,
+    },
+    Complex {
+        re: -0.09243888,
+        im: -0.04534487,
+    },
+    Complex {
+        re: 0.05677452,
+        im: 0.074368276,
+    },
+    Complex {
+        re: 0.22912434,
+        im: -0.046278305,
+    },
+    Complex {
+        re: 0.087501146,
+        im: -0.05956983,
+    },
+    Complex {
+        re: -0.2664675,
+        im: 0.14437783,
+    },
+    Complex {
+        re: -0.1753736,
+        im: 0.091756105,
+    },
+    Complex {
+        re: 0.16792367,
+        im: 0.18965104,
+    },
+    Complex {
+        re: -0.2498877,
+        im: -0.24066785,
+    },
+    Complex {
+        re: 0.31506622,
+        im: 0.1628701,
+    },
+    Complex {
+        re: -0.21437934,
+        im: -0.24084553,
+    },
+    Complex {
+        re: -1.1934884,
+        im: 0.22200212,
+    },
+    Complex {
+        re: -0.27112335,
+        im: 0.4366914,
+    },
+    Complex {
+        re: 1.9720725,
+        im: -1.4560233,
+    },
+    Complex {
+        re: 1.8139713,
+        im: 0.007799357,
+    },
+    Complex {
+        re: -0.5687834,
+        im: -1.5145504,
+    },
+    Complex {
+        re: -2.087999,
+        im: 1.3234642,
+    },
+    Complex {
+        re: -1.4026467,
+        im: -0.6862768,
+    },
+    Complex {
+        re: -1.014613,
+        im: 1.6099608,
+    },
+    Complex {
+        re: -0.26277143,
+        im: -0.77890503,
+    },
+    Complex {
+        re: -0.34114057,
+        im: 0.39332083,
+    },
+    Complex {
+        re: 0.3152993,
+        im: 0.6253356,
+    },
+    Complex {
+        re: -0.9561065,
+        im: -0.19587746,
+    },
+    Complex {
+        re: -0.13932829,
+        im: -0.049910203,
+    },
+    Complex {
+        re: 1.5687947,
+        im: -0.27213168,
+    },
+    Complex {
+        re: -1.264472,
+        im: -0.12569046,
+    },
+    Complex {
+        re: 0.9472764,
+        im: 3.924704,
+    },
+    Complex {
+        re: -1.4695796,
+        im: 0.80558383,
+    },
+    Complex {
+        re: 0.64571977,
+        im: -0.4653666,
+    },
+    Complex {
+        re: -0.3773775,
+        im: 1.1384866,
+    },
+    Complex {
+        re: -0.60412705,
+        im: 1.6523614,
+    },
+    Complex {
+        re: 1.5035454,
+        im: -2.4706988,
+    },
+    Complex {
+        re: 1.5361696,
+        im: 0.04568255,
+    },
+    Complex {
+        re: 0.33727035,
+        im: 0.5034777,
+    },
+    Complex {
+        re: -0.22560042,
+        im: -0.8123044,
+    },
+    Complex {
+        re: 0.0072447658,
+        im: 0.7561133,
+    },
+    Complex {
+        re: -0.6574105,
+        im: 0.44405186,
+    },
+    Complex {
+        re: 1.9145199,
+        im: 0.19079825,
+    },
+    Complex {
+        re: -0.10555136,
+        im: 1.7906791,
+    },
+    Complex {
+        re: 0.60894036,
+        im: 1.0735581,
+    },
+    Complex {
+        re: 1.1210394,
+        im: -0.4453552,
+    },
+    Complex {
+        re: -0.079897374,
+        im: 0.85111535,
+    },
+    Complex {
+        re: 0.48611695,
+        im: 0.70890266,
+    },
+    Complex {
+        re: -1.5311038,
+        im: 0.21119994,
+    },
+    Complex {
+        re: -1.0475789,
+        im: -0.24868637,
+    },
+    Complex {
+        re: -1.4214957,
+        im: 0.29637194,
+    },
+];
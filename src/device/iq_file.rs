@@ -0,0 +1,432 @@
+use std::io::{BufRead, Read, Write};
+
+use anyhow::Context;
+use num_complex::Complex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IqFormat {
+    /// interleaved little-endian f32 (GNU Radio `gr_complex`)
+    Cf32,
+    /// same as the File source: a line with the number of samples,
+    /// then a line of interleaved CS8 values per block
+    Text,
+    /// `IqHeader`, then per block a little-endian u32 number of samples
+    /// followed by the interleaved CS8 values
+    Cs8Bin,
+    /// same as `Cs8Bin` with interleaved little-endian f32
+    Cf32Bin,
+}
+
+impl IqFormat {
+    fn is_binary(self) -> bool {
+        matches!(self, IqFormat::Cs8Bin | IqFormat::Cf32Bin)
+    }
+}
+
+/// First bytes of a binary IQ file
+pub const IQ_MAGIC: [u8; 4] = *b"RFIQ";
+
+const IQ_VERSION: u8 = 1;
+
+// magic, version, sample type, 2 reserved bytes, sample rate, center freq
+const IQ_HEADER_LEN: usize = 24;
+
+/// Header of a binary IQ file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IqHeader {
+    /// Hz
+    pub sample_rate: f64,
+    /// Hz
+    pub center_freq: f64,
+}
+
+impl IqHeader {
+    fn encode(&self, format: IqFormat) -> [u8; IQ_HEADER_LEN] {
+        let mut header = [0; IQ_HEADER_LEN];
+        header[..4].copy_from_slice(&IQ_MAGIC);
+        header[4] = IQ_VERSION;
+        header[5] = (format == IqFormat::Cf32Bin) as u8;
+        header[8..16].copy_from_slice(&self.sample_rate.to_le_bytes());
+        header[16..24].copy_from_slice(&self.center_freq.to_le_bytes());
+
+        header
+    }
+
+    fn decode(header: &[u8; IQ_HEADER_LEN]) -> anyhow::Result<(Self, IqFormat)> {
+        anyhow::ensure!(header[..4] == IQ_MAGIC, "not a binary IQ file");
+        anyhow::ensure!(
+            header[4] == IQ_VERSION,
+            "unsupported IQ file version: {}",
+            header[4]
+        );
+
+        let format = match header[5] {
+            0 => IqFormat::Cs8Bin,
+            1 => IqFormat::Cf32Bin,
+            t => anyhow::bail!("invalid IQ sample type: {}", t),
+        };
+        let f64_at = |i: usize| f64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+
+        Ok((
+            Self {
+                sample_rate: f64_at(8),
+                center_freq: f64_at(16),
+            },
+            format,
+        ))
+    }
+}
+
+// CF32 in [-1, 1) <-> CS8
+fn to_cs8(x: f32) -> i8 {
+    (x * 128.).round().clamp(-128., 127.) as i8
+}
+
+fn from_cs8(x: i8) -> f32 {
+    x as f32 / 128.
+}
+
+impl std::str::FromStr for IqFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "cf32" => Ok(IqFormat::Cf32),
+            "text" => Ok(IqFormat::Text),
+            "cs8bin" => Ok(IqFormat::Cs8Bin),
+            "cf32bin" => Ok(IqFormat::Cf32Bin),
+            _ => anyhow::bail!("invalid IQ format: {}", s),
+        }
+    }
+}
+
+/// Output of a FileSink device
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    pub path: String,
+    pub format: IqFormat,
+    /// recorded in the header of the binary formats
+    pub header: IqHeader,
+}
+
+pub struct IqFileWriter {
+    out: std::io::BufWriter<std::fs::File>,
+    format: IqFormat,
+}
+
+impl IqFileWriter {
+    pub fn create(sink: &FileSink) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(&sink.path)
+            .with_context(|| format!("failed to create {}", sink.path))?;
+
+        let mut out = std::io::BufWriter::new(file);
+        if sink.format.is_binary() {
+            out.write_all(&sink.header.encode(sink.format))?;
+        }
+
+        Ok(Self {
+            out,
+            format: sink.format,
+        })
+    }
+
+    pub fn write(&mut self, samples: &[Complex<f32>]) -> anyhow::Result<()> {
+        match self.format {
+            IqFormat::Cf32 => {
+                for s in samples {
+                    self.out.write_all(&s.re.to_le_bytes())?;
+                    self.out.write_all(&s.im.to_le_bytes())?;
+                }
+            }
+            IqFormat::Text => {
+                let values = samples
+                    .iter()
+                    .flat_map(|s| [to_cs8(s.re), to_cs8(s.im)])
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>();
+
+                writeln!(self.out, "{}", samples.len())?;
+                writeln!(self.out, "{}", values.join(" "))?;
+            }
+            IqFormat::Cs8Bin => {
+                self.out.write_all(&(samples.len() as u32).to_le_bytes())?;
+                for s in samples {
+                    self.out
+                        .write_all(&[to_cs8(s.re) as u8, to_cs8(s.im) as u8])?;
+                }
+            }
+            IqFormat::Cf32Bin => {
+                self.out.write_all(&(samples.len() as u32).to_le_bytes())?;
+                for s in samples {
+                    self.out.write_all(&s.re.to_le_bytes())?;
+                    self.out.write_all(&s.im.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the blocks of a File source input (`IqFormat::Text`) or of a binary IQ file
+pub struct IqFileReader {
+    input: std::io::BufReader<std::fs::File>,
+    format: IqFormat,
+    header: Option<IqHeader>,
+}
+
+impl IqFileReader {
+    /// The format is detected from `IQ_MAGIC`
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("failed to open {}", path))?;
+        let mut input = std::io::BufReader::new(file);
+
+        if !input.fill_buf()?.starts_with(&IQ_MAGIC) {
+            return Ok(Self {
+                input,
+                format: IqFormat::Text,
+                header: None,
+            });
+        }
+
+        let mut header = [0; IQ_HEADER_LEN];
+        input
+            .read_exact(&mut header)
+            .with_context(|| format!("{}: truncated header", path))?;
+        let (header, format) = IqHeader::decode(&header).with_context(|| path.to_string())?;
+
+        Ok(Self {
+            input,
+            format,
+            header: Some(header),
+        })
+    }
+
+    pub fn format(&self) -> IqFormat {
+        self.format
+    }
+
+    /// None for the text format
+    pub fn header(&self) -> Option<IqHeader> {
+        self.header
+    }
+
+    /// The next block, None at the end of the file
+    pub fn read_block(&mut self) -> anyhow::Result<Option<Vec<Complex<f32>>>> {
+        match self.format {
+            IqFormat::Text => self.read_text_block(),
+            IqFormat::Cs8Bin | IqFormat::Cf32Bin => self.read_binary_block(),
+            IqFormat::Cf32 => anyhow::bail!("cf32 files have no blocks"),
+        }
+    }
+
+    fn read_text_block(&mut self) -> anyhow::Result<Option<Vec<Complex<f32>>>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let len = line
+            .trim()
+            .parse::<usize>()
+            .context("invalid block length")?;
+
+        line.clear();
+        self.input.read_line(&mut line)?;
+        let values = line
+            .split_whitespace()
+            .map(|v| v.parse::<i8>().map(from_cs8))
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid sample")?;
+        anyhow::ensure!(
+            values.len() == 2 * len,
+            "block of {} samples has {} values",
+            len,
+            values.len()
+        );
+
+        Ok(Some(
+            values
+                .chunks_exact(2)
+                .map(|iq| Complex::new(iq[0], iq[1]))
+                .collect(),
+        ))
+    }
+
+    fn read_binary_block(&mut self) -> anyhow::Result<Option<Vec<Complex<f32>>>> {
+        let mut len = [0; 4];
+        match self.input.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_le_bytes(len) as usize;
+
+        let value_size = if self.format == IqFormat::Cf32Bin {
+            4
+        } else {
+            1
+        };
+        let mut data = vec![0; 2 * len * value_size];
+        self.input
+            .read_exact(&mut data)
+            .context("truncated block")?;
+
+        let values = match self.format {
+            IqFormat::Cf32Bin => data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect::<Vec<_>>(),
+            _ => data.iter().map(|&b| from_cs8(b as i8)).collect(),
+        };
+
+        Ok(Some(
+            values
+                .chunks_exact(2)
+                .map(|iq| Complex::new(iq[0], iq[1]))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_formats() {
+        let samples = [Complex::new(0.5, -0.25), Complex::new(-1.0, 0.999)];
+
+        let path = std::env::temp_dir().join(format!("rfraptor-iq-{}", std::process::id()));
+        let sink = |format| FileSink {
+            path: path.display().to_string(),
+            format,
+            header: IqHeader {
+                sample_rate: 16e6,
+                center_freq: 2427e6,
+            },
+        };
+
+        let mut writer = IqFileWriter::create(&sink(IqFormat::Cf32)).unwrap();
+        writer.write(&samples).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let data = std::fs::read(&path).unwrap();
+        let values = data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0.5, -0.25, -1.0, 0.999]);
+
+        let mut writer = IqFileWriter::create(&sink(IqFormat::Text)).unwrap();
+        writer.write(&samples).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(text, "2\n64 -32 -128 127\n");
+        assert!("raw".parse::<IqFormat>().is_err());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let blocks = [
+            vec![Complex::new(0.5, -0.25), Complex::new(-1.0, 0.999)],
+            vec![Complex::new(0.125, 0.0)],
+        ];
+        let header = IqHeader {
+            sample_rate: 16e6,
+            center_freq: 2427e6,
+        };
+
+        let read_back = |format| {
+            let path = std::env::temp_dir().join(format!(
+                "rfraptor-iq-{:?}-{}",
+                format,
+                std::process::id()
+            ));
+            let sink = FileSink {
+                path: path.display().to_string(),
+                format,
+                header,
+            };
+
+            let mut writer = IqFileWriter::create(&sink).unwrap();
+            for block in &blocks {
+                writer.write(block).unwrap();
+            }
+            writer.flush().unwrap();
+            drop(writer);
+
+            let mut reader = IqFileReader::open(&sink.path).unwrap();
+            assert_eq!(reader.format(), format);
+
+            let mut read = Vec::new();
+            while let Some(block) = reader.read_block().unwrap() {
+                read.push(block);
+            }
+            let size = std::fs::metadata(&path).unwrap().len();
+            std::fs::remove_file(&path).unwrap();
+
+            (reader.header(), read, size)
+        };
+
+        let (text_header, text, _) = read_back(IqFormat::Text);
+        let (cs8_header, cs8, cs8_size) = read_back(IqFormat::Cs8Bin);
+        let (cf32_header, cf32, _) = read_back(IqFormat::Cf32Bin);
+
+        assert_eq!(text_header, None);
+        assert_eq!(cs8_header, Some(header));
+        assert_eq!(cf32_header, Some(header));
+
+        // CS8 is quantized the same way as the text format
+        assert_eq!(cs8, text);
+        assert_eq!(cf32, blocks);
+        assert_eq!(cs8_size, 24 + (4 + 2 * 2) + (4 + 2));
+    }
+
+    #[test]
+    fn convert_sample_rx() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/test_sample_rx.txt");
+        let out = std::env::temp_dir().join(format!("rfraptor-iq-rx-{}", std::process::id()));
+
+        let mut text = IqFileReader::open(path).unwrap();
+        let sink = FileSink {
+            path: out.display().to_string(),
+            format: IqFormat::Cs8Bin,
+            header: IqHeader {
+                sample_rate: 16e6,
+                center_freq: 2427e6,
+            },
+        };
+        let mut writer = IqFileWriter::create(&sink).unwrap();
+
+        let mut blocks = Vec::new();
+        for _ in 0..4 {
+            let block = text.read_block().unwrap().unwrap();
+            writer.write(&block).unwrap();
+            blocks.push(block);
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut binary = IqFileReader::open(&sink.path).unwrap();
+        for block in &blocks {
+            assert_eq!(binary.read_block().unwrap().as_ref(), Some(block));
+        }
+        assert_eq!(binary.read_block().unwrap(), None);
+
+        // a CS8 value takes 1 byte instead of up to 4 characters and a space
+        let size = std::fs::metadata(&out).unwrap().len() as usize;
+        std::fs::remove_file(&out).unwrap();
+        let samples = blocks.iter().map(|b| b.len()).sum::<usize>();
+        assert_eq!(size, 24 + 4 * blocks.len() + 2 * samples);
+    }
+}
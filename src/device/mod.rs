@@ -1,3 +1,4 @@
+pub mod iq_file;
 pub mod sdr;
 
 use std::{path::Path, sync::Mutex};
@@ -5,12 +6,39 @@ use std::{path::Path, sync::Mutex};
 use anyhow::Context;
 use soapysdr::{Device as RawDevice, Direction};
 
-use sdr::SDRConfig;
+use sdr::{Backpressure, SDRConfig, DEFAULT_STREAM_BUFFERS};
 
 pub struct Device {
     pub raw: RawDevice,
     pub config: SDRConfig,
     pub running: std::sync::Arc<Mutex<bool>>,
+
+    // demodulator used by the rx threads (FskDemod by default)
+    pub demodulator: crate::fsk::DemodulatorFactory,
+
+    // receives the bursts which were too short or failed to demodulate
+    pub burst_dump: Option<std::sync::mpsc::Sender<crate::burst::RawBurst>>,
+
+    // tx writes the synthesized IQ here instead of `raw` (FileSink)
+    pub file_sink: Option<iq_file::FileSink>,
+
+    // rx reads this binary IQ file instead of `raw` (File)
+    pub file_source: Option<String>,
+
+    // receives the magnitude spectrum of each block read by the channelizer
+    pub spectrum: Option<std::sync::mpsc::SyncSender<Vec<f32>>>,
+
+    // receives the pipeline events of the rx threads
+    pub events: Option<std::sync::mpsc::SyncSender<crate::stream::PipelineEvent>>,
+
+    // receives every caught burst with its margins
+    pub burst_record: Option<(
+        crate::burst::RecordMargin,
+        std::sync::mpsc::Sender<(u32, crate::burst::Recording)>,
+    )>,
+
+    // connections followed by the rx threads (learned from the CONNECT_INDs)
+    pub connections: crate::bluetooth::Connections,
 }
 
 impl Device {
@@ -19,8 +47,92 @@ impl Device {
             raw,
             config,
             running: std::sync::Arc::new(Mutex::new(false)),
+            demodulator: std::sync::Arc::new(|config| {
+                Ok(Box::new(crate::fsk::FskDemod::new(
+                    config.sample_rate as _,
+                    config.num_channels,
+                )?))
+            }),
+            burst_dump: None,
+            file_sink: None,
+            file_source: None,
+            spectrum: None,
+            events: None,
+            burst_record: None,
+            connections: crate::bluetooth::Connections::default(),
         }
     }
+
+    /// Use a SoapySDR device opened by the caller (custom args, calibration, ...).
+    /// `config` is checked against the channelizer and applied to `raw`.
+    pub fn from_raw(raw: RawDevice, config: SDRConfig) -> anyhow::Result<Self> {
+        check_channel_mapping(&config)?;
+        config.set(&raw).context("failed to apply the config")?;
+
+        Ok(Self::new(raw, config))
+    }
+
+    /// Send the bursts which could not be demodulated to `sink` (set before starting rx)
+    pub fn dump_failed_bursts(&mut self, sink: std::sync::mpsc::Sender<crate::burst::RawBurst>) {
+        self.burst_dump = Some(sink);
+    }
+
+    /// Send the mean magnitude of every channelizer bin (in SDR index order) per block
+    /// to `sink` (set before starting rx). Rows are dropped while `sink` is full.
+    pub fn tap_spectrum(&mut self, sink: std::sync::mpsc::SyncSender<Vec<f32>>) {
+        self.spectrum = Some(sink);
+    }
+
+    /// Send the `PipelineEvent`s of the rx threads to `sink` (set before starting rx).
+    /// Events are dropped while `sink` is full.
+    pub fn tap_events(&mut self, sink: std::sync::mpsc::SyncSender<crate::stream::PipelineEvent>) {
+        self.events = Some(sink);
+    }
+
+    /// Send every caught burst with `margin` samples around it to `sink` as
+    /// (frequency [MHz], recording), set before starting rx. Only the channelized IQ
+    /// around the bursts is recorded, not the silence between them.
+    pub fn record_bursts(
+        &mut self,
+        margin: crate::burst::RecordMargin,
+        sink: std::sync::mpsc::Sender<(u32, crate::burst::Recording)>,
+    ) {
+        self.burst_record = Some((margin, sink));
+    }
+
+    /// Frequency, sample rate and gain currently reported by the device for each direction,
+    /// to detect drift from `config` (see `SDRReadback::drift`)
+    pub fn readback(&self) -> anyhow::Result<sdr::SDRReadback> {
+        Ok(sdr::SDRReadback {
+            timestamp: chrono::Utc::now(),
+            applied: self
+                .config
+                .read_back(&self.raw)
+                .context("failed to read back the settings")?,
+        })
+    }
+
+    pub fn set_demodulator(
+        &mut self,
+        f: impl Fn(&SDRConfig) -> anyhow::Result<Box<dyn crate::fsk::Demodulator>>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        self.demodulator = std::sync::Arc::new(f);
+    }
+}
+
+/// Several devices (e.g. HackRFs on different sub-bands) received as one stream,
+/// see `MultiDevice::start_rx_with_error`
+pub struct MultiDevice {
+    pub devices: Vec<Device>,
+}
+
+impl MultiDevice {
+    pub fn new(devices: Vec<Device>) -> Self {
+        Self { devices }
+    }
 }
 
 pub mod config {
@@ -37,19 +149,166 @@ pub mod config {
             // serial: ex) 0000000000000000f77c60dc259132c3
             // `hackrf_info` to get serial
             serial: String,
+
+            // rx_gain/tx_gain: dB (optional)
+            #[serde(default)]
+            rx_gain: Option<f64>,
+            #[serde(default)]
+            tx_gain: Option<f64>,
+
+            // worker_threads: number of catcher threads, default one per channel (optional)
+            #[serde(default)]
+            worker_threads: Option<usize>,
+            // worker_priority: priority of the catcher threads, 0..=99, needs permission
+            //                  (e.g. CAP_SYS_NICE), default left to the OS (optional)
+            #[serde(default)]
+            worker_priority: Option<u8>,
+            // stream_buffers: number of SoapySDR rx stream buffers, default 65535 (optional)
+            #[serde(default)]
+            stream_buffers: Option<usize>,
+            // aa_tolerance: bit errors tolerated in the advertising access address,
+            //               default 0 (optional)
+            #[serde(default)]
+            aa_tolerance: Option<u32>,
         },
         Virtual {
             // plugin: soapy-utils/soapy-virtual
             // direction: "Rx" | "Tx" | "RxTx",
             direction: String,
+
+            // freq: MHz, default 2427 (optional)
+            #[serde(default)]
+            freq_mhz: Option<usize>,
+
+            // sample rate: Hz, default num_channels MHz (optional)
+            #[serde(default)]
+            sample_rate_hz: Option<f64>,
+
+            // number of 1 MHz channels, default 16 (optional)
+            #[serde(default)]
+            num_channels: Option<usize>,
+
+            // worker_threads: number of catcher threads, default one per channel (optional)
+            #[serde(default)]
+            worker_threads: Option<usize>,
+            // worker_priority: priority of the catcher threads, 0..=99, needs permission
+            //                  (e.g. CAP_SYS_NICE), default left to the OS (optional)
+            #[serde(default)]
+            worker_priority: Option<u8>,
+            // stream_buffers: number of SoapySDR rx stream buffers, default 65535 (optional)
+            #[serde(default)]
+            stream_buffers: Option<usize>,
+            // aa_tolerance: bit errors tolerated in the advertising access address,
+            //               default 0 (optional)
+            #[serde(default)]
+            aa_tolerance: Option<u32>,
         },
         File {
             // plugin: soapy-utils/soapy-file
             // direction: "Rx"
             direction: String,
 
+            // path: file path, text or binary (cs8bin / cf32bin, written by FileSink)
+            //       the binary files are read without the plugin, their header gives
+            //       the default freq_mhz and sample_rate_hz
+            path: String,
+
+            // repeat: number of passes over the file, default 1 (optional)
+            #[serde(default)]
+            repeat: Option<usize>,
+
+            // freq: MHz, default 2427 (optional)
+            #[serde(default)]
+            freq_mhz: Option<usize>,
+
+            // sample rate: Hz, default num_channels MHz (optional)
+            #[serde(default)]
+            sample_rate_hz: Option<f64>,
+
+            // number of 1 MHz channels, default 16 (optional)
+            #[serde(default)]
+            num_channels: Option<usize>,
+
+            // worker_threads: number of catcher threads, default one per channel (optional)
+            #[serde(default)]
+            worker_threads: Option<usize>,
+            // worker_priority: priority of the catcher threads, 0..=99, needs permission
+            //                  (e.g. CAP_SYS_NICE), default left to the OS (optional)
+            #[serde(default)]
+            worker_priority: Option<u8>,
+            // stream_buffers: number of SoapySDR rx stream buffers, default 65535 (optional)
+            #[serde(default)]
+            stream_buffers: Option<usize>,
+            // aa_tolerance: bit errors tolerated in the advertising access address,
+            //               default 0 (optional)
+            #[serde(default)]
+            aa_tolerance: Option<u32>,
+            // detect_channel: try the whitening of every BLE channel, for captures
+            //                 with a wrong freq_mhz, default false (optional)
+            #[serde(default)]
+            detect_channel: Option<bool>,
+        },
+        FileSink {
+            // writes the synthesized IQ of start_tx (always Tx)
             // path: file path
             path: String,
+
+            // format: "cf32" (interleaved f32, for GNU Radio) | "text" (as read by File)
+            //       | "cs8bin" / "cf32bin" (binary, see iq_file::IqFormat), default "cf32" (optional)
+            #[serde(default)]
+            format: Option<String>,
+
+            // freq: MHz, default 2427 (optional)
+            #[serde(default)]
+            freq_mhz: Option<usize>,
+
+            // sample rate: Hz, default num_channels MHz (optional)
+            #[serde(default)]
+            sample_rate_hz: Option<f64>,
+
+            // number of 1 MHz channels, default 16 (optional)
+            #[serde(default)]
+            num_channels: Option<usize>,
+        },
+        Generic {
+            // any SoapySDR driver: ex) "rtlsdr", "bladerf", "lime"
+            driver: String,
+
+            // extra device args: ex) { serial: "00000001" }
+            #[serde(default)]
+            args: std::collections::HashMap<String, String>,
+
+            // direction: "Rx" | "Tx" | "RxTx",
+            direction: String,
+
+            // freq: MHz
+            freq_mhz: usize,
+
+            // sample rate: Hz, default num_channels MHz (optional)
+            #[serde(default)]
+            sample_rate_hz: Option<f64>,
+
+            // number of 1 MHz channels, default 16 (optional)
+            #[serde(default)]
+            num_channels: Option<usize>,
+
+            // gain: dB
+            gain: f64,
+
+            // worker_threads: number of catcher threads, default one per channel (optional)
+            #[serde(default)]
+            worker_threads: Option<usize>,
+            // worker_priority: priority of the catcher threads, 0..=99, needs permission
+            //                  (e.g. CAP_SYS_NICE), default left to the OS (optional)
+            #[serde(default)]
+            worker_priority: Option<u8>,
+            // stream_buffers: number of SoapySDR rx stream buffers, default 65535 (optional)
+            #[serde(default)]
+            stream_buffers: Option<usize>,
+            // aa_tolerance: bit errors tolerated in the advertising access address,
+            //               default 0 (optional)
+            #[serde(default)]
+            aa_tolerance: Option<u32>,
         },
     }
 
@@ -57,6 +316,90 @@ pub mod config {
     pub struct List {
         pub devices: Vec<Device>,
     }
+
+    // 2.4 GHz ISM band [MHz]
+    const ISM_BAND_MHZ: std::ops::RangeInclusive<usize> = 2400..=2483;
+
+    impl List {
+        /// Check the fields which would otherwise only fail while opening the device
+        pub fn validate(&self) -> anyhow::Result<()> {
+            for (i, dev) in self.devices.iter().enumerate() {
+                let (direction, freq_mhz, serial) = match dev {
+                    Device::HackRF {
+                        direction,
+                        freq_mhz,
+                        serial,
+                        ..
+                    } => (Some(direction), Some(*freq_mhz), Some(serial)),
+                    Device::Virtual {
+                        direction,
+                        freq_mhz,
+                        ..
+                    }
+                    | Device::File {
+                        direction,
+                        freq_mhz,
+                        ..
+                    } => (Some(direction), *freq_mhz, None),
+                    Device::FileSink { freq_mhz, .. } => (None, *freq_mhz, None),
+                    Device::Generic {
+                        direction,
+                        freq_mhz,
+                        ..
+                    } => (Some(direction), Some(*freq_mhz), None),
+                };
+
+                if let Some(direction) = direction {
+                    if super::direction_from_str(direction).is_err() {
+                        anyhow::bail!(
+                            "device[{}].direction must be one of Rx/Tx/RxTx, got '{}'",
+                            i,
+                            direction
+                        );
+                    }
+                }
+
+                if let Some(freq_mhz) = freq_mhz {
+                    if !ISM_BAND_MHZ.contains(&freq_mhz) {
+                        anyhow::bail!(
+                            "device[{}].freq_mhz must be in {}..={} MHz, got {}",
+                            i,
+                            ISM_BAND_MHZ.start(),
+                            ISM_BAND_MHZ.end(),
+                            freq_mhz
+                        );
+                    }
+                }
+
+                if let Some(serial) = serial {
+                    if serial.is_empty()
+                        || 32 < serial.len()
+                        || !serial.chars().all(|c| c.is_ascii_hexdigit())
+                    {
+                        anyhow::bail!(
+                            "device[{}].serial must be up to 32 hex digits (see `hackrf_info`), got '{}'",
+                            i,
+                            serial
+                        );
+                    }
+                }
+
+                let stream_buffers = match dev {
+                    Device::HackRF { stream_buffers, .. }
+                    | Device::Virtual { stream_buffers, .. }
+                    | Device::File { stream_buffers, .. }
+                    | Device::Generic { stream_buffers, .. } => *stream_buffers,
+                    Device::FileSink { .. } => None,
+                };
+
+                if stream_buffers == Some(0) {
+                    anyhow::bail!("device[{}].stream_buffers must be positive", i);
+                }
+            }
+
+            Ok(())
+        }
+    }
 }
 
 fn direction_from_str(s: &str) -> anyhow::Result<Vec<Direction>> {
@@ -71,6 +414,96 @@ fn direction_from_str(s: &str) -> anyhow::Result<Vec<Direction>> {
 const NUM_CHANNELS: usize = 16usize;
 // const NUM_CHANNELS: usize = 2usize;
 
+const DEFAULT_FREQ_MHZ: usize = 2427usize;
+
+// (freq_mhz, num_channels, sample_rate) with the defaults filled in
+fn channel_layout(
+    freq_mhz: Option<usize>,
+    sample_rate_hz: Option<f64>,
+    num_channels: Option<usize>,
+) -> anyhow::Result<(usize, usize, f64)> {
+    let freq_mhz = freq_mhz.unwrap_or(DEFAULT_FREQ_MHZ);
+    let num_channels = num_channels.unwrap_or(NUM_CHANNELS);
+
+    if num_channels == 0 || num_channels % 2 != 0 {
+        anyhow::bail!(
+            "num_channels must be a positive even number: {}",
+            num_channels
+        );
+    }
+
+    // the channelizer assumes 1 MHz per channel
+    let sample_rate = num_channels as f64 * 1.0e6;
+    if let Some(sample_rate_hz) = sample_rate_hz {
+        if sample_rate_hz != sample_rate {
+            anyhow::bail!(
+                "sample_rate_hz must be num_channels MHz ({}): {}",
+                sample_rate,
+                sample_rate_hz
+            );
+        }
+    }
+
+    Ok((freq_mhz, num_channels, sample_rate))
+}
+
+// the open_* helpers build a consistent SDRConfig, a config given to Device::from_raw may not be
+fn check_channel_mapping(config: &SDRConfig) -> anyhow::Result<()> {
+    channel_layout(
+        Some(config.freq_mhz),
+        Some(config.sample_rate),
+        Some(config.num_channels),
+    )?;
+
+    if config.center_freq != config.freq_mhz as f64 * 1.0e6 {
+        anyhow::bail!(
+            "center_freq must be freq_mhz MHz ({} MHz): {}",
+            config.freq_mhz,
+            config.center_freq
+        );
+    }
+
+    if crate::stream::ChannelPlan::from_config(config)
+        .ble_channels()
+        .is_empty()
+    {
+        anyhow::bail!(
+            "no BLE channel in {} MHz +/- {} MHz",
+            config.freq_mhz,
+            config.num_channels / 2
+        );
+    }
+
+    Ok(())
+}
+
+const HACKRF_RX_GAIN: f64 = 64.;
+const HACKRF_TX_GAIN: f64 = 32. + 14.;
+
+// chunks buffered per channel before wake_channelizer starts dropping
+const CHANNEL_DEPTH: usize = 1024usize;
+
+// the optional channel layout of a config::Device
+fn layout(
+    builder: sdr::SDRConfigBuilder,
+    freq_mhz: Option<usize>,
+    sample_rate_hz: Option<f64>,
+    num_channels: Option<usize>,
+) -> sdr::SDRConfigBuilder {
+    let builder = match freq_mhz {
+        Some(freq_mhz) => builder.freq_mhz(freq_mhz),
+        None => builder,
+    };
+    let builder = match sample_rate_hz {
+        Some(sample_rate) => builder.sample_rate(sample_rate),
+        None => builder,
+    };
+    match num_channels {
+        Some(num_channels) => builder.num_channels(num_channels),
+        None => builder,
+    }
+}
+
 fn open_hackrf(config: config::Device) -> anyhow::Result<Device> {
     let driver = "hackrf";
 
@@ -78,35 +511,34 @@ fn open_hackrf(config: config::Device) -> anyhow::Result<Device> {
         direction,
         freq_mhz,
         serial,
+        rx_gain,
+        tx_gain,
+        worker_threads,
+        worker_priority,
+        stream_buffers,
+        aa_tolerance,
     } = config
     else {
         return Err(anyhow::anyhow!("Invalid config"));
     };
 
-    let directions = direction_from_str(direction.as_str())?;
+    let sdr_config = SDRConfig::builder()
+        .driver(driver)
+        .direction(&direction)
+        .freq_mhz(freq_mhz)
+        .rx_gain(rx_gain.unwrap_or(HACKRF_RX_GAIN))
+        .tx_gain(tx_gain.unwrap_or(HACKRF_TX_GAIN))
+        .worker_threads(worker_threads)
+        .worker_priority(worker_priority)
+        .stream_buffers(stream_buffers.unwrap_or(DEFAULT_STREAM_BUFFERS))
+        .aa_tolerance(aa_tolerance.unwrap_or(0))
+        .build()?;
 
     log::trace!("driver: {}, serial: {}", driver, serial);
 
     let dev = RawDevice::new(format!("driver={},serial={}", driver, serial).as_str())
         .context("failed to open device")?;
 
-    let sdr_config = SDRConfig {
-        driver: driver.to_string(),
-        channels: 0,
-        num_channels: NUM_CHANNELS,
-        center_freq: freq_mhz as f64 * 1.0e6,
-        freq_mhz,
-        sample_rate: NUM_CHANNELS as f64 * 1.0e6,
-        bandwidth: NUM_CHANNELS as f64 * 1.0e6,
-        gain: if directions.contains(&Direction::Tx) {
-            32. + 14.
-        } else {
-            64.
-        },
-        directions,
-        // FIXME: separate rx/tx gain
-    };
-
     sdr_config.set(&dev)?;
 
     Ok(Device::new(dev, sdr_config))
@@ -114,29 +546,34 @@ fn open_hackrf(config: config::Device) -> anyhow::Result<Device> {
 fn open_virtual(config: config::Device) -> anyhow::Result<Device> {
     let driver = "virtual";
 
-    let config::Device::Virtual { direction } = config else {
+    let config::Device::Virtual {
+        direction,
+        freq_mhz,
+        sample_rate_hz,
+        num_channels,
+        worker_threads,
+        worker_priority,
+        stream_buffers,
+        aa_tolerance,
+    } = config
+    else {
         return Err(anyhow::anyhow!("Invalid config"));
     };
 
-    let directions = direction_from_str(direction.as_str())?;
+    let sdr_config = layout(SDRConfig::builder(), freq_mhz, sample_rate_hz, num_channels)
+        .driver(driver)
+        .direction(&direction)
+        .worker_threads(worker_threads)
+        .worker_priority(worker_priority)
+        .stream_buffers(stream_buffers.unwrap_or(DEFAULT_STREAM_BUFFERS))
+        .aa_tolerance(aa_tolerance.unwrap_or(0))
+        .build()?;
 
     log::trace!("driver: {}", driver);
 
     let dev =
         RawDevice::new(format!("driver={}", driver).as_str()).context("failed to open device")?;
 
-    let sdr_config = SDRConfig {
-        driver: driver.to_string(),
-        directions,
-        channels: 0,
-        num_channels: NUM_CHANNELS,
-        center_freq: 2427e6, // (TODO: add freqency to config)
-        freq_mhz: 2427,
-        sample_rate: NUM_CHANNELS as f64 * 1.0e6,
-        bandwidth: NUM_CHANNELS as f64 * 1.0e6,
-        gain: 64.,
-    };
-
     sdr_config.set(&dev)?;
 
     Ok(Device::new(dev, sdr_config))
@@ -144,40 +581,231 @@ fn open_virtual(config: config::Device) -> anyhow::Result<Device> {
 fn open_file(config: config::Device) -> anyhow::Result<Device> {
     let driver = "file";
 
-    let config::Device::File { direction, path } = config else {
+    let config::Device::File {
+        direction,
+        path,
+        repeat,
+        freq_mhz,
+        sample_rate_hz,
+        num_channels,
+        worker_threads,
+        worker_priority,
+        stream_buffers,
+        aa_tolerance,
+        detect_channel,
+    } = config
+    else {
         return Err(anyhow::anyhow!("Invalid config"));
     };
 
-    let directions = direction_from_str(direction.as_str())?;
+    // None for the text format (or a missing file, reported by the plugin)
+    let header = iq_file::IqFileReader::open(&path)
+        .ok()
+        .and_then(|reader| reader.header());
+
+    let (freq_mhz, sample_rate_hz) = match header {
+        Some(header) => (
+            freq_mhz.or(Some((header.center_freq / 1e6).round() as usize)),
+            sample_rate_hz.or(Some(header.sample_rate)),
+        ),
+        None => (freq_mhz, sample_rate_hz),
+    };
+
+    let sdr_config = layout(SDRConfig::builder(), freq_mhz, sample_rate_hz, num_channels)
+        .driver(driver)
+        .direction(&direction)
+        .backpressure(Backpressure::Block)
+        .repeat(repeat.unwrap_or(1))
+        .worker_threads(worker_threads)
+        .worker_priority(worker_priority)
+        .stream_buffers(stream_buffers.unwrap_or(DEFAULT_STREAM_BUFFERS))
+        .aa_tolerance(aa_tolerance.unwrap_or(0))
+        .detect_channel(detect_channel.unwrap_or(false))
+        .build()?;
+
+    if header.is_some() {
+        log::trace!("driver: {}, binary: {}", driver, path);
+
+        // nothing is streamed through SoapySDR, the built-in null device stands in
+        let dev = RawDevice::new("driver=null").context("failed to open device")?;
+
+        let mut device = Device::new(dev, sdr_config);
+        device.file_source = Some(path);
+
+        return Ok(device);
+    }
 
     log::trace!("driver: {}", driver);
 
     let dev = RawDevice::new(format!("driver={},path={}", driver, path).as_str())
         .context("failed to open device")?;
 
-    let sdr_config = SDRConfig {
-        driver: driver.to_string(),
-        directions,
-        channels: 0,
-        num_channels: NUM_CHANNELS,
-        center_freq: 2427e6, // (TODO: add freqency to config)
-        freq_mhz: 2427,
-        sample_rate: NUM_CHANNELS as f64 * 1.0e6,
-        bandwidth: NUM_CHANNELS as f64 * 1.0e6,
-        gain: 64.,
+    sdr_config.set(&dev)?;
+
+    Ok(Device::new(dev, sdr_config))
+}
+
+fn open_file_sink(config: config::Device) -> anyhow::Result<Device> {
+    let driver = "file_sink";
+
+    let config::Device::FileSink {
+        path,
+        format,
+        freq_mhz,
+        sample_rate_hz,
+        num_channels,
+    } = config
+    else {
+        return Err(anyhow::anyhow!("Invalid config"));
+    };
+
+    let format = format.as_deref().unwrap_or("cf32").parse()?;
+    let sdr_config = layout(SDRConfig::builder(), freq_mhz, sample_rate_hz, num_channels)
+        .driver(driver)
+        .direction("Tx")
+        .rx_gain(0.)
+        .tx_gain(0.)
+        .backpressure(Backpressure::Block)
+        .build()?;
+
+    log::trace!("driver: {}, path: {}", driver, path);
+
+    // nothing is streamed through SoapySDR, the built-in null device stands in
+    let dev = RawDevice::new("driver=null").context("failed to open device")?;
+
+    let mut device = Device::new(dev, sdr_config);
+    let header = iq_file::IqHeader {
+        sample_rate: device.config.sample_rate,
+        center_freq: device.config.center_freq,
+    };
+    device.file_sink = Some(iq_file::FileSink {
+        path,
+        format,
+        header,
+    });
+
+    Ok(device)
+}
+
+// "driver=xxx,key=value,..." (keys are sorted)
+fn device_args(driver: &str, args: &std::collections::HashMap<String, String>) -> String {
+    let mut keys = args.keys().collect::<Vec<_>>();
+    keys.sort();
+
+    let mut ret = format!("driver={}", driver);
+    for key in keys {
+        ret.push_str(&format!(",{}={}", key, args[key]));
+    }
+
+    ret
+}
+
+fn open_generic(config: config::Device) -> anyhow::Result<Device> {
+    let config::Device::Generic {
+        driver,
+        args,
+        direction,
+        freq_mhz,
+        sample_rate_hz,
+        num_channels,
+        gain,
+        worker_threads,
+        worker_priority,
+        stream_buffers,
+        aa_tolerance,
+    } = config
+    else {
+        return Err(anyhow::anyhow!("Invalid config"));
     };
 
+    let sdr_config = layout(
+        SDRConfig::builder(),
+        Some(freq_mhz),
+        sample_rate_hz,
+        num_channels,
+    )
+    .driver(driver.as_str())
+    .direction(&direction)
+    .rx_gain(gain)
+    .tx_gain(gain)
+    .worker_threads(worker_threads)
+    .worker_priority(worker_priority)
+    .stream_buffers(stream_buffers.unwrap_or(DEFAULT_STREAM_BUFFERS))
+    .aa_tolerance(aa_tolerance.unwrap_or(0))
+    .build()?;
+
+    let args = device_args(&driver, &args);
+    log::trace!("args: {}", args);
+
+    let dev = RawDevice::new(args.as_str()).context("failed to open device")?;
+
     sdr_config.set(&dev)?;
 
     Ok(Device::new(dev, sdr_config))
 }
 
+/// Overrides the directory of the SoapySDR modules (SoapyHackRF, soapy-utils)
+pub const SOAPY_MODULES_ENV: &str = "RFRAPTOR_SOAPY_MODULES";
+
+// true if `dir` has a loadable module in it
+fn has_modules(dir: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+
+    entries.flatten().any(|e| {
+        let path = e.path();
+        matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("so" | "dylib" | "dll")
+        )
+    })
+}
+
+// the override, then the modules built with this binary,
+// None to leave the search to SoapySDR (system module path)
+fn soapy_module_path(
+    override_path: Option<&str>,
+    built: &Path,
+) -> anyhow::Result<Option<std::path::PathBuf>> {
+    if let Some(path) = override_path {
+        let path = Path::new(path);
+        if !has_modules(path) {
+            anyhow::bail!(
+                "{}={}: no SoapySDR module found",
+                SOAPY_MODULES_ENV,
+                path.display()
+            );
+        }
+
+        return Ok(Some(path.to_path_buf()));
+    }
+
+    if has_modules(built) {
+        return Ok(Some(built.to_path_buf()));
+    }
+
+    Ok(None)
+}
+
 // return (rx stream, tx stream)
 pub fn open_device(config: config::List) -> anyhow::Result<Vec<Device>> {
-    let base = Path::new(env!("OUT_DIR"));
-    let module_path = base.join("lib/SoapySDR/modules0.8");
-    log::trace!("module_path: {}", module_path.display());
-    std::env::set_var("SOAPY_SDR_PLUGIN_PATH", module_path.display().to_string());
+    config.validate()?;
+
+    let built = Path::new(env!("OUT_DIR")).join("lib/SoapySDR/modules0.8");
+    let override_path = std::env::var(SOAPY_MODULES_ENV).ok();
+
+    match soapy_module_path(override_path.as_deref(), &built)? {
+        Some(module_path) => {
+            log::trace!("module_path: {}", module_path.display());
+            std::env::set_var("SOAPY_SDR_PLUGIN_PATH", module_path.display().to_string());
+        }
+        None => log::warn!(
+            "{} does not exist, using the system SoapySDR modules (set {} to override)",
+            built.display(),
+            SOAPY_MODULES_ENV
+        ),
+    }
 
     let mut ret = Vec::new();
     for dev_conf in config.devices {
@@ -185,6 +813,8 @@ pub fn open_device(config: config::List) -> anyhow::Result<Vec<Device>> {
             config::Device::HackRF { .. } => open_hackrf(dev_conf)?,
             config::Device::Virtual { .. } => open_virtual(dev_conf)?,
             config::Device::File { .. } => open_file(dev_conf)?,
+            config::Device::FileSink { .. } => open_file_sink(dev_conf)?,
+            config::Device::Generic { .. } => open_generic(dev_conf)?,
         };
 
         ret.push(dev);
@@ -192,3 +822,241 @@ pub fn open_device(config: config::List) -> anyhow::Result<Vec<Device>> {
 
     Ok(ret)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_layout_defaults() {
+        assert_eq!(
+            channel_layout(None, None, None).unwrap(),
+            (DEFAULT_FREQ_MHZ, NUM_CHANNELS, NUM_CHANNELS as f64 * 1.0e6)
+        );
+        assert_eq!(
+            channel_layout(Some(2440), Some(8e6), Some(8)).unwrap(),
+            (2440, 8, 8e6)
+        );
+
+        assert!(channel_layout(None, Some(20e6), None).is_err());
+        assert!(channel_layout(None, None, Some(7)).is_err());
+    }
+
+    #[test]
+    fn channel_mapping() {
+        // the layout is set as is, check_channel_mapping has to reject the invalid ones
+        let config = |freq_mhz: usize, num_channels: usize| SDRConfig {
+            num_channels,
+            center_freq: freq_mhz as f64 * 1.0e6,
+            freq_mhz,
+            sample_rate: num_channels as f64 * 1.0e6,
+            bandwidth: num_channels as f64 * 1.0e6,
+            ..SDRConfig::builder().driver("virtual").build().unwrap()
+        };
+
+        assert!(check_channel_mapping(&config(2427, 16)).is_ok());
+        assert!(check_channel_mapping(&config(2427, 7)).is_err());
+        assert!(check_channel_mapping(&config(2600, 16)).is_err());
+
+        let mut c = config(2427, 16);
+        c.sample_rate = 20e6;
+        assert!(check_channel_mapping(&c).is_err());
+
+        let mut c = config(2427, 16);
+        c.center_freq = 2440e6;
+        assert!(check_channel_mapping(&c).is_err());
+    }
+
+    fn validate(yaml: &str) -> Result<(), String> {
+        let list: config::List = serde_yaml::from_str(yaml).unwrap();
+        list.validate().map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn validate_direction() {
+        assert_eq!(
+            validate("devices:\n- !Virtual\n  direction: RxTx\n"),
+            Ok(())
+        );
+        assert_eq!(
+            validate("devices:\n- !File\n  direction: rx\n  path: a.txt\n"),
+            Err("device[0].direction must be one of Rx/Tx/RxTx, got 'rx'".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_freq() {
+        assert_eq!(
+            validate("devices:\n- !FileSink\n  path: a.cf32\n  freq_mhz: 2480\n"),
+            Ok(())
+        );
+        assert_eq!(
+            validate(
+                "devices:\n- !Virtual\n  direction: Rx\n- !Generic\n  driver: rtlsdr\n  direction: Rx\n  freq_mhz: 915\n  gain: 10\n"
+            ),
+            Err("device[1].freq_mhz must be in 2400..=2483 MHz, got 915".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_stream_buffers() {
+        let yaml = "devices:\n- !Virtual\n  direction: Rx\n  stream_buffers: 16\n";
+        let list: config::List = serde_yaml::from_str(yaml).unwrap();
+        assert!(matches!(
+            list.devices[0],
+            config::Device::Virtual {
+                stream_buffers: Some(16),
+                ..
+            }
+        ));
+        assert_eq!(validate(yaml), Ok(()));
+
+        assert_eq!(
+            validate("devices:\n- !Virtual\n  direction: Rx\n  stream_buffers: 0\n"),
+            Err("device[0].stream_buffers must be positive".to_string())
+        );
+    }
+
+    #[test]
+    fn aa_tolerance_field() {
+        let list: config::List = serde_yaml::from_str(
+            "devices:\n- !File\n  direction: Rx\n  path: a.txt\n  aa_tolerance: 2\n- !Virtual\n  direction: Rx\n",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            list.devices[0],
+            config::Device::File {
+                aa_tolerance: Some(2),
+                ..
+            }
+        ));
+        assert!(matches!(
+            list.devices[1],
+            config::Device::Virtual {
+                aa_tolerance: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn validate_serial() {
+        let hackrf = |serial: &str| {
+            validate(&format!(
+                "devices:\n- !HackRF\n  direction: Rx\n  freq_mhz: 2427\n  serial: '{}'\n",
+                serial
+            ))
+        };
+
+        assert_eq!(hackrf("0000000000000000f77c60dc259132c3"), Ok(()));
+        assert_eq!(
+            hackrf("f77c60dc-2591"),
+            Err(
+                "device[0].serial must be up to 32 hex digits (see `hackrf_info`), got 'f77c60dc-2591'"
+                    .to_string()
+            )
+        );
+        assert!(hackrf("").is_err());
+        assert!(hackrf(&"0".repeat(33)).is_err());
+    }
+
+    #[test]
+    fn module_path_override() {
+        let base = std::env::temp_dir().join(format!("rfraptor-modules-{}", std::process::id()));
+        let built = base.join("built");
+        let custom = base.join("custom");
+        let empty = base.join("empty");
+
+        for dir in [&built, &custom, &empty] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(built.join("libHackRFSupport.so"), b"").unwrap();
+        std::fs::write(custom.join("libFileSupport.so"), b"").unwrap();
+
+        let path = |p: &std::path::Path| p.display().to_string();
+
+        let ret = (
+            soapy_module_path(Some(&path(&custom)), &built).unwrap(),
+            soapy_module_path(None, &built).unwrap(),
+            soapy_module_path(None, &empty).unwrap(),
+            soapy_module_path(Some(&path(&empty)), &built).is_err(),
+        );
+        std::fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(ret.0, Some(custom));
+        assert_eq!(ret.1, Some(built));
+        assert_eq!(ret.2, None);
+        assert!(ret.3);
+    }
+
+    #[test]
+    fn hackrf_gain_from_yaml() {
+        let yaml = r#"
+devices:
+- !HackRF
+  direction: RxTx
+  freq_mhz: 2427
+  serial: 0000000000000000f77c60dc259132c3
+  rx_gain: 40
+  tx_gain: 20
+- !HackRF
+  direction: Rx
+  freq_mhz: 2427
+  serial: 0000000000000000f77c60dc259132c3
+"#;
+
+        let list: config::List = serde_yaml::from_str(yaml).unwrap();
+
+        let config::Device::HackRF {
+            rx_gain, tx_gain, ..
+        } = &list.devices[0]
+        else {
+            panic!("unexpected device: {:?}", list.devices[0]);
+        };
+        assert_eq!((*rx_gain, *tx_gain), (Some(40.), Some(20.)));
+
+        let config::Device::HackRF {
+            rx_gain, tx_gain, ..
+        } = &list.devices[1]
+        else {
+            panic!("unexpected device: {:?}", list.devices[1]);
+        };
+        assert_eq!((*rx_gain, *tx_gain), (None, None));
+    }
+
+    #[test]
+    fn generic_device_args() {
+        let yaml = r#"
+devices:
+- !Generic
+  driver: rtlsdr
+  args:
+    serial: "00000001"
+    buffers: "16"
+  direction: Rx
+  freq_mhz: 2426
+  num_channels: 2
+  gain: 40
+"#;
+
+        let list: config::List = serde_yaml::from_str(yaml).unwrap();
+
+        let config::Device::Generic {
+            driver,
+            args,
+            num_channels,
+            ..
+        } = &list.devices[0]
+        else {
+            panic!("unexpected device: {:?}", list.devices[0]);
+        };
+
+        assert_eq!(*num_channels, Some(2));
+        assert_eq!(
+            device_args(driver, args),
+            "driver=rtlsdr,buffers=16,serial=00000001"
+        );
+        assert_eq!(device_args("lime", &Default::default()), "driver=lime");
+    }
+}
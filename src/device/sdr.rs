@@ -1,3 +1,8 @@
+use anyhow::Context;
+
+/// SoapySDR rx stream buffers unless configured
+pub const DEFAULT_STREAM_BUFFERS: usize = 65535;
+
 #[derive(Debug, Clone)]
 pub struct SDRConfig {
     pub driver: String,
@@ -22,11 +27,99 @@ pub struct SDRConfig {
     /// Bandwidth of the SDR
     pub bandwidth: f64,
 
-    /// Gain of the SDR
-    pub gain: f64,
+    /// Rx gain of the SDR [dB]
+    pub rx_gain: f64,
+
+    /// Tx gain of the SDR [dB]
+    pub tx_gain: f64,
+
+    /// Depth of each channelizer -> catcher queue [chunks]
+    pub channel_depth: usize,
+
+    /// What the channelizer does when a queue is full
+    pub backpressure: Backpressure,
+
+    /// Number of passes over the input (File driver)
+    pub repeat: usize,
+
+    /// Squelch of the burst catcher
+    pub squelch: crate::burst::BurstParams,
+
+    /// Number of catcher threads (`None`: one per channel)
+    pub worker_threads: Option<usize>,
+
+    /// Priority of the catcher threads, 0..=99 (`None`: left to the OS).
+    /// Raising it needs permission (e.g. CAP_SYS_NICE), the threads run anyway without it
+    pub worker_priority: Option<u8>,
+
+    /// Bit errors tolerated in the advertising access address (0: exact match)
+    pub aa_tolerance: u32,
+
+    /// Bit errors tolerated in the preamble (0: exact match)
+    pub preamble_tolerance: u32,
+
+    /// Mean input power [dBFS] below which a block is not channelized (`None`: channelize every block)
+    pub energy_gate_db: Option<f32>,
+
+    /// Access addresses to decode, the other packets are dropped before parsing (`None`: every AA)
+    pub aa_filter: Option<std::collections::HashSet<u32>>,
+
+    /// Blocks discarded after the stream is activated, while the front-end settles
+    pub warmup_blocks: usize,
+
+    /// Number of SoapySDR rx stream buffers (see `stream_args`)
+    pub stream_buffers: usize,
+
+    /// Try the whitening of every BLE channel when a packet does not decode,
+    /// for captures with a wrong frequency (see `bitops::bits_to_packet_detect_channel`)
+    pub detect_channel: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for the catcher (no sample is lost, for replaying files)
+    Block,
+    /// Drop the oldest queued chunk and count it, the newest samples are kept (for live capture)
+    Drop,
 }
 
 impl SDRConfig {
+    /// Start from the defaults, e.g. for `Device::from_raw`
+    pub fn builder() -> SDRConfigBuilder {
+        SDRConfigBuilder {
+            config: SDRConfig {
+                driver: String::new(),
+                directions: Vec::new(),
+                channels: 0,
+                num_channels: 0,
+                center_freq: 0.,
+                freq_mhz: 0,
+                sample_rate: 0.,
+                bandwidth: 0.,
+                rx_gain: 64.,
+                tx_gain: 64.,
+                channel_depth: super::CHANNEL_DEPTH,
+                backpressure: Backpressure::Drop,
+                repeat: 1,
+                squelch: crate::burst::BurstParams::default(),
+                worker_threads: None,
+                worker_priority: None,
+                aa_tolerance: 0,
+                preamble_tolerance: 0,
+                energy_gate_db: None,
+                aa_filter: None,
+                warmup_blocks: 0,
+                stream_buffers: DEFAULT_STREAM_BUFFERS,
+                detect_channel: false,
+            },
+            direction: "Rx".to_string(),
+            freq_mhz: None,
+            num_channels: None,
+            sample_rate: None,
+            bandwidth: None,
+        }
+    }
+
     pub fn set(&self, dev: &soapysdr::Device) -> anyhow::Result<()> {
         // for channel in 0..=self.channels {
         //     dev.set_frequency(Rx, channel, self.center_freq, ())?;
@@ -36,24 +129,508 @@ impl SDRConfig {
         // }
 
         for direction in &self.directions {
-            for channel in 0..self.num_channels {
+            // some drivers do not report the ranges
+            match dev.get_sample_rate_range(*direction, self.channels) {
+                Ok(ranges) => check_sample_rate(self.sample_rate, &ranges).with_context(|| {
+                    format!(
+                        "{} ({:?}, {} channels)",
+                        self.driver, direction, self.num_channels
+                    )
+                })?,
+                Err(e) => log::debug!("{}: no sample rate range: {}", self.driver, e),
+            }
+
+            // the antenna channels of the device (1 or 2 on most of them), not the channelizer outputs
+            let antenna_channels = dev.num_channels(*direction)?;
+            if self.channels >= antenna_channels {
+                anyhow::bail!(
+                    "{}: no antenna channel {} ({:?}, {} channels)",
+                    self.driver,
+                    self.channels,
+                    direction,
+                    antenna_channels
+                );
+            }
+
+            for channel in 0..antenna_channels {
                 dev.set_frequency(*direction, channel, self.center_freq, ())?;
                 dev.set_sample_rate(*direction, channel, self.sample_rate)?;
                 dev.set_bandwidth(*direction, channel, self.bandwidth)?;
-                dev.set_gain(*direction, channel, self.gain)?;
+                dev.set_gain(*direction, channel, self.gain(*direction))?;
             }
         }
 
         Ok(())
     }
+
+    pub fn gain(&self, direction: soapysdr::Direction) -> f64 {
+        match direction {
+            soapysdr::Direction::Rx => self.rx_gain,
+            soapysdr::Direction::Tx => self.tx_gain,
+        }
+    }
+
+    /// Center frequency [Hz] of each channelizer output, in output order
+    /// (see `stream::ChannelPlan`: the upper half of the outputs is below `center_freq`)
+    pub fn channel_frequencies(&self) -> Vec<f64> {
+        let plan = crate::stream::ChannelPlan::from_config(self);
+
+        (0..self.num_channels)
+            .map(|i| {
+                let mhz = plan
+                    .sdr_idx_to_freq(i)
+                    .expect("channelizer output below 0 Hz");
+                mhz as f64 * 1e6
+            })
+            .collect()
+    }
+
+    /// Stream args of the rx stream (`Device::rx_stream_args`)
+    pub fn stream_args(&self) -> String {
+        format!("buffers={}", self.stream_buffers)
+    }
+
+    /// Read the settings back from `dev` (antenna channel only)
+    pub fn read_back(&self, dev: &soapysdr::Device) -> anyhow::Result<Vec<AppliedSettings>> {
+        let mut ret = Vec::new();
+
+        for direction in &self.directions {
+            let channel = self.channels;
+
+            ret.push(AppliedSettings {
+                direction: *direction,
+                channel,
+                antenna: dev.antenna(*direction, channel)?,
+                center_freq: dev.frequency(*direction, channel)?,
+                sample_rate: dev.sample_rate(*direction, channel)?,
+                gain: dev.gain(*direction, channel)?,
+            });
+        }
+
+        Ok(ret)
+    }
+}
+
+// `sample_rate` [Hz] must be in one of the `ranges` reported by the device (if any)
+fn check_sample_rate(sample_rate: f64, ranges: &[soapysdr::Range]) -> anyhow::Result<()> {
+    if ranges.is_empty()
+        || ranges
+            .iter()
+            .any(|r| r.minimum <= sample_rate && sample_rate <= r.maximum)
+    {
+        return Ok(());
+    }
+
+    let allowed = ranges
+        .iter()
+        .map(|r| format!("{}..={}", r.minimum, r.maximum))
+        .collect::<Vec<_>>();
+
+    anyhow::bail!(
+        "sample rate {} Hz is not supported, allowed: {} Hz (fewer num_channels?)",
+        sample_rate,
+        allowed.join(", ")
+    )
+}
+
+/// Settings reported by the device after `SDRConfig::set`
+#[derive(Debug, Clone)]
+pub struct AppliedSettings {
+    pub direction: soapysdr::Direction,
+    pub channel: usize,
+    pub antenna: String,
+    pub center_freq: f64,
+    pub sample_rate: f64,
+    pub gain: f64,
+}
+
+impl AppliedSettings {
+    /// Human readable differences from the requested `config` (empty if all were applied)
+    pub fn mismatches(&self, config: &SDRConfig) -> Vec<String> {
+        let mut ret = Vec::new();
+
+        let checks = [
+            ("center_freq", config.center_freq, self.center_freq, 1.),
+            ("sample_rate", config.sample_rate, self.sample_rate, 1.),
+            ("gain", config.gain(self.direction), self.gain, 0.5),
+        ];
+
+        for (name, requested, applied, tolerance) in checks {
+            if (requested - applied).abs() > tolerance {
+                ret.push(format!(
+                    "{}: requested {}, applied {}",
+                    name, requested, applied
+                ));
+            }
+        }
+
+        ret
+    }
+}
+
+/// Settings reported by the device at `timestamp`, one per direction (see `Device::readback`)
+#[derive(Debug, Clone)]
+pub struct SDRReadback {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub applied: Vec<AppliedSettings>,
+}
+
+impl SDRReadback {
+    /// `AppliedSettings::mismatches` of every direction, prefixed by the direction
+    pub fn drift(&self, config: &SDRConfig) -> Vec<String> {
+        self.applied
+            .iter()
+            .flat_map(|applied| {
+                applied
+                    .mismatches(config)
+                    .into_iter()
+                    .map(move |m| format!("{:?} {}", applied.direction, m))
+            })
+            .collect()
+    }
+}
+
+/// Builds an `SDRConfig` with the defaults of the `open_*` helpers (see `SDRConfig::builder`)
+#[derive(Debug, Clone)]
+pub struct SDRConfigBuilder {
+    // everything but the direction and the channel layout
+    config: SDRConfig,
+
+    direction: String,
+    freq_mhz: Option<usize>,
+    num_channels: Option<usize>,
+    sample_rate: Option<f64>,
+    bandwidth: Option<f64>,
+}
+
+impl SDRConfigBuilder {
+    /// "Rx" | "Tx" | "RxTx", default "Rx"
+    pub fn direction(mut self, direction: &str) -> Self {
+        self.direction = direction.to_string();
+        self
+    }
+
+    /// [MHz], default 2427
+    pub fn freq_mhz(mut self, freq_mhz: usize) -> Self {
+        self.freq_mhz = Some(freq_mhz);
+        self
+    }
+
+    /// number of 1 MHz channels, default 16
+    pub fn num_channels(mut self, num_channels: usize) -> Self {
+        self.num_channels = Some(num_channels);
+        self
+    }
+
+    /// [Hz], default (and only valid value) num_channels MHz
+    pub fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// [Hz], default the sample rate
+    pub fn bandwidth(mut self, bandwidth: f64) -> Self {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// the SoapySDR driver (informational, the device is opened by the caller)
+    pub fn driver(mut self, value: impl Into<String>) -> Self {
+        self.config.driver = value.into();
+        self
+    }
+
+    /// SDR antenna channel, default 0
+    pub fn channels(mut self, value: usize) -> Self {
+        self.config.channels = value;
+        self
+    }
+
+    /// default 64 dB
+    pub fn rx_gain(mut self, value: f64) -> Self {
+        self.config.rx_gain = value;
+        self
+    }
+
+    /// default 64 dB
+    pub fn tx_gain(mut self, value: f64) -> Self {
+        self.config.tx_gain = value;
+        self
+    }
+
+    /// default 1024 chunks
+    pub fn channel_depth(mut self, value: usize) -> Self {
+        self.config.channel_depth = value;
+        self
+    }
+
+    /// default `Backpressure::Drop`
+    pub fn backpressure(mut self, value: Backpressure) -> Self {
+        self.config.backpressure = value;
+        self
+    }
+
+    /// default 1
+    pub fn repeat(mut self, value: usize) -> Self {
+        self.config.repeat = value;
+        self
+    }
+
+    /// default `BurstParams::default()`
+    pub fn squelch(mut self, value: crate::burst::BurstParams) -> Self {
+        self.config.squelch = value;
+        self
+    }
+
+    /// default one per channel
+    pub fn worker_threads(mut self, value: Option<usize>) -> Self {
+        self.config.worker_threads = value;
+        self
+    }
+
+    /// default None
+    pub fn worker_priority(mut self, value: Option<u8>) -> Self {
+        self.config.worker_priority = value;
+        self
+    }
+
+    /// default 0
+    pub fn aa_tolerance(mut self, value: u32) -> Self {
+        self.config.aa_tolerance = value;
+        self
+    }
+
+    /// default 0
+    pub fn preamble_tolerance(mut self, value: u32) -> Self {
+        self.config.preamble_tolerance = value;
+        self
+    }
+
+    /// default None
+    pub fn energy_gate_db(mut self, value: Option<f32>) -> Self {
+        self.config.energy_gate_db = value;
+        self
+    }
+
+    /// default None
+    pub fn aa_filter(mut self, value: Option<std::collections::HashSet<u32>>) -> Self {
+        self.config.aa_filter = value;
+        self
+    }
+
+    /// default 0
+    pub fn warmup_blocks(mut self, value: usize) -> Self {
+        self.config.warmup_blocks = value;
+        self
+    }
+
+    /// default `DEFAULT_STREAM_BUFFERS`
+    pub fn stream_buffers(mut self, value: usize) -> Self {
+        self.config.stream_buffers = value;
+        self
+    }
+
+    /// default false
+    pub fn detect_channel(mut self, value: bool) -> Self {
+        self.config.detect_channel = value;
+        self
+    }
+
+    /// Fill in the channel layout and check it against the channelizer
+    pub fn build(self) -> anyhow::Result<SDRConfig> {
+        let directions = super::direction_from_str(&self.direction).with_context(|| {
+            format!("direction must be one of Rx/Tx/RxTx: '{}'", self.direction)
+        })?;
+        let (freq_mhz, num_channels, sample_rate) =
+            super::channel_layout(self.freq_mhz, self.sample_rate, self.num_channels)?;
+
+        if let Some(priority) = self.config.worker_priority {
+            anyhow::ensure!(
+                priority <= 99,
+                "worker_priority must be in 0..=99: {}",
+                priority
+            );
+        }
+
+        let config = SDRConfig {
+            directions,
+            num_channels,
+            center_freq: freq_mhz as f64 * 1.0e6,
+            freq_mhz,
+            sample_rate,
+            bandwidth: self.bandwidth.unwrap_or(sample_rate),
+            ..self.config
+        };
+        super::check_channel_mapping(&config)?;
+
+        Ok(config)
+    }
 }
 
 impl core::fmt::Display for SDRConfig {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
             f,
-            "SDRConfig {{ driver: {}, directions: {:?}, channels: {}, num_channels: {}, center_freq: {}, sample_rate: {}, bandwidth: {}, gain: {} }}",
-            self.driver, self.directions, self.channels, self.num_channels, self.center_freq, self.sample_rate, self.bandwidth, self.gain
+            "SDRConfig {{ driver: {}, directions: {:?}, channels: {}, num_channels: {}, center_freq: {}, sample_rate: {}, bandwidth: {}, rx_gain: {}, tx_gain: {}, channel_depth: {}, backpressure: {:?}, repeat: {}, squelch: {:?}, worker_threads: {:?}, worker_priority: {:?}, aa_tolerance: {}, preamble_tolerance: {}, energy_gate_db: {:?}, aa_filter: {:?}, warmup_blocks: {}, stream_buffers: {}, detect_channel: {} }}",
+            self.driver, self.directions, self.channels, self.num_channels, self.center_freq, self.sample_rate, self.bandwidth, self.rx_gain, self.tx_gain, self.channel_depth, self.backpressure, self.repeat, self.squelch, self.worker_threads, self.worker_priority, self.aa_tolerance, self.preamble_tolerance, self.energy_gate_db, self.aa_filter, self.warmup_blocks, self.stream_buffers, self.detect_channel
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_rate_range() {
+        // as reported by a HackRF
+        let hackrf = [soapysdr::Range {
+            minimum: 1e6,
+            maximum: 20e6,
+            step: 0.,
+        }];
+
+        assert!(check_sample_rate(16e6, &hackrf).is_ok());
+        assert!(check_sample_rate(16e6, &[]).is_ok());
+
+        let e = check_sample_rate(32e6, &hackrf).unwrap_err().to_string();
+        assert!(e.contains("1000000..=20000000"), "{}", e);
+    }
+
+    #[test]
+    fn builder_defaults() {
+        let config = SDRConfig::builder()
+            .freq_mhz(2440)
+            .driver("hackrf")
+            .build()
+            .unwrap();
+
+        assert_eq!(config.driver, "hackrf");
+        assert_eq!(config.directions, vec![soapysdr::Direction::Rx]);
+        assert_eq!(config.freq_mhz, 2440);
+        assert_eq!(config.center_freq, 2440e6);
+        assert_eq!(config.num_channels, 16);
+        assert_eq!(config.sample_rate, 16e6);
+        assert_eq!(config.bandwidth, 16e6);
+        assert_eq!(config.channel_depth, 1024);
+        assert_eq!(config.backpressure, Backpressure::Drop);
+        assert_eq!(config.repeat, 1);
+        assert_eq!(config.stream_buffers, DEFAULT_STREAM_BUFFERS);
+        assert_eq!(config.worker_threads, None);
+        assert_eq!(config.worker_priority, None);
+        assert!(!config.detect_channel);
+
+        let config = SDRConfig::builder()
+            .direction("RxTx")
+            .num_channels(8)
+            .build()
+            .unwrap();
+        assert_eq!(config.freq_mhz, 2427);
+        assert_eq!(config.sample_rate, 8e6);
+        assert_eq!(config.directions.len(), 2);
+
+        assert!(SDRConfig::builder().direction("rx").build().is_err());
+        assert!(SDRConfig::builder().freq_mhz(2600).build().is_err());
+        assert!(SDRConfig::builder().num_channels(7).build().is_err());
+        assert!(SDRConfig::builder().sample_rate(20e6).build().is_err());
+        assert!(SDRConfig::builder()
+            .worker_priority(Some(99))
+            .build()
+            .is_ok());
+        assert!(SDRConfig::builder()
+            .worker_priority(Some(100))
+            .build()
+            .is_err());
+    }
+
+    fn config() -> SDRConfig {
+        SDRConfig::builder()
+            .driver("file")
+            .freq_mhz(2427)
+            .num_channels(16)
+            .rx_gain(40.)
+            .tx_gain(20.)
+            .backpressure(Backpressure::Block)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn applied_settings_mismatches() {
+        let config = config();
+
+        let mut applied = AppliedSettings {
+            direction: soapysdr::Direction::Rx,
+            channel: 0,
+            antenna: "RX".to_string(),
+            center_freq: 2427e6,
+            sample_rate: 16e6,
+            gain: 40.,
+        };
+        assert!(applied.mismatches(&config).is_empty());
+
+        applied.center_freq = 2426e6;
+        applied.gain = 20.;
+        assert_eq!(
+            applied.mismatches(&config),
+            vec![
+                "center_freq: requested 2427000000, applied 2426000000".to_string(),
+                "gain: requested 40, applied 20".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn readback_drift() {
+        let config = config();
+        let applied = |direction, center_freq| AppliedSettings {
+            direction,
+            channel: 0,
+            antenna: "RX".to_string(),
+            center_freq,
+            sample_rate: 16e6,
+            gain: 40.,
+        };
+
+        let mut readback = SDRReadback {
+            timestamp: chrono::Utc::now(),
+            applied: vec![applied(soapysdr::Direction::Rx, 2427e6)],
+        };
+        assert!(readback.drift(&config).is_empty());
+
+        readback.applied[0].center_freq = 2427.01e6;
+        assert_eq!(
+            readback.drift(&config),
+            vec!["Rx center_freq: requested 2427000000, applied 2427010000".to_string()]
+        );
+    }
+
+    #[test]
+    fn stream_args() {
+        let mut config = config();
+        assert_eq!(config.stream_args(), "buffers=65535");
+
+        config.stream_buffers = 16;
+        assert_eq!(config.stream_args(), "buffers=16");
+    }
+
+    #[test]
+    fn channel_frequencies() {
+        let config = config();
+        let freqs = config.channel_frequencies();
+
+        let mhz = freqs.iter().map(|f| f / 1e6).collect::<Vec<_>>();
+        assert_eq!(
+            mhz,
+            vec![
+                2427., 2428., 2429., 2430., 2431., 2432., 2433., 2434., // above the center
+                2419., 2420., 2421., 2422., 2423., 2424., 2425., 2426., // below
+            ]
+        );
+
+        let plan = crate::stream::ChannelPlan::from_config(&config);
+        for (i, f) in mhz.iter().enumerate() {
+            assert_eq!(plan.sdr_idx_to_freq(i), Some(*f as usize));
+        }
+    }
+}
@@ -17,15 +17,34 @@ use num_traits::Signed;
 /// at least 64 symbols are needed to calculate the median
 const MEDIAN_SYMBOLS: usize = 64usize;
 
+/// loop gain of the symbol timing recovery [samples per unit of timing error]
+const TIMING_GAIN: f32 = 0.05;
+
+/// EWMA weight of the newest sample in the leading silence skip
+const SILENCE_ALPHA: f32 = 0.8;
+
+/// the burst starts where the EWMA of |demod| (in units of the deviation) exceeds this
+const SILENCE_THRESHOLD: f32 = 0.5;
+
+/// Turns a burst into bits (see `FskDemod`)
+pub trait Demodulator {
+    fn demodulate(&mut self, burst: burst::Packet) -> anyhow::Result<Packet>;
+}
+
+/// Creates a demodulator for each channel (called on the worker thread)
+pub type DemodulatorFactory = std::sync::Arc<
+    dyn Fn(&crate::device::sdr::SDRConfig) -> anyhow::Result<Box<dyn Demodulator>> + Send + Sync,
+>;
+
 /// FSK demodulator
 #[derive(Debug)]
 pub struct FskDemod {
     #[allow(unused)]
     freqdem: NonNull<freqdem_s>,
 
-    /// number of samples per symbol
+    /// number of samples per symbol (may be fractional, e.g. 20 MHz / 16 channels)
     #[allow(unused)]
-    pub sample_per_symbol: usize,
+    pub sample_per_symbol: f32,
 
     /// number of symbols needed to calculate the median
     #[allow(unused)]
@@ -34,6 +53,54 @@ pub struct FskDemod {
     /// limit of the frequency offset
     #[allow(unused)]
     pub max_freq_offset: f32,
+
+    /// track the symbol timing instead of sampling every `sample_per_symbol`
+    #[allow(unused)]
+    pub timing_recovery: bool,
+
+    /// EWMA weight of the newest sample in the leading silence skip
+    #[allow(unused)]
+    pub silence_alpha: f32,
+
+    /// squelch of the leading silence skip (in units of the deviation)
+    #[allow(unused)]
+    pub silence_threshold: f32,
+
+    /// samples above this (in units of the deviation) are a phase jump, not a symbol, in the
+    /// leading silence skip
+    #[allow(unused)]
+    pub phase_jump: Option<f32>,
+}
+
+/// Parameters of the CFO / deviation estimation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FskParams {
+    /// number of symbols needed to calculate the median
+    pub need_symbol: usize,
+    /// limit of the frequency offset
+    pub max_freq_offset: f32,
+    /// track the symbol timing (Gardner) instead of sampling every `sample_per_symbol`
+    pub timing_recovery: bool,
+    /// EWMA weight of the newest sample in the leading silence skip
+    pub silence_alpha: f32,
+    /// squelch of the leading silence skip (lower it for weak bursts)
+    pub silence_threshold: f32,
+    /// ignore larger samples in the leading silence skip, e.g. the phase jump into a
+    /// synthesized burst (off by default)
+    pub phase_jump: Option<f32>,
+}
+
+impl Default for FskParams {
+    fn default() -> Self {
+        Self {
+            need_symbol: MEDIAN_SYMBOLS,
+            max_freq_offset: 0.4f32,
+            timing_recovery: false,
+            silence_alpha: SILENCE_ALPHA,
+            silence_threshold: SILENCE_THRESHOLD,
+            phase_jump: None,
+        }
+    }
 }
 
 /// FSK demodulated packet
@@ -57,6 +124,18 @@ pub struct Packet {
     /// frequency deviation
     #[allow(unused)]
     pub deviation: f32,
+
+    /// quality estimate (deviation / interquartile spread of the symbol clusters)
+    #[allow(unused)]
+    pub snr: f32,
+
+    /// index in `demod` of the first sampled symbol (the leading silence is skipped)
+    #[allow(unused)]
+    pub symbol_start: usize,
+
+    /// nominal step in `demod` between the sampled symbols
+    #[allow(unused)]
+    pub sample_per_symbol: f32,
 }
 
 impl Drop for FskDemod {
@@ -77,22 +156,52 @@ impl FskDemod {
     /// # Arguments
     /// * `sample_rate` [Hz] - The sample rate of the incoming data
     /// * `num_channels` - The number of channels to use
-    pub fn new(sample_rate: f32, num_channels: usize) -> Self {
+    pub fn new(sample_rate: f32, num_channels: usize) -> anyhow::Result<Self> {
+        Self::with_params(sample_rate, num_channels, FskParams::default())
+    }
+
+    /// Create a new FSK demodulator with a custom median window (e.g. for short frames)
+    pub fn with_params(
+        sample_rate: f32,
+        num_channels: usize,
+        params: FskParams,
+    ) -> anyhow::Result<Self> {
+        if !(params.silence_alpha > 0. && params.silence_alpha <= 1.) {
+            anyhow::bail!("silence_alpha must be in (0, 1]: {}", params.silence_alpha);
+        }
+
+        // a burst is normalized to a deviation of 1, a squelch at or above it never opens
+        if !(params.silence_threshold > 0. && params.silence_threshold < 1.) {
+            anyhow::bail!(
+                "silence_threshold must be in (0, 1): {}",
+                params.silence_threshold
+            );
+        }
+
+        // the skewed fallback needs a quarter of the window on each side of zero
+        if params.need_symbol < 4 {
+            anyhow::bail!("need_symbol must be at least 4: {}", params.need_symbol);
+        }
+
         let freqdem = liquid_get_pointer(|| unsafe { freqdem_create(0.8f32) })
-            .expect("freqdem_create failed");
-        let sample_per_symbol = (sample_rate / (num_channels as f32) / 1e6f32 * 2.0) as usize;
+            .context("freqdem_create failed")?;
+        let sample_per_symbol = sample_rate / (num_channels as f32) / 1e6f32 * 2.0;
 
-        Self {
+        Ok(Self {
             freqdem,
             sample_per_symbol,
-            need_symbol: MEDIAN_SYMBOLS,
-            max_freq_offset: 0.4f32,
-        }
+            need_symbol: params.need_symbol,
+            max_freq_offset: params.max_freq_offset,
+            timing_recovery: params.timing_recovery,
+            silence_alpha: params.silence_alpha,
+            silence_threshold: params.silence_threshold,
+            phase_jump: params.phase_jump,
+        })
     }
 
     // Number of samples needed to calculate the median
     fn median_size(&self) -> usize {
-        self.sample_per_symbol * self.need_symbol
+        (self.sample_per_symbol * self.need_symbol as f32).ceil() as usize
     }
 
     // Raw demodulation
@@ -140,8 +249,8 @@ impl FskDemod {
         // demodulate the data
         let mut demod = self.liquid_demod(data)?;
 
-        // get the CFO and deviation
-        let (cfo, deviation) = self.correction(&demod)?;
+        // get the CFO, deviation and quality
+        let (cfo, deviation, snr) = self.correction(&demod)?;
         demod.iter_mut().for_each(|d| {
             *d -= cfo;
             *d /= deviation;
@@ -152,18 +261,44 @@ impl FskDemod {
             demod[0] = 0.;
         }
 
+        // skip silence at the beginning
         let mut ewma = 0.;
-        let bits = demod
+        let symbol_start = demod
             .iter()
-            // skip silence at the beginning
-            .skip_while(|v| {
-                const ALPHA: f32 = 0.8;
-                ewma = ewma * (1. - ALPHA) + v.abs() * ALPHA;
-
-                ewma <= 0.5
+            .position(|v| {
+                let v = match self.phase_jump {
+                    Some(jump) if v.abs() > jump => 0.,
+                    _ => v.abs(),
+                };
+                ewma = ewma * (1. - self.silence_alpha) + v * self.silence_alpha;
+
+                ewma > self.silence_threshold
             })
-            // each symbol has 2 samples (?)
-            .step_by(self.sample_per_symbol)
+            .unwrap_or(demod.len());
+
+        // e.g. a constant tone
+        if symbol_start == demod.len() {
+            anyhow::bail!("no symbols after the leading silence");
+        }
+
+        let symbol_start = align_to_crossing(&demod, symbol_start, self.sample_per_symbol);
+
+        let symbols = if self.timing_recovery {
+            recover_symbols(&demod[symbol_start..], self.sample_per_symbol)
+        } else {
+            let demod = &demod[symbol_start..];
+            let last = demod.len() as f32 - 1.;
+
+            // fixed (possibly fractional) step, interpolated between the samples
+            (0..)
+                .map(|i| i as f32 * self.sample_per_symbol)
+                .take_while(|&t| t <= last)
+                .map(|t| interpolate(demod, t))
+                .collect()
+        };
+
+        let bits = symbols
+            .iter()
             .map(|v| if v > &0.0 { 1 } else { 0 })
             .collect::<Vec<u8>>();
 
@@ -173,11 +308,14 @@ impl FskDemod {
             demod,
             cfo,
             deviation,
+            snr,
+            symbol_start,
+            sample_per_symbol: self.sample_per_symbol,
         })
     }
 
-    // Calculate the CFO and deviation
-    fn correction(&self, demod: &[f32]) -> anyhow::Result<(f32, f32)> {
+    // Calculate the CFO, deviation and quality
+    fn correction(&self, demod: &[f32]) -> anyhow::Result<(f32, f32, f32)> {
         let mut pos = Vec::new();
         let mut neg = Vec::new();
 
@@ -194,9 +332,13 @@ impl FskDemod {
             }
         }
 
-        // the data is too skewed
+        // the data is too skewed (e.g. strong DC offset near the channel edge)
         if pos.len() < self.need_symbol / 4 || neg.len() < self.need_symbol / 4 {
-            anyhow::bail!("data is too skewed");
+            return self.skewed_correction(pos.into_iter().chain(neg).collect());
+        }
+
+        if pos.is_empty() || neg.is_empty() {
+            anyhow::bail!("no symbols on one side of zero");
         }
 
         // sort the data
@@ -209,23 +351,134 @@ impl FskDemod {
         let cfo = median;
         let deviation = pos[pos.len() * 3 / 4] - median;
 
-        Ok((cfo, deviation))
+        // spread of each cluster (interquartile range)
+        let pos_iqr = pos[pos.len() * 3 / 4] - pos[pos.len() / 4];
+        let neg_iqr = neg[neg.len() * 3 / 4] - neg[neg.len() / 4];
+        let spread = ((pos_iqr + neg_iqr) / 2.0).max(f32::EPSILON);
+
+        let snr = deviation / spread;
+
+        Ok((cfo, deviation, snr))
+    }
+
+    // Fallback for bursts where one side of zero is (almost) empty
+    // CFO is estimated from the overall mean and the deviation is bounded by `max_freq_offset`
+    fn skewed_correction(&self, samples: Vec<f32>) -> anyhow::Result<(f32, f32, f32)> {
+        let cfo = samples.iter().sum::<f32>() / samples.len() as f32;
+
+        let mut spread = samples.iter().map(|d| (d - cfo).abs()).collect::<Vec<_>>();
+        spread.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let deviation = spread[spread.len() * 3 / 4].min(self.max_freq_offset);
+
+        // no modulation at all
+        if deviation <= f32::EPSILON {
+            anyhow::bail!("data is too skewed");
+        }
+
+        let iqr = (spread[spread.len() * 3 / 4] - spread[spread.len() / 4]).max(f32::EPSILON);
+        let snr = deviation / iqr;
+
+        Ok((cfo, deviation, snr))
+    }
+}
+
+// Sample `demod` (CFO corrected and normalized) once per symbol, starting at its first sample.
+// A Gardner detector compares each symbol pair with the sample between them and moves the
+// sampling instant towards the middle of the symbols, so clock drift over a long packet is tracked.
+fn recover_symbols(demod: &[f32], sps: f32) -> Vec<f32> {
+    if demod.is_empty() {
+        return Vec::new();
+    }
+
+    let at = |t: f32| interpolate(demod, t);
+
+    let last = (demod.len() - 1) as f32;
+    let mut symbols = Vec::with_capacity((demod.len() as f32 / sps.max(1.)) as usize + 1);
+
+    let mut t = 0.;
+    let mut prev = at(t);
+    symbols.push(prev);
+
+    while t + sps <= last {
+        let mid = at(t + sps / 2.);
+        let cur = at(t + sps);
+
+        // > 0: sampling late, < 0: early (0 without a transition)
+        let error = ((cur - prev) * mid).clamp(-1., 1.);
+
+        t = (t + sps - TIMING_GAIN * sps * error).min(last);
+        prev = at(t);
+        symbols.push(prev);
+    }
+
+    symbols
+}
+
+// Move `start` (where the squelch opened) to the first symbol of the preamble centered after it.
+// The centers are half a symbol off its first zero crossing, so e.g. a single sample of phase
+// jump at 2.5 samples per symbol does not start the sampling a symbol early.
+fn align_to_crossing(demod: &[f32], start: usize, sps: f32) -> usize {
+    // a whole number of samples per symbol starts on a symbol boundary already
+    if sps.fract() == 0. {
+        return start;
+    }
+
+    // the preamble alternates, so a crossing is within two symbols
+    let end = demod.len().min(start + (2. * sps).ceil() as usize + 1);
+    let Some(i) = (start..end.saturating_sub(1)).find(|&i| (demod[i] > 0.) != (demod[i + 1] > 0.))
+    else {
+        return start;
+    };
+
+    // fractional index of the crossing
+    let crossing = i as f32 + demod[i] / (demod[i] - demod[i + 1]);
+
+    // the last symbol before the crossing has its center here
+    let center = crossing - sps / 2.;
+    let center = center - ((center - start as f32) / sps).floor() * sps;
+
+    (center.floor() as usize).clamp(start, demod.len() - 1)
+}
+
+// Linear interpolation of `demod` at a fractional index
+fn interpolate(demod: &[f32], t: f32) -> f32 {
+    let i = t.floor() as usize;
+    let frac = t - i as f32;
+    let next = demod.get(i + 1).copied().unwrap_or(demod[i]);
+
+    demod[i] * (1. - frac) + next * frac
+}
+
+impl Demodulator for FskDemod {
+    fn demodulate(&mut self, burst: burst::Packet) -> anyhow::Result<Packet> {
+        FskDemod::demodulate(self, burst)
     }
 }
 
+/// Demodulate recorded bursts one by one on the calling thread (no SDR needed).
+/// See `bitops::fsk_corpus_to_packets` to decode the results.
+pub fn demodulate_corpus(
+    sample_rate: f32,
+    num_channels: usize,
+    bursts: impl IntoIterator<Item = Vec<Complex<f32>>>,
+) -> anyhow::Result<impl Iterator<Item = anyhow::Result<Packet>>> {
+    let mut demod = FskDemod::new(sample_rate, num_channels)?;
+
+    Ok(bursts
+        .into_iter()
+        .map(move |burst| demod.demodulate_signal(&burst)))
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct FskMod {
     #[doc(hidden)]
     freqmod: NonNull<freqmod_s>,
 
-    /// The number of samples per symbol
-    #[allow(unused)]
-    sample_per_symbol: u32,
-
-    /// The number of bits per symbol
+    /// The number of samples per symbol (may be fractional)
     #[allow(unused)]
-    bits_per_symbol: u32,
+    sample_per_symbol: f32,
 }
 
 impl Drop for FskMod {
@@ -245,18 +498,16 @@ impl FskMod {
     /// # Arguments
     /// * `sample_rate` [Hz] - The sample rate of the transmitted data
     /// * `num_channels` - The number of channels to use
-    pub fn new(sample_rate: f32, num_channels: u32) -> Self {
-        let freqmod =
-            liquid_get_pointer(|| unsafe { freqmod_create(0.8f32) }).expect("fskmod_create failed");
+    pub fn new(sample_rate: f32, num_channels: u32) -> anyhow::Result<Self> {
+        let freqmod = liquid_get_pointer(|| unsafe { freqmod_create(0.8f32) })
+            .context("fskmod_create failed")?;
 
-        let sample_per_symbol = (sample_rate / (num_channels as f32) / 1e6f32 * 2.0) as u32;
-        let bits_per_symbol = sample_per_symbol.trailing_zeros();
+        let sample_per_symbol = sample_rate / (num_channels as f32) / 1e6f32 * 2.0;
 
-        Self {
+        Ok(Self {
             freqmod,
             sample_per_symbol,
-            bits_per_symbol,
-        }
+        })
     }
 
     fn freqmod(&self) -> freqmod {
@@ -287,11 +538,18 @@ impl FskMod {
     }
 
     pub fn modulate(&mut self, data: &[u8]) -> anyhow::Result<Vec<num_complex::Complex<f32>>> {
-        let mut f = vec![];
-
-        f.extend(data.iter().flat_map(|b| {
-            (0..=self.bits_per_symbol).map(move |_| if b & 1 != 0 { -1.0 } else { 1.0 })
-        }));
+        // hold each bit for `sample_per_symbol` samples (on a fractional clock)
+        let len = (data.len() as f32 * self.sample_per_symbol).round() as usize;
+        let f = (0..len)
+            .map(|i| {
+                let b = data[((i as f32 / self.sample_per_symbol) as usize).min(data.len() - 1)];
+                if b & 1 != 0 {
+                    -1.0
+                } else {
+                    1.0
+                }
+            })
+            .collect::<Vec<_>>();
 
         self.liquid_modulate(&f)
     }
@@ -305,7 +563,7 @@ mod tests {
 
     #[test]
     fn test_simple_demod() {
-        let mut fsk = FskDemod::new(20e6, 20);
+        let mut fsk = FskDemod::new(20e6, 20).unwrap();
         let packet = fsk
             .demodulate_signal(&EXPECT_DATA_1_FREQ)
             .expect("demod failed");
@@ -349,13 +607,13 @@ mod tests {
 
     #[test]
     fn test_simple_modul() {
-        let mut modulater = FskMod::new(20e6, 20);
+        let mut modulater = FskMod::new(20e6, 20).unwrap();
         let packet = EXPECT_DATA_1_BITS.to_vec();
 
         let modulated = modulater.modulate(&packet).expect("modul failed");
         println!("{:?}", modulated);
 
-        let mut demodulater = FskDemod::new(20e6, 20);
+        let mut demodulater = FskDemod::new(20e6, 20).unwrap();
         let demodulated = demodulater
             .demodulate_signal(&modulated)
             .expect("demod failed");
@@ -363,6 +621,334 @@ mod tests {
         assert_eq!(packet, demodulated.bits);
     }
 
+    #[test]
+    fn snr_clean_vs_noisy() {
+        use rand::prelude::*;
+
+        let mut modulater = FskMod::new(20e6, 20).unwrap();
+        let modulated = modulater
+            .modulate(&EXPECT_DATA_1_BITS)
+            .expect("modul failed");
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let noisy = modulated
+            .iter()
+            .map(|s| s + Complex::new(rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1)))
+            .collect::<Vec<_>>();
+
+        let mut demodulater = FskDemod::new(20e6, 20).unwrap();
+        let clean = demodulater
+            .demodulate_signal(&modulated)
+            .expect("demod failed");
+        let noisy = demodulater.demodulate_signal(&noisy).expect("demod failed");
+
+        assert!(clean.snr > noisy.snr);
+    }
+
+    #[test]
+    fn symbol_start_after_gap() {
+        let mut modulater = FskMod::new(20e6, 20).unwrap();
+        let modulated = modulater
+            .modulate(&EXPECT_DATA_1_BITS)
+            .expect("modul failed");
+
+        let gap = 5;
+        let gapped = std::iter::repeat(Complex::new(0.0, 0.0))
+            .take(gap)
+            .chain(modulated.iter().copied())
+            .collect::<Vec<_>>();
+
+        let mut demodulater = FskDemod::new(20e6, 20).unwrap();
+        let clean = demodulater
+            .demodulate_signal(&modulated)
+            .expect("demod failed");
+        let shifted = demodulater
+            .demodulate_signal(&gapped)
+            .expect("demod failed");
+
+        assert_eq!(shifted.symbol_start, clean.symbol_start + gap);
+        assert_eq!(shifted.sample_per_symbol, clean.sample_per_symbol);
+        assert_eq!(shifted.bits, clean.bits);
+    }
+
+    #[test]
+    fn short_burst() {
+        let bits = [0u8, 1, 0, 1, 0, 1, 1, 0, 0, 1]
+            .into_iter()
+            .cycle()
+            .take(40)
+            .collect::<Vec<_>>();
+
+        let mut modulater = FskMod::new(20e6, 20).unwrap();
+        let modulated = modulater.modulate(&bits).expect("modul failed");
+
+        let mut fsk = FskDemod::new(20e6, 20).unwrap();
+        assert!(fsk.demodulate_signal(&modulated).is_err());
+
+        let params = FskParams {
+            need_symbol: 16,
+            ..Default::default()
+        };
+        let mut fsk = FskDemod::with_params(20e6, 20, params).unwrap();
+        let demodulated = fsk.demodulate_signal(&modulated).expect("demod failed");
+
+        assert_eq!(demodulated.bits, bits);
+    }
+
+    // resample `signal` `ratio` times slower (a TX clock slightly off), interpolating the phase
+    fn stretch(signal: &[Complex<f32>], ratio: f32) -> Vec<Complex<f32>> {
+        let mut phase = vec![signal[0].arg()];
+        for w in signal.windows(2) {
+            let last = *phase.last().unwrap();
+            phase.push(last + (w[1] * w[0].conj()).arg());
+        }
+
+        (0..)
+            .map(|i| i as f32 / ratio)
+            .take_while(|&t| t < (signal.len() - 1) as f32)
+            .map(|t| {
+                let i = t as usize;
+                let frac = t - i as f32;
+                Complex::from_polar(1.0, phase[i] * (1. - frac) + phase[i + 1] * frac)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn silence_threshold() {
+        let bits =
+            crate::bitops::packet_to_bits(&[0x42; 16], 2426, crate::bluetooth::ADV_ACCESS_ADDRESS);
+
+        let mut modulater = FskMod::new(20e6, 20).unwrap();
+        let modulated = modulater.modulate(&bits).expect("modul failed");
+
+        // the preamble (8 bits) rises slowly: its deviation is only a third
+        let mut phase = modulated[0].arg();
+        let mut signal = vec![modulated[0]];
+        for (i, w) in modulated.windows(2).enumerate() {
+            let step = (w[1] * w[0].conj()).arg();
+            phase += if i < 8 * 2 { step * 0.3 } else { step };
+            signal.push(Complex::from_polar(1.0, phase));
+        }
+
+        let decode = |params: FskParams| {
+            let mut fsk = FskDemod::with_params(20e6, 20, params).unwrap();
+            let demodulated = fsk.demodulate_signal(&signal).expect("demod failed");
+            crate::bitops::bits_to_packet(&demodulated.bits, 2426)
+        };
+
+        // the default squelch skips the weak preamble
+        assert!(decode(FskParams::default()).is_err());
+
+        let packet = decode(FskParams {
+            silence_threshold: 0.2,
+            ..Default::default()
+        })
+        .expect("decode failed");
+        assert_eq!(packet.aa, crate::bluetooth::ADV_ACCESS_ADDRESS);
+    }
+
+    #[test]
+    fn fractional_sample_per_symbol() {
+        let bits =
+            crate::bitops::packet_to_bits(&[0x5a; 24], 2426, crate::bluetooth::ADV_ACCESS_ADDRESS);
+
+        let mut modulater = FskMod::new(16e6, 16).unwrap();
+        let modulated = modulater.modulate(&bits).expect("modul failed");
+
+        // 20 MHz over 16 channels: 2.5 samples per symbol
+        let signal = stretch(&modulated, 1.25);
+
+        let decode = |sample_per_symbol: Option<f32>, timing_recovery: bool| {
+            let mut fsk = FskDemod::with_params(
+                20e6,
+                16,
+                FskParams {
+                    timing_recovery,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(fsk.sample_per_symbol, 2.5);
+
+            if let Some(sample_per_symbol) = sample_per_symbol {
+                fsk.sample_per_symbol = sample_per_symbol;
+            }
+
+            let demodulated = fsk.demodulate_signal(&signal).expect("demod failed");
+            crate::bitops::bits_to_packet(&demodulated.bits, 2426)
+        };
+
+        // the truncated step drifts off by half a symbol every symbol
+        assert!(decode(Some(2.), false).is_err());
+
+        for timing_recovery in [false, true] {
+            let packet = decode(None, timing_recovery).expect("decode failed");
+            assert_eq!(packet.aa, crate::bluetooth::ADV_ACCESS_ADDRESS);
+            assert_eq!(packet.bytes[6..30], [0x5a; 24]);
+        }
+    }
+
+    #[test]
+    fn align_to_crossing_at_fractional_sps() {
+        // a channelized burst at 2.5 samples per symbol: a sample of phase jump (-1.56) opens
+        // the squelch, the preamble (0, 1, 0, 1, ...) starts two samples later
+        let demod = [
+            0.0f32, -1.56, -0.35, -0.66, -0.43, 0.32, 0.53, -0.18, -1.14, -0.39, 0.33, 0.53,
+        ];
+        assert_eq!(align_to_crossing(&demod, 1, 2.5), 3);
+
+        // the squelch opens in the first symbol of a clean burst
+        let demod = [0.0f32, -1., -1., 1., 1., -1., -1., -1., 1., 1.];
+        assert_eq!(align_to_crossing(&demod, 1, 2.5), 1);
+
+        // a whole number of samples per symbol is left alone
+        assert_eq!(align_to_crossing(&demod, 1, 2.), 1);
+    }
+
+    #[test]
+    fn constant_tone() {
+        assert!(recover_symbols(&[], 2.).is_empty());
+
+        let tone = (0..400)
+            .map(|i| Complex::from_polar(1.0, 0.3 * i as f32))
+            .collect::<Vec<_>>();
+
+        for timing_recovery in [false, true] {
+            let mut fsk = FskDemod::with_params(
+                20e6,
+                20,
+                FskParams {
+                    timing_recovery,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            assert!(fsk.demodulate_signal(&tone).is_err());
+        }
+    }
+
+    #[test]
+    fn silence_params_checked() {
+        let with = |silence_alpha: f32, silence_threshold: f32| {
+            FskDemod::with_params(
+                20e6,
+                20,
+                FskParams {
+                    silence_alpha,
+                    silence_threshold,
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(with(1.0, 0.2).is_ok());
+        assert!(with(0.0, 0.5).is_err());
+        assert!(with(1.5, 0.5).is_err());
+        assert!(with(f32::NAN, 0.5).is_err());
+        assert!(with(0.8, 0.0).is_err());
+        assert!(with(0.8, 2.0).is_err());
+    }
+
+    #[test]
+    fn need_symbol_checked() {
+        let with = |need_symbol: usize| {
+            FskDemod::with_params(
+                20e6,
+                20,
+                FskParams {
+                    need_symbol,
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(with(4).is_ok());
+        assert!(with(3).is_err());
+        assert!(with(0).is_err());
+    }
+
+    #[test]
+    fn timing_recovery_on_drift() {
+        use rand::prelude::*;
+
+        let errors = |timing_recovery: bool| {
+            let mut rng = SmallRng::seed_from_u64(0);
+            let mut modulater = FskMod::new(16e6, 16).unwrap();
+            let mut demodulater = FskDemod::with_params(
+                16e6,
+                16,
+                FskParams {
+                    timing_recovery,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            (0..10)
+                .map(|_| {
+                    // a long advertisement
+                    let payload = (0..6 + 200).map(|_| rng.gen()).collect::<Vec<u8>>();
+                    let bits = crate::bitops::packet_to_bits(
+                        &payload,
+                        2426,
+                        crate::bluetooth::ADV_ACCESS_ADDRESS,
+                    );
+
+                    let modulated = modulater.modulate(&bits).expect("modul failed");
+                    let mut signal = stretch(&modulated, 1.002);
+                    crate::test_support::awgn(&mut rng, &mut signal, 14.);
+
+                    let demodulated = demodulater
+                        .demodulate_signal(&signal)
+                        .expect("demod failed");
+
+                    bits.iter()
+                        .zip(&demodulated.bits)
+                        .filter(|(a, b)| a != b)
+                        .count()
+                })
+                .sum::<usize>()
+        };
+
+        let fixed = errors(false);
+        let recovered = errors(true);
+
+        assert!(
+            recovered * 10 < fixed,
+            "fixed {} recovered {}",
+            fixed,
+            recovered
+        );
+    }
+
+    #[test]
+    fn skewed_correction() {
+        let fsk = FskDemod::new(20e6, 20).unwrap();
+
+        // every sample is positive: cfo = 0.15, deviation = 0.1
+        let demod = [0.25f32, 0.25, 0.05, 0.05]
+            .into_iter()
+            .cycle()
+            .take(8 + fsk.median_size())
+            .collect::<Vec<_>>();
+
+        let (cfo, deviation, _snr) = fsk.correction(&demod).expect("correction failed");
+
+        assert!((cfo - 0.15).abs() < 1e-3);
+        assert!((deviation - 0.1).abs() < 1e-3);
+
+        // an all-positive preamble with the smallest window takes the fallback too
+        let params = FskParams {
+            need_symbol: 4,
+            ..Default::default()
+        };
+        let fsk = FskDemod::with_params(20e6, 20, params).unwrap();
+        let demod = vec![0.2f32; 8 + fsk.median_size()];
+        assert!(fsk.correction(&demod).is_err());
+    }
+
     #[test]
     fn compatible_data_test() {
         use std::io::BufRead;
@@ -406,7 +992,7 @@ mod tests {
 
         println!("loaded {:?}", data.len());
 
-        let mut fsk = FskDemod::new(20e6, 20);
+        let mut fsk = FskDemod::new(20e6, 20).unwrap();
 
         for d in data.iter() {
             let demod = fsk.demodulate_signal(&d.0);
@@ -431,4 +1017,34 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn corpus() {
+        let mut modulater = FskMod::new(20e6, 20).unwrap();
+
+        let packets = (0..3u8)
+            .map(|i| {
+                crate::bitops::packet_to_bits(&[i; 8], 2426, crate::bluetooth::ADV_ACCESS_ADDRESS)
+            })
+            .collect::<Vec<_>>();
+
+        let mut bursts = packets
+            .iter()
+            .map(|bits| modulater.modulate(bits).expect("modul failed"))
+            .collect::<Vec<_>>();
+        // too short to demodulate
+        bursts.insert(1, vec![Complex::new(1.0, 0.0); 10]);
+
+        let results = demodulate_corpus(20e6, 20, bursts)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 4);
+        assert!(results[1].is_err());
+
+        let decoded = crate::bitops::fsk_corpus_to_packets(results, 2426, 0, 0)
+            .filter_map(|p| p.ok())
+            .map(|p| p.bytes[6..14].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, vec![vec![0; 8], vec![1; 8], vec![2; 8]]);
+    }
 }
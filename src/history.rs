@@ -0,0 +1,264 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+
+use crate::bluetooth::{Bluetooth, MacAddress};
+use crate::rssi::{RssiBucket, WindowedRssi};
+
+/// Packets kept per address by default
+pub const DEFAULT_CAPACITY: usize = 500;
+
+/// Recent packets of one address, with totals over every packet seen
+#[derive(Debug, Clone, Default)]
+pub struct AddressHistory {
+    packets: VecDeque<Bluetooth>,
+    // receptions of each kept packet (see `PacketHistory::push_collapsed`)
+    copies: VecDeque<usize>,
+
+    // including the evicted packets
+    total: usize,
+    rssi_sum: f32,
+    rssi_count: usize,
+    recent_rssi: WindowedRssi,
+}
+
+impl AddressHistory {
+    fn push(&mut self, packet: Bluetooth, capacity: usize, collapse: bool) {
+        self.total += 1;
+
+        if let Some(ref byte_packet) = packet.bytes_packet {
+            if let Some(rssi) = byte_packet.rssi_average {
+                self.rssi_sum += rssi;
+                self.rssi_count += 1;
+
+                if let Some(timestamp) = byte_packet.timestamp {
+                    self.recent_rssi.push(timestamp, rssi);
+                }
+            }
+        }
+
+        if collapse {
+            if let (Some(last), Some(copies)) = (self.packets.back(), self.copies.back_mut()) {
+                if last.content_id() == packet.content_id() {
+                    *copies += 1;
+                    return;
+                }
+            }
+        }
+
+        if self.packets.len() == capacity {
+            self.packets.pop_front();
+            self.copies.pop_front();
+        }
+        self.packets.push_back(packet);
+        self.copies.push_back(1);
+    }
+
+    /// Number of kept packets
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Number of packets seen, including the evicted ones
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Mean RSSI of every packet seen (None if no packet had one)
+    pub fn mean_rssi(&self) -> Option<f32> {
+        (self.rssi_count > 0).then(|| self.rssi_sum / self.rssi_count as f32)
+    }
+
+    /// Mean RSSI over the last `rssi::DEFAULT_WINDOW` before `now`
+    pub fn recent_rssi(&self, now: DateTime<Utc>) -> Option<f32> {
+        self.recent_rssi.mean(now)
+    }
+
+    /// (timestamp, RSSI) of the kept packets which have both, oldest first
+    pub fn rssi_samples(&self) -> impl Iterator<Item = (DateTime<Utc>, f32)> + '_ {
+        self.packets.iter().filter_map(|p| {
+            let byte_packet = p.bytes_packet.as_ref()?;
+            Some((byte_packet.timestamp?, byte_packet.rssi_average?))
+        })
+    }
+
+    /// RSSI of the kept packets in `width` buckets from the oldest one up to `now`
+    pub fn rssi_buckets(&self, width: std::time::Duration, now: DateTime<Utc>) -> Vec<RssiBucket> {
+        match self.rssi_samples().next() {
+            Some((start, _)) => crate::rssi::buckets(self.rssi_samples(), start, now, width),
+            None => Vec::new(),
+        }
+    }
+
+    /// Oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Bluetooth> {
+        self.packets.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Bluetooth> {
+        self.packets.get(index)
+    }
+
+    /// Number of times the packet at `index` was received (1 unless collapsed)
+    pub fn copies(&self, index: usize) -> usize {
+        self.copies.get(index).copied().unwrap_or(0)
+    }
+
+    pub fn first(&self) -> Option<&Bluetooth> {
+        self.packets.front()
+    }
+
+    pub fn last(&self) -> Option<&Bluetooth> {
+        self.packets.back()
+    }
+}
+
+/// Bounded packet history per address (`None` for packets without one)
+#[derive(Debug, Clone)]
+pub struct PacketHistory {
+    capacity: usize,
+    entries: HashMap<Option<MacAddress>, AddressHistory>,
+}
+
+impl Default for PacketHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl PacketHistory {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+
+        Self {
+            capacity,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Append `packet`, evicting the oldest one of `address` at capacity.
+    /// Returns true if `address` was not seen before.
+    pub fn push(&mut self, address: Option<MacAddress>, packet: Bluetooth) -> bool {
+        let is_new = !self.entries.contains_key(&address);
+
+        self.entries
+            .entry(address)
+            .or_default()
+            .push(packet, self.capacity, false);
+
+        is_new
+    }
+
+    /// Same as `push`, but a packet with the `content_id` of the newest one of `address`
+    /// (e.g. a retransmission on another primary channel) is only counted
+    pub fn push_collapsed(&mut self, address: Option<MacAddress>, packet: Bluetooth) -> bool {
+        let is_new = !self.entries.contains_key(&address);
+
+        self.entries
+            .entry(address)
+            .or_default()
+            .push(packet, self.capacity, true);
+
+        is_new
+    }
+
+    pub fn get(&self, address: &Option<MacAddress>) -> Option<&AddressHistory> {
+        self.entries.get(address)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::packets;
+
+    fn packet(freq: usize, rssi: f32) -> Bluetooth {
+        let byte_packet = crate::bitops::BytePacket {
+            freq,
+            rssi_average: Some(rssi),
+            ..packets::byte_packet(packets::adv_bytes(&packets::ADV_PDU))
+        };
+
+        Bluetooth::from_bytes(byte_packet, freq).unwrap()
+    }
+
+    #[test]
+    fn keeps_newest_at_capacity() {
+        let address: Option<MacAddress> = Some("12:34:56:00:01:00".parse().unwrap());
+        let mut history = PacketHistory::new(3);
+
+        assert!(history.push(address.clone(), packet(2402, -10.)));
+        for i in 1..5 {
+            assert!(!history.push(address.clone(), packet(2402 + i * 2, -10. - i as f32)));
+        }
+
+        let entry = history.get(&address).unwrap();
+        assert_eq!(entry.len(), 3);
+        assert_eq!(entry.total(), 5);
+        assert_eq!(
+            entry.iter().map(|p| p.freq).collect::<Vec<_>>(),
+            vec![2406, 2408, 2410]
+        );
+        assert_eq!(entry.last().unwrap().freq, 2410);
+
+        // over all 5 packets, not only the kept ones
+        assert_eq!(entry.mean_rssi(), Some(-12.));
+
+        assert!(history.get(&None).is_none());
+    }
+
+    #[test]
+    fn collapse_retransmissions() {
+        let mut history = PacketHistory::default();
+
+        for freq in [2402, 2426, 2480] {
+            history.push_collapsed(None, packet(freq, -10.));
+        }
+        history.push(None, packet(2402, -10.));
+
+        let mut other = packet(2426, -20.);
+        other.bytes_packet.as_mut().unwrap().bytes[6] = 0x02;
+        history.push_collapsed(None, other);
+
+        let entry = history.get(&None).unwrap();
+        assert_eq!(entry.len(), 3);
+        assert_eq!(entry.total(), 5);
+        assert_eq!(
+            (0..3).map(|i| entry.copies(i)).collect::<Vec<_>>(),
+            vec![3, 1, 1]
+        );
+        assert_eq!(entry.mean_rssi(), Some(-12.));
+    }
+
+    #[test]
+    fn rssi_over_time() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut history = PacketHistory::default();
+
+        for (secs, rssi) in [(0, -30.), (1, -20.), (7, -10.), (8, -6.)] {
+            let mut p = packet(2402, rssi);
+            p.bytes_packet.as_mut().unwrap().timestamp =
+                Some(start + chrono::TimeDelta::seconds(secs));
+            history.push(None, p);
+        }
+
+        let entry = history.get(&None).unwrap();
+        let now = start + chrono::TimeDelta::seconds(9);
+
+        assert_eq!(entry.mean_rssi(), Some(-16.5));
+        assert_eq!(entry.recent_rssi(now), Some(-8.));
+
+        let buckets = entry.rssi_buckets(std::time::Duration::from_secs(5), now);
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].mean, Some(-25.));
+        assert_eq!(buckets[1].count, 2);
+    }
+}
@@ -1,8 +1,16 @@
 pub mod bitops;
 pub mod bluetooth;
+pub mod btsnoop;
 pub mod burst;
 pub mod channelizer;
+pub mod connection;
 pub mod device;
 pub mod fsk;
+pub mod history;
 pub mod liquid;
+pub mod pcap;
+pub mod rssi;
 pub mod stream;
+
+#[cfg(test)]
+mod test_support;
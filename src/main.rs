@@ -4,7 +4,6 @@ use clap::Parser;
 
 use anyhow::Context;
 
-use stream::ProcessFailKind;
 #[allow(unused_imports)] // use with permission use thread_priority::{set_current_thread_priority, ThreadPriority};
 #[derive(Parser, Debug)]
 #[command(
@@ -15,6 +14,47 @@ use stream::ProcessFailKind;
 pub(crate) struct Args {
     #[arg(short, long)]
     path: String,
+
+    /// Open the configured devices, print the applied settings and exit
+    #[arg(long)]
+    check: bool,
+
+    /// Receive for SECONDS on each device, print the activity per BLE channel and exit
+    #[arg(long, value_name = "SECONDS")]
+    scan: Option<f64>,
+}
+
+// print each device with the settings read back from it
+fn check(configs: &[String], devices: &[device::Device]) -> anyhow::Result<()> {
+    for (config, dev) in configs.iter().zip(devices) {
+        println!("{}", config);
+        println!("  {}", dev.config);
+
+        for applied in dev.config.read_back(&dev.raw)? {
+            let mismatches = applied.mismatches(&dev.config);
+
+            println!(
+                "  {:?} channel {}: antenna={:?} freq={} sample_rate={} gain={} {}",
+                applied.direction,
+                applied.channel,
+                applied.antenna,
+                applied.center_freq,
+                applied.sample_rate,
+                applied.gain,
+                if mismatches.is_empty() {
+                    "ok"
+                } else {
+                    "MISMATCH"
+                }
+            );
+
+            for m in mismatches {
+                log::warn!("{}: {}", dev.config.driver, m);
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[log_derive::logfn(ok = "TRACE", err = "ERROR")]
@@ -29,7 +69,27 @@ fn main() -> anyhow::Result<()> {
     let config: device::config::List =
         serde_yaml::from_reader(file).context("failed to parse config")?;
 
+    let configs = config
+        .devices
+        .iter()
+        .map(|d| format!("{:?}", d))
+        .collect::<Vec<_>>();
+
     let mut streams = device::open_device(config)?;
+
+    if args.check {
+        return check(&configs, &streams);
+    }
+
+    if let Some(seconds) = args.scan {
+        for (config, dev) in configs.iter().zip(streams.iter_mut()) {
+            println!("{}", config);
+            print!("{}", dev.scan(std::time::Duration::from_secs_f64(seconds))?);
+        }
+
+        return Ok(());
+    }
+
     println!("streams: {:?}", streams.len());
 
     let mut stop_signals = vec![];
@@ -49,8 +109,8 @@ fn main() -> anyhow::Result<()> {
         let mut hackrf_rx = streams.remove(0);
         println!("hackrf_rx: {:?}", hackrf_rx.config);
 
-        let mut demod_counter = 0;
-        for r in hackrf_rx.start_rx_with_error()? {
+        let mut rx = hackrf_rx.start_rx_with_error()?;
+        for r in rx.by_ref() {
             use stream::StreamResult;
 
             match r {
@@ -66,32 +126,45 @@ fn main() -> anyhow::Result<()> {
                         //         address: [0xfb, 0x81, 0x00, 0xd4, 0x09, 0x18],
                         //     })
                         {
-                            log::info!(
-                                "rssi = {}",
-                                p.bytes_packet
-                                    .unwrap()
-                                    .raw
-                                    .unwrap()
-                                    .raw
-                                    .unwrap()
-                                    .rssi_average
-                            );
+                            log::info!("rssi = {}", p.bytes_packet.unwrap().rssi_average.unwrap());
                             log::info!("{}", adv);
                         }
                     }
                 }
+                // the channel was restarted
+                StreamResult::Error(e @ stream::StreamError::CatcherPanic { .. }) => {
+                    log::error!("Error: {}", e);
+                }
                 StreamResult::Error(e) => {
                     log::error!("Error: {}", e);
                     break;
                 }
-                StreamResult::ProcessFail(ProcessFailKind::Demod(_)) => {
-                    demod_counter += 1;
-                }
                 StreamResult::ProcessFail(_kind) => {}
             }
         }
 
-        println!("done, demod_counter = {}", demod_counter);
+        let stats = rx.stats();
+        println!("done, stats = {:?}", stats);
+        if let Some(summary) = stats.summary() {
+            println!("{}", summary);
+        }
+
+        let mut occupancy = rx.occupancy().into_iter().collect::<Vec<_>>();
+        occupancy.sort_by_key(|(ch, _)| ch.to_freq());
+
+        let max_bursts = occupancy.iter().map(|(_, o)| o.bursts).max().unwrap_or(0);
+        for (ch, o) in occupancy {
+            let bar = "#".repeat(o.bursts * 40 / max_bursts.max(1));
+            println!(
+                "{} MHz {:6} bursts {:4} packets rssi={:6.1} {}",
+                ch.to_freq(),
+                o.bursts,
+                o.packets,
+                o.mean_rssi().unwrap_or(f32::NAN),
+                bar
+            );
+        }
+
         *hackrf_rx.running.lock().unwrap() = false;
     } else {
         #[allow(unused_mut)]
@@ -175,8 +248,8 @@ fn main() -> anyhow::Result<()> {
             log::warn!("tx done");
         });
 
-        let mut demod_counter = 0;
-        for r in hackrf_rx.start_rx_with_error()? {
+        let mut rx = hackrf_rx.start_rx_with_error()?;
+        for r in rx.by_ref() {
             use stream::StreamResult;
 
             let finding_mac = [bluetooth::MacAddress {
@@ -190,33 +263,22 @@ fn main() -> anyhow::Result<()> {
                         let mac = &adv.address;
 
                         if finding_mac.contains(mac) {
-                            log::info!(
-                                "rssi = {}",
-                                p.bytes_packet
-                                    .unwrap()
-                                    .raw
-                                    .unwrap()
-                                    .raw
-                                    .unwrap()
-                                    .rssi_average
-                            );
+                            log::info!("rssi = {}", p.bytes_packet.unwrap().rssi_average.unwrap());
                             log::info!("{}", adv);
                         }
                     }
                 }
-                StreamResult::Error(e) => {
-                    if e.to_string().contains("Interrupted") {
-                        break;
-                    }
-                }
-                StreamResult::ProcessFail(ProcessFailKind::Demod(_)) => {
-                    demod_counter += 1;
-                }
+                StreamResult::Error(stream::StreamError::Interrupted) => break,
+                StreamResult::Error(_e) => {}
                 StreamResult::ProcessFail(_kind) => {}
             }
         }
 
-        println!("done, demod_counter = {}", demod_counter);
+        let stats = rx.stats();
+        println!("done, stats = {:?}", stats);
+        if let Some(summary) = stats.summary() {
+            println!("{}", summary);
+        }
         *hackrf_rx.running.lock().unwrap() = false;
     }
 
@@ -0,0 +1,157 @@
+use std::io::Write;
+
+use anyhow::Context;
+
+/// LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR
+pub const LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR: u32 = 256;
+
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const SNAPLEN: u32 = 65535;
+
+// flags of the LE LL pseudo header
+const FLAG_DEWHITENED: u16 = 0x0001;
+const FLAG_SIGNAL_POWER_VALID: u16 = 0x0002;
+const FLAG_REF_AA_VALID: u16 = 0x0010;
+const FLAG_CRC_CHECKED: u16 = 0x0400;
+const FLAG_CRC_VALID: u16 = 0x0800;
+
+pub struct PcapWriter {
+    out: std::io::BufWriter<std::fs::File>,
+}
+
+impl PcapWriter {
+    pub fn new(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(path.as_ref())
+            .with_context(|| format!("failed to create {}", path.as_ref().display()))?;
+
+        let mut out = std::io::BufWriter::new(file);
+
+        out.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?; // version major
+        out.write_all(&4u16.to_le_bytes())?; // version minor
+        out.write_all(&0i32.to_le_bytes())?; // thiszone
+        out.write_all(&0u32.to_le_bytes())?; // sigfigs
+        out.write_all(&SNAPLEN.to_le_bytes())?;
+        out.write_all(&LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR.to_le_bytes())?;
+
+        Ok(Self { out })
+    }
+
+    /// Write the packet as access address + PDU + CRC with the LE LL pseudo header
+    pub fn write_packet(&mut self, pkt: &crate::bluetooth::Bluetooth) -> anyhow::Result<()> {
+        let byte_packet = pkt
+            .bytes_packet
+            .as_ref()
+            .context("packet has no bytes_packet")?;
+
+        let rf_channel = (pkt.freq.saturating_sub(2402) / 2) as u8;
+
+        let mut flags = FLAG_DEWHITENED | FLAG_REF_AA_VALID | FLAG_CRC_CHECKED;
+        if pkt.packet.crc_valid {
+            flags |= FLAG_CRC_VALID;
+        }
+
+        let signal_power = match byte_packet.rssi_average {
+            Some(rssi) => {
+                flags |= FLAG_SIGNAL_POWER_VALID;
+                rssi.round().clamp(i8::MIN as f32, i8::MAX as f32) as i8
+            }
+            None => 0,
+        };
+
+        let mut record = Vec::with_capacity(10 + byte_packet.bytes.len() + 3);
+        record.push(rf_channel);
+        record.push(signal_power as u8);
+        record.push(0); // noise power
+        record.push(0); // access address offenses
+        record.extend_from_slice(&byte_packet.aa.to_le_bytes());
+        record.extend_from_slice(&flags.to_le_bytes());
+
+        record.extend_from_slice(&byte_packet.bytes);
+        record.extend_from_slice(&pkt.packet.crc);
+
+        let timestamp = byte_packet.timestamp.unwrap_or_else(chrono::Utc::now);
+
+        self.out
+            .write_all(&(timestamp.timestamp() as u32).to_le_bytes())?;
+        self.out
+            .write_all(&timestamp.timestamp_subsec_micros().to_le_bytes())?;
+        self.out.write_all(&(record.len() as u32).to_le_bytes())?; // incl_len
+        self.out.write_all(&(record.len() as u32).to_le_bytes())?; // orig_len
+        self.out.write_all(&record)?;
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adv_packet(payload: &[u8]) -> crate::bluetooth::Bluetooth {
+        let mut pdu = vec![0x00, payload.len() as u8];
+        pdu.extend_from_slice(payload);
+
+        let byte_packet = crate::bitops::BytePacket {
+            timestamp: Some(chrono::DateTime::from_timestamp(1_700_000_000, 123_000).unwrap()),
+            rssi_average: Some(-42.4),
+            ..crate::test_support::packets::byte_packet(crate::test_support::packets::adv_bytes(
+                &pdu,
+            ))
+        };
+
+        crate::bluetooth::Bluetooth::from_bytes(byte_packet, 2426).unwrap()
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let path = std::env::temp_dir().join(format!("rfraptor-{}.pcap", std::process::id()));
+
+        let packets = [
+            adv_packet(&[0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x02, 0x01, 0x06]),
+            adv_packet(&[0x12, 0x34, 0x56, 0x00, 0x01, 0x00]),
+        ];
+
+        let mut writer = PcapWriter::new(&path).unwrap();
+        for p in &packets {
+            writer.write_packet(p).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+
+        assert_eq!(u32_at(0), PCAP_MAGIC);
+        assert_eq!(u32_at(20), LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR);
+
+        let mut pos = 24;
+        for p in &packets {
+            // pseudo header + access address and PDU + CRC
+            let expected = 10 + p.bytes_packet.as_ref().unwrap().bytes.len() + 3;
+
+            assert_eq!(u32_at(pos), 1_700_000_000);
+            assert_eq!(u32_at(pos + 4), 123);
+            assert_eq!(u32_at(pos + 8) as usize, expected);
+            assert_eq!(u32_at(pos + 12) as usize, expected);
+
+            let record = &data[pos + 16..][..expected];
+            assert_eq!(record[0], 12); // 2426 MHz
+            assert_eq!(record[1] as i8, -42);
+
+            let flags = u16::from_le_bytes([record[8], record[9]]);
+            assert_ne!(flags & FLAG_CRC_VALID, 0);
+
+            pos += 16 + expected;
+        }
+
+        assert_eq!(pos, data.len());
+    }
+}
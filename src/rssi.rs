@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// Window of `WindowedRssi::default`
+pub const DEFAULT_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Mean raw RSSI over the last `window`
+#[derive(Debug, Clone)]
+pub struct WindowedRssi {
+    window: TimeDelta,
+    samples: VecDeque<(DateTime<Utc>, f32)>,
+}
+
+impl Default for WindowedRssi {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl WindowedRssi {
+    pub fn new(window: std::time::Duration) -> Self {
+        Self {
+            window: TimeDelta::from_std(window).unwrap_or(TimeDelta::MAX),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Add a sample, dropping the ones older than `window` before the newest
+    pub fn push(&mut self, timestamp: DateTime<Utc>, rssi: f32) {
+        let pos = self.samples.partition_point(|(t, _)| *t <= timestamp);
+        self.samples.insert(pos, (timestamp, rssi));
+
+        let newest = self.samples.back().unwrap().0;
+        while let Some(&(t, _)) = self.samples.front() {
+            if newest.signed_duration_since(t) < self.window {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    /// Mean of the samples in (`now` - window, `now`], None if there is none
+    pub fn mean(&self, now: DateTime<Utc>) -> Option<f32> {
+        let (sum, count) = self
+            .samples
+            .iter()
+            .filter(|(t, _)| *t <= now && now.signed_duration_since(*t) < self.window)
+            .fold((0., 0), |(sum, count), (_, rssi)| (sum + rssi, count + 1));
+
+        (count > 0).then(|| sum / count as f32)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RssiBucket {
+    pub count: usize,
+    /// None for an empty bucket
+    pub mean: Option<f32>,
+}
+
+/// Split `samples` into `width` buckets from `start` up to the one containing `now`.
+/// Samples outside [`start`, `now`] are ignored.
+pub fn buckets(
+    samples: impl IntoIterator<Item = (DateTime<Utc>, f32)>,
+    start: DateTime<Utc>,
+    now: DateTime<Utc>,
+    width: std::time::Duration,
+) -> Vec<RssiBucket> {
+    let width = (width.as_millis() as i64).max(1);
+    let index = |t: DateTime<Utc>| {
+        t.signed_duration_since(start)
+            .num_milliseconds()
+            .div_euclid(width)
+    };
+
+    if now < start {
+        return Vec::new();
+    }
+
+    let mut sums = vec![(0., 0); index(now) as usize + 1];
+    for (t, rssi) in samples {
+        if t < start || t > now {
+            continue;
+        }
+
+        let (sum, count) = &mut sums[index(t) as usize];
+        *sum += rssi;
+        *count += 1;
+    }
+
+    sums.into_iter()
+        .map(|(sum, count)| RssiBucket {
+            count,
+            mean: (count > 0).then(|| sum / count as f32),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(1_700_000_000_000 + ms).unwrap()
+    }
+
+    #[test]
+    fn windowed_mean() {
+        let mut rssi = WindowedRssi::new(std::time::Duration::from_secs(2));
+        assert_eq!(rssi.mean(at(0)), None);
+
+        rssi.push(at(0), -10.);
+        rssi.push(at(1000), -20.);
+        rssi.push(at(500), -30.);
+        assert_eq!(rssi.mean(at(1000)), Some(-20.));
+
+        // at(0) falls out of the window
+        rssi.push(at(2000), -40.);
+        assert_eq!(rssi.len(), 3);
+        assert_eq!(rssi.mean(at(2000)), Some(-30.));
+
+        // only at(2000) is left by now
+        assert_eq!(rssi.mean(at(3500)), Some(-40.));
+        assert_eq!(rssi.mean(at(4000)), None);
+    }
+
+    #[test]
+    fn bucketize() {
+        let samples = [
+            (at(-100), 0.),
+            (at(0), -10.),
+            (at(400), -20.),
+            (at(2100), -30.),
+            (at(5000), 0.),
+        ];
+
+        let buckets = buckets(samples, at(0), at(3000), std::time::Duration::from_secs(1));
+
+        assert_eq!(
+            buckets,
+            vec![
+                RssiBucket {
+                    count: 2,
+                    mean: Some(-15.)
+                },
+                RssiBucket {
+                    count: 0,
+                    mean: None
+                },
+                RssiBucket {
+                    count: 1,
+                    mean: Some(-30.)
+                },
+                RssiBucket {
+                    count: 0,
+                    mean: None
+                },
+            ]
+        );
+    }
+}
@@ -7,29 +7,148 @@ pub struct BluetoothChannel {
 }
 
 impl BluetoothChannel {
-    fn from_freq(freq: u32) -> Self {
-        BluetoothChannel {
-            blch: (freq - 2402) / 2,
+    /// None unless `freq` [MHz] is one of the 40 BLE channels (2402, 2404, ..., 2480)
+    pub fn from_freq(freq: u32) -> Option<Self> {
+        if freq % 2 != 0 || !(2402..=2480).contains(&freq) {
+            return None;
         }
+
+        Some(BluetoothChannel {
+            blch: (freq - 2402) / 2,
+        })
     }
-    fn to_freq(self) -> u32 {
+
+    pub fn to_freq(self) -> u32 {
         2402 + 2 * self.blch
     }
+
+    /// RF channel (0..=39)
+    pub fn index(self) -> u32 {
+        self.blch
+    }
+
+    /// BLE channel index: 37/38/39 for the advertising channels, 0..=36 for the data channels
+    pub fn channel_index(self) -> u8 {
+        CHANNEL_INDEX_FREQS
+            .iter()
+            .position(|freq| *freq == self.to_freq())
+            .expect("every RF channel has a channel index") as u8
+    }
+
+    /// None unless `index` is a data channel index (0..=36)
+    pub fn from_data_channel(index: u8) -> Option<Self> {
+        if index > 36 {
+            return None;
+        }
+
+        Self::from_channel_index(index)
+    }
+
+    /// Inverse of `channel_index`, None unless `index` is a BLE channel index (0..=39)
+    pub fn from_channel_index(index: u8) -> Option<Self> {
+        CHANNEL_INDEX_FREQS
+            .get(index as usize)
+            .and_then(|freq| Self::from_freq(*freq))
+    }
+}
+
+// center frequency [MHz] of each BLE channel index
+const CHANNEL_INDEX_FREQS: [u32; 40] = [
+    // data channels 0..=10
+    2404, 2406, 2408, 2410, 2412, 2414, 2416, 2418, 2420, 2422, 2424,
+    // data channels 11..=36
+    2428, 2430, 2432, 2434, 2436, 2438, 2440, 2442, 2444, 2446, 2448, 2450, 2452, 2454, 2456, 2458,
+    2460, 2462, 2464, 2466, 2468, 2470, 2472, 2474, 2476, 2478,
+    // advertising channels 37..=39
+    2402, 2426, 2480,
+];
+
+/// Mapping between the channelizer outputs and frequencies
+///
+/// Output `i` is centered at `freq_mhz + i` for `i < num_channels / 2`
+/// and at `freq_mhz + i - num_channels` for the rest.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ChannelPlan {
+    pub freq_mhz: usize,
+    pub num_channels: usize,
+}
+
+impl ChannelPlan {
+    pub fn new(freq_mhz: usize, num_channels: usize) -> Self {
+        Self {
+            freq_mhz,
+            num_channels,
+        }
+    }
+
+    pub fn from_config(config: &crate::device::sdr::SDRConfig) -> Self {
+        Self::new(config.freq_mhz, config.num_channels)
+    }
+
+    /// center frequency [MHz] of the channelizer output
+    pub fn sdr_idx_to_freq(&self, sdr_idx: usize) -> Option<usize> {
+        if sdr_idx >= self.num_channels {
+            return None;
+        }
+
+        let channel_half = self.num_channels / 2;
+        if sdr_idx < channel_half {
+            Some(self.freq_mhz + sdr_idx)
+        } else {
+            (self.freq_mhz + sdr_idx).checked_sub(self.num_channels)
+        }
+    }
+
+    /// channelizer output centered at `freq` [MHz]
+    pub fn freq_to_sdr_idx(&self, freq: usize) -> Option<usize> {
+        let channel_half = self.num_channels as isize / 2;
+        let freq_offset = freq as isize - self.freq_mhz as isize;
+
+        if !(-channel_half..channel_half).contains(&freq_offset) {
+            return None;
+        }
+
+        Some(freq_offset.rem_euclid(self.num_channels as isize) as usize)
+    }
+
+    /// BLE channels covered by the channelizer outputs, in output order
+    pub fn ble_channels(&self) -> Vec<(usize, BluetoothChannel)> {
+        (0..self.num_channels)
+            .filter_map(|sdr_idx| {
+                let freq = self.sdr_idx_to_freq(sdr_idx)?;
+                let blch = BluetoothChannel::from_freq(freq.try_into().ok()?)?;
+
+                Some((sdr_idx, blch))
+            })
+            .collect()
+    }
+
+    pub fn blch_to_sdr_idx(&self, blch: BluetoothChannel) -> Option<usize> {
+        self.freq_to_sdr_idx(blch.to_freq() as usize)
+    }
 }
 
 type RxChannelSender = (
     BluetoothChannel,
-    std::sync::mpsc::Sender<Vec<num_complex::Complex<f32>>>,
-);
-type RxChannelReceiver = (
-    SdrIdx,
-    std::sync::mpsc::Receiver<Vec<num_complex::Complex<f32>>>,
+    ChunkSender<Vec<num_complex::Complex<f32>>>,
 );
+type RxChannelReceiver = (SdrIdx, ChunkReceiver<Vec<num_complex::Complex<f32>>>);
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use anyhow::Context;
 
+use crate::device::sdr::Backpressure;
+
+// SdrIdx of the synthesizer input for the frequency [MHz]
+fn freq_to_sdridx(config: &crate::device::sdr::SDRConfig, freq: usize) -> Option<SdrIdx> {
+    ChannelPlan::from_config(config)
+        .freq_to_sdr_idx(freq)
+        .map(SdrIdx)
+}
+
 #[derive(Debug)]
 pub enum ProcessFailKind {
     Catcher,
@@ -37,310 +156,3018 @@ pub enum ProcessFailKind {
     #[allow(dead_code)]
     Demod(anyhow::Error),
     Bitops,
+    // the access address is not in `SDRConfig::aa_filter`
+    Filtered,
     Bluetooth,
 }
 
-pub trait Stream {
-    fn start_rx(&mut self) -> anyhow::Result<RxStream<crate::bluetooth::Bluetooth>>;
-    fn start_tx(&mut self) -> anyhow::Result<TxStream<crate::bluetooth::Bluetooth>>;
+/// Typed counterparts of the pipeline's trace logs, see `Device::tap_events`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineEvent {
+    /// the reader got a block of `samples` samples from the SDR
+    ReadBlock { samples: usize },
+    /// buffers queued in the driver, not read yet (SoapyHackRF only)
+    BufferBacklog { count: usize },
+    /// the reader lost samples before the next block
+    Overflow,
+    /// the input file ended and was reopened (`SDRConfig::repeat`)
+    Rewind,
+    /// a packet on `freq` [MHz] has `len` bits left after its CRC
+    RemainBits { freq: u32, len: usize },
 }
 
-impl crate::device::Device {
-    fn prepare_pfbch2_fsk_mpsc(
-        &self,
-    ) -> (
-        HashMap<SdrIdx, RxChannelSender>,
-        HashMap<BluetoothChannel, RxChannelReceiver>,
-    ) {
-        let mut sdridx_to_sender: HashMap<SdrIdx, RxChannelSender> = HashMap::new();
-        let mut blch_to_receiver: HashMap<BluetoothChannel, RxChannelReceiver> = HashMap::new();
+// events are dropped while `events` is full, the pipeline never waits for a subscriber
+fn emit(events: &Option<std::sync::mpsc::SyncSender<PipelineEvent>>, event: PipelineEvent) {
+    if let Some(ref events) = events {
+        let _ = events.try_send(event);
+    }
+}
 
-        let channel_half = self.config.num_channels as isize / 2;
+// what the rx thread does after a failed read (other than an overflow)
+#[derive(Debug, PartialEq, Eq)]
+enum ReadRecovery {
+    Retry,
+    // reopen the stream for the next pass over the input
+    Rewind,
+    Fail,
+}
 
-        for (sdr_idx, (tx, rx)) in (0..self.config.num_channels)
-            .map(|_| std::sync::mpsc::channel::<Vec<num_complex::Complex<f32>>>())
-            .enumerate()
-        {
-            let sdr_idx_isize = sdr_idx as isize;
-            let freq_offset = if sdr_idx_isize < channel_half {
-                sdr_idx_isize
-            } else {
-                sdr_idx_isize - self.config.num_channels as isize
-            };
+fn read_recovery(code: soapysdr::ErrorCode, passes: usize) -> ReadRecovery {
+    match code {
+        // no samples within the timeout, the SDR may still deliver
+        soapysdr::ErrorCode::Timeout => ReadRecovery::Retry,
+        // the File driver reports the end of the input as a stream error
+        soapysdr::ErrorCode::StreamError if passes > 1 => ReadRecovery::Rewind,
+        _ => ReadRecovery::Fail,
+    }
+}
 
-            let freq = self.config.freq_mhz as isize + freq_offset;
+// idle wait of a catcher worker which serves several channels
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+// shared counters updated by the rx worker threads
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    catcher: AtomicUsize,
+    too_short: AtomicUsize,
+    demod: AtomicUsize,
+    bitops: AtomicUsize,
+    filtered: AtomicUsize,
+    bluetooth: AtomicUsize,
+    packets: AtomicUsize,
+    samples: AtomicUsize,
+    dropped: AtomicUsize,
+    overflows: AtomicUsize,
+    gated: AtomicUsize,
+    panics: AtomicUsize,
+    occupancy: std::sync::Mutex<HashMap<BluetoothChannel, ChannelOccupancy>>,
+    capture_start: std::sync::Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+    capture_end: std::sync::Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+}
 
-            if freq & 1 == 0 && (2402..=2480).contains(&freq) {
-                let blch = BluetoothChannel::from_freq(freq as u32);
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStatsSnapshot {
+    pub catcher: usize,
+    pub too_short: usize,
+    pub demod: usize,
+    pub bitops: usize,
+    pub filtered: usize,
+    pub bluetooth: usize,
+    // successfully decoded packets
+    pub packets: usize,
+    // samples fed into the channelizer
+    pub samples: usize,
+    // channelized chunks dropped because catch_and_process fell behind
+    pub dropped: usize,
+    // reads which reported an overflow (samples lost in the SDR or the driver)
+    pub overflows: usize,
+    // blocks skipped by the energy gate (`SDRConfig::energy_gate_db`)
+    pub gated: usize,
+    // catchers restarted after a panic
+    pub panics: usize,
+    // the SDR stream was activated (None before the reader started)
+    pub capture_start: Option<chrono::DateTime<chrono::Utc>>,
+    // the SDR stream was deactivated (None while capturing)
+    pub capture_end: Option<chrono::DateTime<chrono::Utc>>,
+}
 
-                sdridx_to_sender.insert(SdrIdx(sdr_idx), (blch, tx));
-                blch_to_receiver.insert(blch, (SdrIdx(sdr_idx), rx));
-            }
-        }
+impl StreamStatsSnapshot {
+    /// Length of the capture so far (up to now while capturing)
+    pub fn duration(&self) -> Option<chrono::TimeDelta> {
+        let start = self.capture_start?;
+        let end = self.capture_end.unwrap_or_else(chrono::Utc::now);
 
-        (sdridx_to_sender, blch_to_receiver)
+        Some(end.signed_duration_since(start))
     }
 
-    // for SoapyHackRF
-    fn check_remain_count(raw: &soapysdr::Device) -> anyhow::Result<()> {
-        if let Some(remain_count) = raw
-            .channel_info(soapysdr::Direction::Rx, 0)
-            .context("channel_info")?
-            .get("buffer_count")
-        {
-            let remain_count = remain_count.parse::<usize>()?;
-            log::trace!("remain_count: {}", remain_count);
+    /// "capture: start .. end (seconds), samples, packets" (None before the capture started)
+    pub fn summary(&self) -> Option<String> {
+        let duration = self.duration()?;
+
+        Some(format!(
+            "capture: {} .. {} ({:.3} s), {} samples, {} packets",
+            self.capture_start?.to_rfc3339(),
+            self.capture_end
+                .map_or("running".to_string(), |end| end.to_rfc3339()),
+            duration.num_milliseconds() as f64 / 1e3,
+            self.samples,
+            self.packets
+        ))
+    }
+}
+
+// bursts seen on a BLE channel
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChannelOccupancy {
+    pub bursts: usize,
+    // bursts decoded as a packet
+    pub packets: usize,
+    rssi_sum: f32,
+}
+
+impl ChannelOccupancy {
+    pub fn mean_rssi(&self) -> Option<f32> {
+        (self.bursts > 0).then(|| self.rssi_sum / self.bursts as f32)
+    }
+}
+
+/// Activity per BLE channel seen by `Device::scan`
+#[derive(Debug, Clone)]
+pub struct ScanReport {
+    /// how long the pipeline ran (shorter than asked if the input ended)
+    pub elapsed: std::time::Duration,
+    /// every BLE channel in the band of the device, by frequency
+    pub channels: Vec<(BluetoothChannel, ChannelOccupancy)>,
+}
+
+impl ScanReport {
+    /// channels with at least one decoded packet, by frequency
+    pub fn active(&self) -> Vec<BluetoothChannel> {
+        self.channels
+            .iter()
+            .filter(|(_, o)| o.packets > 0)
+            .map(|(ch, _)| *ch)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for ScanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "scanned for {:.1} s", self.elapsed.as_secs_f64())?;
+
+        for (ch, o) in &self.channels {
+            writeln!(
+                f,
+                "ch {:2} ({} MHz) {:6} bursts {:4} packets rssi={:6.1}",
+                ch.channel_index(),
+                ch.to_freq(),
+                o.bursts,
+                o.packets,
+                o.mean_rssi().unwrap_or(f32::NAN)
+            )?;
         }
 
         Ok(())
     }
+}
 
-    fn wake_channelizer(
-        &mut self,
-        sdridx_to_sender: HashMap<SdrIdx, RxChannelSender>,
-        on_error: impl Fn(anyhow::Error) + 'static + Send + Clone,
-    ) -> anyhow::Result<()> {
-        let config = self.config.clone();
-        let raw = self.raw.clone();
-        let running = self.running.clone();
+impl StreamStats {
+    fn burst(&self, ch: BluetoothChannel, rssi: f32) {
+        let mut occupancy = self.occupancy.lock().expect("failed to lock");
+        let entry = occupancy.entry(ch).or_default();
 
-        let mut read_stream = self.raw.rx_stream_args::<num_complex::Complex<f32>, _>(
-            &[self.config.channels],
-            "buffers=65535",
-        )?;
+        entry.bursts += 1;
+        entry.rssi_sum += rssi;
+    }
 
-        // let mut channelizer = crate::channelizer::Channelizer::new(config.num_channels, 4, 0.75);
-        let mut channelizer = crate::channelizer::Channelizer::new(config.num_channels);
-        // log::trace!("wake_channelizer\n{}", channelizer);
+    fn packet(&self, ch: BluetoothChannel) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
 
-        let mut fft_result: Vec<Vec<num_complex::Complex<f32>>> = (0..config.num_channels)
-            .map(|_| Vec::with_capacity(131072 / (config.num_channels / 2)))
-            .collect::<Vec<_>>();
+        let mut occupancy = self.occupancy.lock().expect("failed to lock");
+        occupancy.entry(ch).or_default().packets += 1;
+    }
 
-        let mut buffer =
-            vec![num_complex::Complex::default(); read_stream.mtu()?].into_boxed_slice();
+    pub fn occupancy(&self) -> HashMap<BluetoothChannel, ChannelOccupancy> {
+        self.occupancy.lock().expect("failed to lock").clone()
+    }
 
-        // std::thread::spawn(move || {
-        let _ = std::thread::Builder::new()
-            .name("wake_channelizer".to_string())
-            .spawn(move || {
-                if let Err(e) = read_stream.activate(None) {
-                    on_error(e.into());
-                    return;
-                }
+    fn capture_started(&self) {
+        *self.capture_start.lock().expect("failed to lock") = Some(chrono::Utc::now());
+    }
 
-                let ret: anyhow::Result<()> = (|| loop {
-                    let _read = read_stream
-                        .read(&mut [&mut buffer[..]], 1_000_000)
-                        .context("wake_channelizer(read)")?;
+    fn capture_stopped(&self) {
+        *self.capture_end.lock().expect("failed to lock") = Some(chrono::Utc::now());
+    }
 
-                    Self::check_remain_count(&raw)?;
+    /// See `StreamStatsSnapshot::duration`
+    pub fn duration(&self) -> Option<chrono::TimeDelta> {
+        self.snapshot().duration()
+    }
 
-                    for fft in fft_result.iter_mut() {
-                        fft.clear();
-                    }
+    fn process_fail(&self, kind: &ProcessFailKind) {
+        let counter = match kind {
+            ProcessFailKind::Catcher => &self.catcher,
+            ProcessFailKind::TooShort => &self.too_short,
+            ProcessFailKind::Demod(_) => &self.demod,
+            ProcessFailKind::Bitops => &self.bitops,
+            ProcessFailKind::Filtered => &self.filtered,
+            ProcessFailKind::Bluetooth => &self.bluetooth,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
 
-                    for chunk in buffer.chunks_exact_mut(config.num_channels / 2) {
-                        for (sdridx, fft) in channelizer.channelize(chunk).iter().enumerate() {
-                            if sdridx_to_sender.contains_key(&SdrIdx(sdridx)) {
-                                fft_result[sdridx].push(*fft);
-                            }
-                        }
-                    }
+    pub fn snapshot(&self) -> StreamStatsSnapshot {
+        StreamStatsSnapshot {
+            catcher: self.catcher.load(Ordering::Relaxed),
+            too_short: self.too_short.load(Ordering::Relaxed),
+            demod: self.demod.load(Ordering::Relaxed),
+            bitops: self.bitops.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            bluetooth: self.bluetooth.load(Ordering::Relaxed),
+            packets: self.packets.load(Ordering::Relaxed),
+            samples: self.samples.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            overflows: self.overflows.load(Ordering::Relaxed),
+            gated: self.gated.load(Ordering::Relaxed),
+            panics: self.panics.load(Ordering::Relaxed),
+            capture_start: *self.capture_start.lock().expect("failed to lock"),
+            capture_end: *self.capture_end.lock().expect("failed to lock"),
+        }
+    }
+}
 
-                    for (sdridx, fft) in fft_result.iter().enumerate() {
-                        if let Some((_blch, tx)) = sdridx_to_sender.get(&SdrIdx(sdridx)) {
-                            tx.send(fft.clone()).context("wake_channelizer(send)")?;
-                        }
-                    }
+// burst -> demodulator -> bitops -> Bluetooth on a single channel
+struct Catcher {
+    blch: BluetoothChannel,
+    burst: crate::burst::Burst,
+    demodulator: Box<dyn crate::fsk::Demodulator>,
 
-                    if !*running.lock().expect("failed to lock") {
-                        anyhow::bail!("Interrupted");
-                    }
-                })();
+    // bursts which could not be demodulated
+    dump: Option<std::sync::mpsc::Sender<crate::burst::RawBurst>>,
 
-                *running.lock().expect("failed to lock") = false;
+    // bit errors tolerated in the advertising AA
+    aa_tolerance: u32,
 
-                if let Err(e) = read_stream.deactivate(None) {
-                    on_error(e.into());
-                }
+    // bit errors tolerated in the preamble
+    preamble_tolerance: u32,
 
-                if let Err(e) = ret {
-                    on_error(e);
-                }
-            });
+    // access addresses to decode (None: every AA)
+    aa_filter: Option<std::collections::HashSet<u32>>,
 
-        Ok(())
-    }
+    // see `Device::tap_events`
+    events: Option<std::sync::mpsc::SyncSender<PipelineEvent>>,
 
-    fn catch_and_process(
-        &mut self,
-        rxs: HashMap<BluetoothChannel, RxChannelReceiver>,
+    // see `SDRConfig::detect_channel`
+    detect_channel: bool,
 
-        sender: impl Fn(crate::bluetooth::Bluetooth) + 'static + Send + Clone,
-        process_fail: impl Fn(ProcessFailKind) + 'static + Send + Clone,
-        on_error: impl Fn(anyhow::Error) + 'static + Send + Clone,
-    ) -> anyhow::Result<()> {
-        let sample_rate = self.config.sample_rate;
-        let num_channels = self.config.num_channels;
+    // see `Device::record_bursts`
+    record: Option<std::sync::mpsc::Sender<(u32, crate::burst::Recording)>>,
 
-        for (ble_ch_idx, sdr_idx_rx) in rxs.into_iter() {
-            let freq = ble_ch_idx.to_freq();
+    // see `Device::connections`
+    connections: crate::bluetooth::Connections,
+}
 
-            let (_sdr_idx, rx) = sdr_idx_rx;
+impl Catcher {
+    fn new(
+        blch: BluetoothChannel,
+        squelch: crate::burst::BurstParams,
+        demodulator: Box<dyn crate::fsk::Demodulator>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            blch,
+            burst: crate::burst::Burst::with_params(squelch)?,
+            demodulator,
+            dump: None,
+            aa_tolerance: 0,
+            preamble_tolerance: 0,
+            aa_filter: None,
+            events: None,
+            detect_channel: false,
+            record: None,
+            connections: crate::bluetooth::Connections::default(),
+        })
+    }
 
-            let sender = sender.clone();
-            let process_fail = process_fail.clone();
-            let on_error = on_error.clone();
+    fn record(
+        &mut self,
+        margin: crate::burst::RecordMargin,
+        sink: std::sync::mpsc::Sender<(u32, crate::burst::Recording)>,
+    ) {
+        self.burst.record(margin);
+        self.record = Some(sink);
+    }
 
-            std::thread::spawn(move || {
-                let mut burst = crate::burst::Burst::new();
-                let mut fsk = crate::fsk::FskDemod::new(sample_rate as _, num_channels);
-
-                loop {
-                    let channelized_values = match rx.recv().context("catch_and_process(recv)") {
-                        Ok(v) => v,
-                        Err(e) => {
-                            on_error(e);
-                            break;
-                        }
-                    };
+    fn send_recordings(&mut self) {
+        let Some(ref record) = self.record else {
+            return;
+        };
 
-                    for s in channelized_values {
-                        let ret: Result<(), ProcessFailKind> = (|| {
-                            let packet = burst
-                                // .catcher(s / num_channels as f32)
-                                .catcher(s)
-                                .ok_or(ProcessFailKind::Catcher)?;
+        while let Some(recording) = self.burst.take_recording() {
+            let _ = record.send((self.blch.to_freq(), recording));
+        }
+    }
 
-                            if packet.data.len() < 132 {
-                                return Err(ProcessFailKind::TooShort);
-                            }
+    fn dump(&self, packet: &crate::burst::Packet) {
+        if let Some(ref dump) = self.dump {
+            let _ = dump.send((packet.timestamp, packet.data.clone()));
+        }
+    }
 
-                            let demodulated =
-                                fsk.demodulate(packet).map_err(ProcessFailKind::Demod)?;
+    fn catch(
+        &mut self,
+        s: num_complex::Complex<f32>,
+        stats: &StreamStats,
+    ) -> Result<crate::bluetooth::Bluetooth, ProcessFailKind> {
+        let freq = self.blch.to_freq() as usize;
+
+        let packet = self
+            .burst
+            // .catcher(s / num_channels as f32)
+            .catcher(s);
+        self.send_recordings();
+        let packet = packet.ok_or(ProcessFailKind::Catcher)?;
+
+        stats.burst(self.blch, packet.rssi_average);
+
+        if packet.data.len() < 132 {
+            self.dump(&packet);
+            return Err(ProcessFailKind::TooShort);
+        }
 
-                            let byte_packet =
-                                crate::bitops::fsk_to_packet(demodulated, freq as usize)
-                                    .map_err(|_| ProcessFailKind::Bitops)?;
+        let raw = self.dump.is_some().then(|| packet.clone());
+        let demodulated = self.demodulator.demodulate(packet).map_err(|e| {
+            if let Some(ref raw) = raw {
+                self.dump(raw);
+            }
 
-                            if !byte_packet.remain_bits.is_empty() {
-                                log::trace!("remain bits: {:?}", byte_packet.remain_bits);
-                            }
+            ProcessFailKind::Demod(e)
+        })?;
 
-                            let bt =
-                                crate::bluetooth::Bluetooth::from_bytes(byte_packet, freq as usize)
-                                    .map_err(|_| ProcessFailKind::Bluetooth)?;
+        let byte_packet = if self.detect_channel {
+            crate::bitops::fsk_to_packet_detect_channel(
+                demodulated,
+                freq,
+                self.aa_tolerance,
+                self.preamble_tolerance,
+            )
+        } else {
+            crate::bitops::fsk_to_packet(
+                demodulated,
+                freq,
+                self.aa_tolerance,
+                self.preamble_tolerance,
+            )
+        }
+        .map_err(|_| ProcessFailKind::Bitops)?;
+        // the detected channel if the burst was whitened for another one
+        let freq = byte_packet.freq;
 
-                            sender(bt);
+        if let Some(ref aa_filter) = self.aa_filter {
+            if !aa_filter.contains(&byte_packet.aa) {
+                return Err(ProcessFailKind::Filtered);
+            }
+        }
 
-                            Ok(())
-                        })();
+        if !byte_packet.remain_bits.is_empty() {
+            log::trace!("remain bits: {:?}", byte_packet.remain_bits);
+            emit(
+                &self.events,
+                PipelineEvent::RemainBits {
+                    freq: freq as u32,
+                    len: byte_packet.remain_bits.len(),
+                },
+            );
+        }
 
-                        if let Err(e) = ret {
-                            process_fail(e);
-                        }
-                    }
+        crate::bluetooth::Bluetooth::from_bytes_with(byte_packet, freq, &self.connections)
+            .map_err(|_| ProcessFailKind::Bluetooth)
+    }
+}
+
+impl Drop for Catcher {
+    // the last burst may still be waiting for its post margin
+    fn drop(&mut self) {
+        self.burst.finish_recording();
+        self.send_recordings();
+    }
+}
+
+// feed a channelized chunk to `catcher`. If it panics, the rest of the chunk is lost,
+// the panic is reported and the catcher is replaced by `restart()`.
+// false if the catcher could not be restarted (the channel is given up)
+fn catch_chunk(
+    catcher: &mut Catcher,
+    values: Vec<num_complex::Complex<f32>>,
+    stats: &StreamStats,
+    sender: &impl Fn(crate::bluetooth::Bluetooth),
+    process_fail: &impl Fn(ProcessFailKind),
+    on_error: &impl Fn(StreamError),
+    restart: impl FnOnce() -> anyhow::Result<Catcher>,
+) -> bool {
+    let ret = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        for s in values {
+            match catcher.catch(s, stats) {
+                Ok(bt) => {
+                    sender(bt);
+                    stats.packet(catcher.blch);
                 }
-            });
+                Err(e) => {
+                    stats.process_fail(&e);
+                    process_fail(e);
+                }
+            }
+        }
+    }));
+
+    if let Err(payload) = ret {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let freq = catcher.blch.to_freq();
+
+        log::error!("catcher on {} MHz panicked: {}, restarting", freq, message);
+        stats.panics.fetch_add(1, Ordering::Relaxed);
+        on_error(StreamError::CatcherPanic { freq, message });
+
+        match restart() {
+            Ok(restarted) => *catcher = restarted,
+            Err(e) => {
+                log::error!("catcher on {} MHz could not be restarted: {:#}", freq, e);
+                on_error(StreamError::Other(e));
+                return false;
+            }
         }
-
-        Ok(())
     }
 
-    pub fn start_rx_with_error(&mut self) -> anyhow::Result<RxStream<StreamResult>> {
-        // sink/source Bluetooth Packet
+    true
+}
 
-        let (packet_sink, packet_source) = std::sync::mpsc::channel();
-        *self.running.lock().expect("failed to lock") = true;
+// priority of the calling catcher thread (`SDRConfig::worker_priority`)
+fn set_worker_priority(priority: u8) -> anyhow::Result<()> {
+    use thread_priority::{ThreadPriority, ThreadPriorityValue};
 
-        let (sdridx_to_sender, blch_to_receiver) = self.prepare_pfbch2_fsk_mpsc();
+    let value = ThreadPriorityValue::try_from(priority)
+        .map_err(|e| anyhow::anyhow!("invalid worker_priority {}: {:?}", priority, e))?;
 
-        let ps1 = packet_sink.clone();
+    thread_priority::set_current_thread_priority(ThreadPriority::Crossplatform(value))
+        .map_err(|e| anyhow::anyhow!("failed to set worker_priority {}: {:?}", priority, e))
+}
 
-        self.wake_channelizer(sdridx_to_sender, move |e| {
-            let _ = ps1.send(StreamResult::Error(e));
-        })?;
+// bounded queue of the chunks from the reader to a catcher
+// unlike a sync_channel, a full queue can make room by dropping its oldest chunk
+struct ChunkQueue<T> {
+    state: std::sync::Mutex<ChunkQueueState<T>>,
+    changed: std::sync::Condvar,
+}
 
-        let ps2 = packet_sink.clone();
-        let ps3 = packet_sink.clone();
-        let ps4 = packet_sink.clone();
+struct ChunkQueueState<T> {
+    items: std::collections::VecDeque<T>,
+    capacity: usize,
+    sender: bool,
+    receiver: bool,
+}
 
-        self.catch_and_process(
-            blch_to_receiver,
-            move |packet| {
-                let _ = ps2.send(StreamResult::Packet(Box::new(packet)));
-            },
-            move |fail| {
-                let _ = ps3.send(StreamResult::ProcessFail(fail));
-            },
-            move |e| {
-                let _ = ps4.send(StreamResult::Error(e));
-            },
-        )?;
+struct ChunkSender<T>(Arc<ChunkQueue<T>>);
+struct ChunkReceiver<T>(Arc<ChunkQueue<T>>);
+
+fn chunk_queue<T>(capacity: usize) -> (ChunkSender<T>, ChunkReceiver<T>) {
+    let queue = Arc::new(ChunkQueue {
+        state: std::sync::Mutex::new(ChunkQueueState {
+            items: std::collections::VecDeque::new(),
+            capacity: capacity.max(1),
+            sender: true,
+            receiver: true,
+        }),
+        changed: std::sync::Condvar::new(),
+    });
+
+    (ChunkSender(queue.clone()), ChunkReceiver(queue))
+}
 
-        Ok(RxStream {
-            source: packet_source,
-        })
+impl<T> ChunkQueue<T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, ChunkQueueState<T>> {
+        self.state.lock().expect("failed to lock")
     }
 }
 
-impl Drop for crate::device::Device {
-    fn drop(&mut self) {
-        *self.running.lock().expect("failed to lock") = false;
+impl<T> ChunkSender<T> {
+    // wait for room, Err once the receiver is gone
+    fn send(&self, item: T) -> Result<(), T> {
+        let mut state = self.0.lock();
+        while state.receiver && state.items.len() >= state.capacity {
+            state = self.0.changed.wait(state).expect("failed to lock");
+        }
+        if !state.receiver {
+            return Err(item);
+        }
+
+        state.items.push_back(item);
+        self.0.changed.notify_all();
+
+        Ok(())
     }
-}
 
-impl Stream for crate::device::Device {
-    fn start_rx(&mut self) -> anyhow::Result<RxStream<crate::bluetooth::Bluetooth>> {
-        // sink/source Bluetooth Packet
+    // never waits: a full queue drops its oldest chunk, which is returned
+    fn send_drop_oldest(&self, item: T) -> Result<Option<T>, T> {
+        let mut state = self.0.lock();
+        if !state.receiver {
+            return Err(item);
+        }
 
-        let (packet_sink, packet_source) = std::sync::mpsc::channel();
-        *self.running.lock().expect("failed to lock") = true;
+        let dropped = if state.items.len() >= state.capacity {
+            state.items.pop_front()
+        } else {
+            None
+        };
+        state.items.push_back(item);
+        self.0.changed.notify_all();
 
-        let (sdridx_to_sender, blch_to_receiver) = self.prepare_pfbch2_fsk_mpsc();
+        Ok(dropped)
+    }
+}
 
-        self.wake_channelizer(sdridx_to_sender, |_e| {})?;
-        self.catch_and_process(
-            blch_to_receiver,
-            move |packet| {
-                let _ = packet_sink.send(packet);
-            },
-            |_fail| {},
-            |_e| {},
-        )?;
+impl<T> ChunkReceiver<T> {
+    fn recv(&self) -> Result<T, std::sync::mpsc::RecvError> {
+        let mut state = self.0.lock();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.0.changed.notify_all();
+                return Ok(item);
+            }
+            if !state.sender {
+                return Err(std::sync::mpsc::RecvError);
+            }
 
-        Ok(RxStream {
-            source: packet_source,
-        })
+            state = self.0.changed.wait(state).expect("failed to lock");
+        }
     }
 
-    fn start_tx(&mut self) -> anyhow::Result<TxStream<crate::bluetooth::Bluetooth>> {
-        // unimplemented!()
-        let (tx, _rx) = std::sync::mpsc::channel();
+    fn try_recv(&self) -> Result<T, std::sync::mpsc::TryRecvError> {
+        let mut state = self.0.lock();
+        match state.items.pop_front() {
+            Some(item) => {
+                self.0.changed.notify_all();
+                Ok(item)
+            }
+            None if state.sender => Err(std::sync::mpsc::TryRecvError::Empty),
+            None => Err(std::sync::mpsc::TryRecvError::Disconnected),
+        }
+    }
 
-        Ok(TxStream { sink: tx })
+    #[cfg(test)]
+    fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
     }
 }
 
-pub enum StreamResult {
-    Packet(Box<crate::bluetooth::Bluetooth>),
-    Error(anyhow::Error),
-    ProcessFail(ProcessFailKind),
+impl<T> Drop for ChunkSender<T> {
+    fn drop(&mut self) {
+        self.0.lock().sender = false;
+        self.0.changed.notify_all();
+    }
 }
 
-pub struct RxStream<ReceiveItem> {
-    pub source: std::sync::mpsc::Receiver<ReceiveItem>,
+impl<T> Drop for ChunkReceiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.receiver = false;
+        state.items.clear();
+        self.0.changed.notify_all();
+    }
 }
 
-pub struct TxStream<SendItem> {
-    pub sink: std::sync::mpsc::Sender<SendItem>,
+// a chunk which is not delivered (the newest one when the catcher is gone, the oldest one
+// queued when it is too slow) is counted as dropped
+fn send_or_drop<T>(tx: &ChunkSender<T>, item: T, backpressure: Backpressure, stats: &StreamStats) {
+    let sent = match backpressure {
+        Backpressure::Block => tx.send(item).is_ok(),
+        Backpressure::Drop => matches!(tx.send_drop_oldest(item), Ok(None)),
+    };
+
+    if !sent {
+        stats.dropped.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
-impl<T> std::iter::Iterator for RxStream<T> {
-    type Item = T;
+// mean power of `block` [dBFS]
+fn block_power_db(block: &[num_complex::Complex<f32>]) -> f32 {
+    let power = block.iter().map(|s| s.norm_sqr()).sum::<f32>() / block.len().max(1) as f32;
+    10. * power.log10()
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.source.recv().ok()
+// channelizes each block read by wake_channelizer and sends the BLE channels to the catchers
+struct ChannelizerStage {
+    channelizer: crate::channelizer::Channelizer,
+    fft_result: Vec<Vec<num_complex::Complex<f32>>>,
+    sdridx_to_sender: HashMap<SdrIdx, RxChannelSender>,
+    num_channels: usize,
+    backpressure: Backpressure,
+    energy_gate_db: Option<f32>,
+    // the previous block was skipped by the energy gate
+    gated: bool,
+    // blocks left to discard (`SDRConfig::warmup_blocks`)
+    warmup_blocks: usize,
+    // tail of the previous block, shorter than a channelizer input
+    remainder: Vec<num_complex::Complex<f32>>,
+
+    // mean magnitude of every bin per block (see `Device::tap_spectrum`)
+    spectrum: Option<std::sync::mpsc::SyncSender<Vec<f32>>>,
+    magnitude: Vec<f32>,
+}
+
+impl ChannelizerStage {
+    fn new(
+        config: &crate::device::sdr::SDRConfig,
+        sdridx_to_sender: HashMap<SdrIdx, RxChannelSender>,
+    ) -> Self {
+        // let mut channelizer = crate::channelizer::Channelizer::new(config.num_channels, 4, 0.75);
+        let channelizer = crate::channelizer::Channelizer::new(config.num_channels);
+        // log::trace!("wake_channelizer\n{}", channelizer);
+
+        let fft_result = (0..config.num_channels)
+            .map(|_| Vec::with_capacity(131072 / (config.num_channels / 2)))
+            .collect::<Vec<_>>();
+
+        Self {
+            channelizer,
+            fft_result,
+            sdridx_to_sender,
+            num_channels: config.num_channels,
+            backpressure: config.backpressure,
+            energy_gate_db: config.energy_gate_db,
+            gated: false,
+            warmup_blocks: config.warmup_blocks,
+            remainder: Vec::with_capacity(config.num_channels / 2),
+            spectrum: None,
+            magnitude: vec![0.; config.num_channels],
+        }
+    }
+
+    fn process(
+        &mut self,
+        buffer: &[num_complex::Complex<f32>],
+        overflowed: bool,
+        stats: &StreamStats,
+    ) {
+        if self.warmup_blocks > 0 {
+            self.warmup_blocks -= 1;
+            return;
+        }
+
+        if let Some(threshold) = self.energy_gate_db {
+            if block_power_db(buffer) < threshold {
+                stats.gated.fetch_add(1, Ordering::Relaxed);
+                self.gated = true;
+                return;
+            }
+        }
+
+        // do not filter across the gap
+        if overflowed || std::mem::take(&mut self.gated) {
+            self.channelizer.reset();
+            self.remainder.clear();
+        }
+
+        for fft in self.fft_result.iter_mut() {
+            fft.clear();
+        }
+
+        let half = self.num_channels / 2;
+        let mut chunks = 0;
+
+        // complete the chunk left over from the previous block first
+        let mut buffer = buffer;
+        if !self.remainder.is_empty() {
+            let (head, rest) = buffer.split_at((half - self.remainder.len()).min(buffer.len()));
+            self.remainder.extend_from_slice(head);
+            buffer = rest;
+
+            if self.remainder.len() == half {
+                let remainder = std::mem::take(&mut self.remainder);
+                self.channelize(&remainder, &mut chunks);
+
+                self.remainder = remainder;
+                self.remainder.clear();
+            }
+        }
+
+        let mut iter = buffer.chunks_exact(half);
+        for chunk in iter.by_ref() {
+            self.channelize(chunk, &mut chunks);
+        }
+        self.remainder.extend_from_slice(iter.remainder());
+
+        if let Some(ref spectrum) = self.spectrum {
+            let row = self
+                .magnitude
+                .iter()
+                .map(|m| m / chunks.max(1) as f32)
+                .collect();
+            self.magnitude.fill(0.);
+
+            // a slow viewer loses rows instead of stalling the reader
+            let _ = spectrum.try_send(row);
+        }
+
+        for (sdridx, fft) in self.fft_result.iter().enumerate() {
+            if let Some((_blch, tx)) = self.sdridx_to_sender.get(&SdrIdx(sdridx)) {
+                send_or_drop(tx, fft.clone(), self.backpressure, stats);
+            }
+        }
+    }
+
+    // one channelizer input of num_channels / 2 samples
+    fn channelize(&mut self, chunk: &[num_complex::Complex<f32>], chunks: &mut usize) {
+        let channelized = self.channelizer.channelize(chunk);
+
+        if self.spectrum.is_some() {
+            for (m, fft) in self.magnitude.iter_mut().zip(channelized) {
+                *m += fft.norm();
+            }
+            *chunks += 1;
+        }
+
+        for (sdridx, fft) in channelized.iter().enumerate() {
+            if self.sdridx_to_sender.contains_key(&SdrIdx(sdridx)) {
+                self.fft_result[sdridx].push(*fft);
+            }
+        }
+    }
+}
+
+// shifts the BLE channel of wake_downconverter to baseband and decimates each block read
+struct DownconverterStage {
+    downconverter: crate::channelizer::Downconverter,
+    decimation: usize,
+    // blocks left to discard (`SDRConfig::warmup_blocks`)
+    warmup_blocks: usize,
+    // tail of the previous block, shorter than the decimation
+    remainder: Vec<num_complex::Complex<f32>>,
+}
+
+impl DownconverterStage {
+    fn new(config: &crate::device::sdr::SDRConfig, blch: BluetoothChannel) -> Self {
+        // same output rate as the channelizer
+        let decimation = config.num_channels / 2;
+        let offset = (blch.to_freq() as f64 - config.freq_mhz as f64) * 1e6 / config.sample_rate;
+
+        Self {
+            downconverter: crate::channelizer::Downconverter::new(offset as f32, decimation),
+            decimation,
+            warmup_blocks: config.warmup_blocks,
+            remainder: Vec::with_capacity(decimation),
+        }
+    }
+
+    // None while warming up
+    fn process(
+        &mut self,
+        buffer: &[num_complex::Complex<f32>],
+        overflowed: bool,
+    ) -> Option<Vec<num_complex::Complex<f32>>> {
+        if self.warmup_blocks > 0 {
+            self.warmup_blocks -= 1;
+            return None;
+        }
+
+        // do not filter across the gap
+        if overflowed {
+            self.downconverter.reset();
+            self.remainder.clear();
+        }
+
+        let mut downconverted = Vec::with_capacity(buffer.len() / self.decimation + 1);
+
+        // complete the tail left over from the previous block first
+        let mut buffer = buffer;
+        if !self.remainder.is_empty() {
+            let (head, rest) =
+                buffer.split_at((self.decimation - self.remainder.len()).min(buffer.len()));
+            self.remainder.extend_from_slice(head);
+            buffer = rest;
+
+            if self.remainder.len() == self.decimation {
+                downconverted.extend_from_slice(self.downconverter.downconvert(&self.remainder));
+                self.remainder.clear();
+            }
+        }
+
+        let whole = buffer.len() - buffer.len() % self.decimation;
+        if whole > 0 {
+            downconverted.extend_from_slice(self.downconverter.downconvert(&buffer[..whole]));
+        }
+        self.remainder.extend_from_slice(&buffer[whole..]);
+
+        Some(downconverted)
+    }
+}
+
+// BLE channels covered by the channelizer outputs
+fn ble_channels(
+    config: &crate::device::sdr::SDRConfig,
+) -> anyhow::Result<Vec<(SdrIdx, BluetoothChannel)>> {
+    let ret = ChannelPlan::from_config(config)
+        .ble_channels()
+        .into_iter()
+        .map(|(sdr_idx, blch)| (SdrIdx(sdr_idx), blch))
+        .collect::<Vec<_>>();
+
+    if ret.is_empty() {
+        anyhow::bail!(
+            "no BLE channel in {} MHz +/- {} MHz",
+            config.freq_mhz,
+            config.num_channels / 2
+        );
+    }
+
+    check_unique_channels(&ret)?;
+
+    Ok(ret)
+}
+
+// a BLE channel fed by two channelizer outputs would silently lose one of them
+fn check_unique_channels(channels: &[(SdrIdx, BluetoothChannel)]) -> anyhow::Result<()> {
+    let mut seen: HashMap<BluetoothChannel, SdrIdx> = HashMap::new();
+
+    for &(sdr_idx, blch) in channels {
+        if let Some(other) = seen.insert(blch, sdr_idx) {
+            anyhow::bail!(
+                "{} MHz is mapped to both channelizer outputs {} and {}",
+                blch.to_freq(),
+                other.0,
+                sdr_idx.0
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Silence around each synthesized burst so that the receiver's squelch can settle [samples]
+pub const TX_PADDING: usize = 256;
+
+// IQ at the SDR rate of `bits` (see `PacketInner::to_bits`) on the synthesizer input `sdridx`
+fn synthesize_bits(
+    synthesizer: &mut crate::channelizer::Synthesizer,
+    modulater: &mut crate::fsk::FskMod,
+    num_channels: usize,
+    SdrIdx(sdridx): SdrIdx,
+    bits: &[u8],
+) -> anyhow::Result<Vec<num_complex::Complex<f32>>> {
+    let modulated = modulater.modulate(bits)?;
+
+    let silence = num_complex::Complex::default();
+    let mut synthesized = vec![];
+
+    for s in std::iter::repeat(silence)
+        .take(TX_PADDING)
+        .chain(modulated)
+        .chain(std::iter::repeat(silence).take(TX_PADDING))
+    {
+        let mut signals = vec![silence; num_channels];
+        signals[sdridx] = s;
+
+        synthesized.extend_from_slice(synthesizer.synthesize(&signals));
+    }
+
+    Ok(synthesized)
+}
+
+// destination of wake_synthesizer
+enum TxOutput {
+    Soapy(soapysdr::TxStream<num_complex::Complex<f32>>),
+    File(crate::device::iq_file::IqFileWriter),
+}
+
+impl TxOutput {
+    fn activate(&mut self) -> anyhow::Result<()> {
+        if let TxOutput::Soapy(stream) = self {
+            stream.activate(None)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[num_complex::Complex<f32>]) -> anyhow::Result<()> {
+        match self {
+            TxOutput::Soapy(stream) => stream.write_all(&[samples], None, true, 1_000_000)?,
+            TxOutput::File(writer) => {
+                // flushed per packet so that the file is complete once the packet is acked
+                writer.write(samples)?;
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> anyhow::Result<()> {
+        match self {
+            TxOutput::Soapy(stream) => stream.deactivate(None)?,
+            TxOutput::File(writer) => writer.flush()?,
+        }
+
+        Ok(())
+    }
+}
+
+// samples handed out per read of a binary IQ file (the block size of the File source)
+const FILE_MTU: usize = 131072;
+
+// source of wake_reader
+enum RxInput {
+    Soapy(soapysdr::RxStream<num_complex::Complex<f32>>),
+    File {
+        path: String,
+        reader: crate::device::iq_file::IqFileReader,
+        // rest of the last block read
+        pending: std::collections::VecDeque<num_complex::Complex<f32>>,
+    },
+}
+
+impl RxInput {
+    fn open_file(path: &str) -> anyhow::Result<Self> {
+        Ok(RxInput::File {
+            path: path.to_string(),
+            reader: crate::device::iq_file::IqFileReader::open(path)?,
+            pending: std::collections::VecDeque::new(),
+        })
+    }
+
+    fn mtu(&self) -> anyhow::Result<usize> {
+        match self {
+            RxInput::Soapy(stream) => Ok(stream.mtu()?),
+            RxInput::File { .. } => Ok(FILE_MTU),
+        }
+    }
+
+    fn activate(&mut self) -> anyhow::Result<()> {
+        if let RxInput::Soapy(stream) = self {
+            stream.activate(None)?;
+        }
+
+        Ok(())
+    }
+
+    // the end of a file is a StreamError, as the File source reports it
+    fn read(&mut self, buffer: &mut [num_complex::Complex<f32>]) -> Result<usize, soapysdr::Error> {
+        let (reader, pending) = match self {
+            RxInput::Soapy(stream) => return stream.read(&mut [buffer], 1_000_000),
+            RxInput::File {
+                reader, pending, ..
+            } => (reader, pending),
+        };
+
+        if pending.is_empty() {
+            let block = reader.read_block().map_err(|e| soapysdr::Error {
+                code: soapysdr::ErrorCode::Other,
+                message: format!("{:#}", e),
+            })?;
+            let Some(block) = block else {
+                return Err(soapysdr::Error {
+                    code: soapysdr::ErrorCode::StreamError,
+                    message: "end of file".to_string(),
+                });
+            };
+
+            pending.extend(block);
+        }
+
+        let read = pending.len().min(buffer.len());
+        for (b, s) in buffer.iter_mut().zip(pending.drain(..read)) {
+            *b = s;
+        }
+
+        Ok(read)
+    }
+
+    // start over from the beginning of the input
+    fn rewind(
+        &mut self,
+        raw: &soapysdr::Device,
+        config: &crate::device::sdr::SDRConfig,
+    ) -> anyhow::Result<()> {
+        match self {
+            RxInput::Soapy(stream) => {
+                stream.deactivate(None)?;
+                *stream = raw.rx_stream_args(&[config.channels], config.stream_args().as_str())?;
+                stream.activate(None)?;
+            }
+            RxInput::File { path, .. } => *self = Self::open_file(&path.clone())?,
+        }
+
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> anyhow::Result<()> {
+        if let RxInput::Soapy(stream) = self {
+            stream.deactivate(None)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub trait Stream {
+    fn start_rx(&mut self) -> anyhow::Result<RxStream<crate::bluetooth::Bluetooth>>;
+    fn start_tx(&mut self) -> anyhow::Result<TxStream<crate::bluetooth::Bluetooth>>;
+}
+
+type LoopbackReceiver = (usize, std::sync::mpsc::Sender<crate::bluetooth::Bluetooth>);
+
+/// In-memory medium for `LoopbackStream`s (no SDR needed): a packet sent by one stream is
+/// received, as is, by every other stream of the same broker
+#[derive(Clone, Default)]
+pub struct LoopbackBroker {
+    // (stream id, sink) of each started receiver
+    receivers: Arc<std::sync::Mutex<Vec<LoopbackReceiver>>>,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl LoopbackBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new device on this medium
+    pub fn stream(&self) -> LoopbackStream {
+        LoopbackStream {
+            broker: self.clone(),
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    // `packet` to the receivers of the other streams (the closed ones are dropped)
+    fn deliver(&self, from: usize, packet: &crate::bluetooth::Bluetooth) {
+        self.receivers
+            .lock()
+            .expect("failed to lock")
+            .retain(|(id, sink)| *id == from || sink.send(packet.clone()).is_ok());
+    }
+}
+
+/// `Stream` on a `LoopbackBroker`, e.g. to test the exploit logic without SoapySDR
+pub struct LoopbackStream {
+    broker: LoopbackBroker,
+    id: usize,
+}
+
+impl Stream for LoopbackStream {
+    fn start_rx(&mut self) -> anyhow::Result<RxStream<crate::bluetooth::Bluetooth>> {
+        let (sink, source) = std::sync::mpsc::channel();
+
+        self.broker
+            .receivers
+            .lock()
+            .expect("failed to lock")
+            .push((self.id, sink));
+
+        Ok(RxStream::new(source))
+    }
+
+    fn start_tx(&mut self) -> anyhow::Result<TxStream<crate::bluetooth::Bluetooth>> {
+        let (packet_sink, packet_source) = std::sync::mpsc::channel();
+        let (tx, ack) = TxStream::new(packet_sink);
+
+        let broker = self.broker.clone();
+        let from = self.id;
+
+        // exits when the TxStream is dropped
+        std::thread::Builder::new()
+            .name(format!("loopback({})", from))
+            .spawn(move || {
+                for packet in packet_source {
+                    broker.deliver(from, &packet);
+                    let _ = ack.send(());
+                }
+            })?;
+
+        Ok(tx)
+    }
+}
+
+impl crate::device::Device {
+    fn prepare_pfbch2_fsk_mpsc(
+        &self,
+    ) -> anyhow::Result<(
+        HashMap<SdrIdx, RxChannelSender>,
+        HashMap<BluetoothChannel, RxChannelReceiver>,
+    )> {
+        let mut sdridx_to_sender: HashMap<SdrIdx, RxChannelSender> = HashMap::new();
+        let mut blch_to_receiver: HashMap<BluetoothChannel, RxChannelReceiver> = HashMap::new();
+
+        for (sdr_idx, blch) in ble_channels(&self.config)? {
+            let (tx, rx) = chunk_queue(self.config.channel_depth);
+
+            sdridx_to_sender.insert(sdr_idx, (blch, tx));
+            blch_to_receiver.insert(blch, (sdr_idx, rx));
+        }
+
+        Ok((sdridx_to_sender, blch_to_receiver))
+    }
+
+    // for SoapyHackRF
+    fn check_remain_count(raw: &soapysdr::Device) -> anyhow::Result<Option<usize>> {
+        let info = raw
+            .channel_info(soapysdr::Direction::Rx, 0)
+            .context("channel_info")?;
+        let Some(remain_count) = info.get("buffer_count") else {
+            return Ok(None);
+        };
+
+        let remain_count = remain_count.parse::<usize>()?;
+        log::trace!("remain_count: {}", remain_count);
+
+        Ok(Some(remain_count))
+    }
+
+    // read the SDR on a dedicated thread and pass each block (and the number of samples read) to `process`
+    // the last argument of `process` is true if samples were lost right before the block
+    fn wake_reader(
+        &mut self,
+        name: &str,
+        stats: Arc<StreamStats>,
+        on_error: impl Fn(StreamError) + 'static + Send + Clone,
+        mut process: impl FnMut(&mut [num_complex::Complex<f32>], usize, bool) + 'static + Send,
+    ) -> anyhow::Result<std::thread::JoinHandle<()>> {
+        let config = self.config.clone();
+        let raw = self.raw.clone();
+        let running = self.running.clone();
+        let events = self.events.clone();
+
+        let mut input = match self.file_source {
+            Some(ref path) => RxInput::open_file(path)?,
+            None => RxInput::Soapy(
+                self.raw
+                    .rx_stream_args(&[self.config.channels], self.config.stream_args().as_str())?,
+            ),
+        };
+
+        let mut buffer = vec![num_complex::Complex::default(); input.mtu()?].into_boxed_slice();
+
+        let thread_name = name.to_string();
+
+        // std::thread::spawn(move || {
+        let handle = std::thread::Builder::new()
+            .name(thread_name.clone())
+            .spawn(move || {
+                if let Err(e) = input.activate() {
+                    on_error(StreamError::Other(e));
+                    return;
+                }
+                stats.capture_started();
+
+                let mut passes = config.repeat;
+                let mut overflowed = false;
+
+                let ret: Result<(), StreamError> = (|| loop {
+                    let read = match input.read(&mut buffer) {
+                        Ok(read) => read,
+                        Err(e) if e.code == soapysdr::ErrorCode::Overflow => {
+                            log::warn!("{}: overflow ({})", thread_name, e);
+                            stats.overflows.fetch_add(1, Ordering::Relaxed);
+                            emit(&events, PipelineEvent::Overflow);
+                            overflowed = true;
+
+                            continue;
+                        }
+                        Err(e) if read_recovery(e.code, passes) == ReadRecovery::Retry => {
+                            log::debug!("{}: retrying ({})", thread_name, e);
+                            continue;
+                        }
+                        Err(e) if read_recovery(e.code, passes) == ReadRecovery::Rewind => {
+                            // end of the input: reopen the stream to start over
+                            log::info!("{}: rewinding ({})", thread_name, e);
+                            emit(&events, PipelineEvent::Rewind);
+                            passes -= 1;
+
+                            input
+                                .rewind(&raw, &config)
+                                .with_context(|| format!("{}(rewind)", thread_name))?;
+
+                            continue;
+                        }
+                        Err(e) => return Err(StreamError::DeviceRead(e)),
+                    };
+                    stats.samples.fetch_add(read, Ordering::Relaxed);
+                    emit(&events, PipelineEvent::ReadBlock { samples: read });
+
+                    if let Some(count) = Self::check_remain_count(&raw)? {
+                        emit(&events, PipelineEvent::BufferBacklog { count });
+                    }
+
+                    process(&mut buffer, read, std::mem::take(&mut overflowed));
+
+                    if !*running.lock().expect("failed to lock") {
+                        return Err(StreamError::Interrupted);
+                    }
+                })();
+
+                *running.lock().expect("failed to lock") = false;
+
+                if let Err(e) = input.deactivate() {
+                    on_error(StreamError::Other(e));
+                }
+                stats.capture_stopped();
+
+                if let Err(e) = ret {
+                    on_error(e);
+                }
+            })
+            .with_context(|| format!("{}(spawn)", name))?;
+
+        Ok(handle)
+    }
+
+    fn wake_channelizer(
+        &mut self,
+        sdridx_to_sender: HashMap<SdrIdx, RxChannelSender>,
+        stats: Arc<StreamStats>,
+        on_error: impl Fn(StreamError) + 'static + Send + Clone,
+    ) -> anyhow::Result<std::thread::JoinHandle<()>> {
+        let mut stage = ChannelizerStage::new(&self.config, sdridx_to_sender);
+        stage.spectrum = self.spectrum.clone();
+
+        let sender_stats = stats.clone();
+
+        self.wake_reader(
+            "wake_channelizer",
+            stats,
+            on_error,
+            move |buffer, read, overflowed| {
+                stage.process(&buffer[..read], overflowed, &sender_stats)
+            },
+        )
+    }
+
+    // single channel counterpart of wake_channelizer
+    fn wake_downconverter(
+        &mut self,
+        blch: BluetoothChannel,
+        tx: ChunkSender<Vec<num_complex::Complex<f32>>>,
+        stats: Arc<StreamStats>,
+        on_error: impl Fn(StreamError) + 'static + Send + Clone,
+    ) -> anyhow::Result<std::thread::JoinHandle<()>> {
+        let mut stage = DownconverterStage::new(&self.config, blch);
+        let backpressure = self.config.backpressure;
+
+        let sender_stats = stats.clone();
+
+        self.wake_reader(
+            "wake_downconverter",
+            stats,
+            on_error,
+            move |buffer, read, overflowed| {
+                if let Some(downconverted) = stage.process(&buffer[..read], overflowed) {
+                    send_or_drop(&tx, downconverted, backpressure, &sender_stats);
+                }
+            },
+        )
+    }
+
+    fn wake_synthesizer(
+        &mut self,
+        packet_source: std::sync::mpsc::Receiver<crate::bluetooth::Bluetooth>,
+        ack: std::sync::mpsc::Sender<()>,
+        on_error: impl Fn(StreamError) + 'static + Send + Clone,
+    ) -> anyhow::Result<()> {
+        let config = self.config.clone();
+        let running = self.running.clone();
+
+        let mut output = match self.file_sink {
+            Some(ref sink) => TxOutput::File(crate::device::iq_file::IqFileWriter::create(sink)?),
+            None => TxOutput::Soapy(
+                self.raw
+                    .tx_stream::<num_complex::Complex<f32>>(&[self.config.channels])?,
+            ),
+        };
+
+        let _ = std::thread::Builder::new()
+            .name("wake_synthesizer".to_string())
+            .spawn(move || {
+                let mut synthesizer = crate::channelizer::Synthesizer::new(config.num_channels);
+                let mut modulater = match crate::fsk::FskMod::new(
+                    config.sample_rate as _,
+                    config.num_channels as _,
+                ) {
+                    Ok(modulater) => modulater,
+                    Err(e) => {
+                        on_error(StreamError::Other(e));
+                        return;
+                    }
+                };
+
+                if let Err(e) = output.activate() {
+                    on_error(StreamError::Other(e));
+                    return;
+                }
+
+                let ret: Result<(), StreamError> = (|| {
+                    for packet in packet_source.iter() {
+                        if !*running.lock().expect("failed to lock") {
+                            return Err(StreamError::Interrupted);
+                        }
+
+                        let Some(sdridx) = freq_to_sdridx(&config, packet.freq) else {
+                            log::warn!("wake_synthesizer: {} MHz is out of band", packet.freq);
+                            let _ = ack.send(());
+                            continue;
+                        };
+
+                        let bits = match packet.packet.inner.to_bits(packet.freq) {
+                            Ok(bits) => bits,
+                            Err(e) => {
+                                log::warn!("wake_synthesizer: {:#}", e);
+                                let _ = ack.send(());
+                                continue;
+                            }
+                        };
+
+                        let synthesized = synthesize_bits(
+                            &mut synthesizer,
+                            &mut modulater,
+                            config.num_channels,
+                            sdridx,
+                            &bits,
+                        )?;
+
+                        output
+                            .write(&synthesized)
+                            .context("wake_synthesizer(write)")?;
+
+                        let _ = ack.send(());
+                    }
+
+                    Ok(())
+                })();
+
+                if let Err(e) = output.deactivate() {
+                    on_error(StreamError::Other(e));
+                }
+
+                if let Err(e) = ret {
+                    on_error(e);
+                }
+            });
+
+        Ok(())
+    }
+
+    fn catch_and_process(
+        &mut self,
+        rxs: HashMap<BluetoothChannel, RxChannelReceiver>,
+        stats: Arc<StreamStats>,
+
+        sender: impl Fn(crate::bluetooth::Bluetooth) + 'static + Send + Clone,
+        process_fail: impl Fn(ProcessFailKind) + 'static + Send + Clone,
+        on_error: impl Fn(StreamError) + 'static + Send + Clone,
+    ) -> anyhow::Result<Vec<std::thread::JoinHandle<()>>> {
+        let config = self.config.clone();
+        let demodulator = self.demodulator.clone();
+        let burst_dump = self.burst_dump.clone();
+        let events = self.events.clone();
+        let burst_record = self.burst_record.clone();
+        let connections = self.connections.clone();
+
+        // round-robin the channels onto the workers (one worker per channel by default)
+        let mut rxs = rxs.into_iter().collect::<Vec<_>>();
+        rxs.sort_by_key(|(blch, _)| blch.to_freq());
+
+        let num_workers = config
+            .worker_threads
+            .unwrap_or(rxs.len())
+            .clamp(1, rxs.len().max(1));
+
+        let mut pools = (0..num_workers).map(|_| Vec::new()).collect::<Vec<_>>();
+        for (i, (blch, (_sdr_idx, rx))) in rxs.into_iter().enumerate() {
+            pools[i % num_workers].push((blch, rx));
+        }
+
+        let mut handles = Vec::new();
+        for (worker, pool) in pools.into_iter().enumerate() {
+            let sender = sender.clone();
+            let process_fail = process_fail.clone();
+            let on_error = on_error.clone();
+            let stats = stats.clone();
+            let config = config.clone();
+            let demodulator = demodulator.clone();
+            let burst_dump = burst_dump.clone();
+            let events = events.clone();
+            let burst_record = burst_record.clone();
+            let connections = connections.clone();
+
+            let name = match &pool[..] {
+                [(blch, _)] => format!("catch_and_process({})", blch.to_freq()),
+                _ => format!("catch_and_process(worker {})", worker),
+            };
+
+            let handle = std::thread::Builder::new()
+                .name(name)
+                .spawn(move || {
+                    if let Some(priority) = config.worker_priority {
+                        if let Err(e) = set_worker_priority(priority) {
+                            log::warn!("{:#}", e);
+                        }
+                    }
+
+                    let new_catcher = |blch: BluetoothChannel| -> anyhow::Result<Catcher> {
+                        let mut catcher = Catcher::new(blch, config.squelch, demodulator(&config)?)
+                            .with_context(|| format!("catcher on {} MHz", blch.to_freq()))?;
+                        catcher.dump = burst_dump.clone();
+                        catcher.aa_tolerance = config.aa_tolerance;
+                        catcher.preamble_tolerance = config.preamble_tolerance;
+                        catcher.aa_filter = config.aa_filter.clone();
+                        catcher.events = events.clone();
+                        catcher.detect_channel = config.detect_channel;
+                        catcher.connections = connections.clone();
+                        if let Some((margin, ref sink)) = burst_record {
+                            catcher.record(margin, sink.clone());
+                        }
+
+                        Ok(catcher)
+                    };
+
+                    // a channel without a catcher is given up, its queue is dropped
+                    let mut channels = pool
+                        .into_iter()
+                        .filter_map(|(blch, rx)| match new_catcher(blch) {
+                            Ok(catcher) => Some((catcher, rx)),
+                            Err(e) => {
+                                on_error(StreamError::Other(e));
+                                None
+                            }
+                        })
+                        .collect::<Vec<_>>();
+
+                    // a single channel can block on recv, a pool has to poll
+                    let poll = channels.len() > 1;
+
+                    while !channels.is_empty() {
+                        let mut received = false;
+
+                        channels.retain_mut(|(catcher, rx)| {
+                            let channelized_values = if poll {
+                                match rx.try_recv() {
+                                    Ok(values) => values,
+                                    Err(std::sync::mpsc::TryRecvError::Empty) => return true,
+                                    Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                                        on_error(StreamError::ChannelClosed);
+                                        return false;
+                                    }
+                                }
+                            } else {
+                                let Ok(values) = rx.recv() else {
+                                    on_error(StreamError::ChannelClosed);
+                                    return false;
+                                };
+                                values
+                            };
+                            received = true;
+
+                            let blch = catcher.blch;
+                            catch_chunk(
+                                catcher,
+                                channelized_values,
+                                &stats,
+                                &sender,
+                                &process_fail,
+                                &on_error,
+                                || new_catcher(blch),
+                            )
+                        });
+
+                        if poll && !received {
+                            std::thread::sleep(POLL_INTERVAL);
+                        }
+                    }
+                })
+                .context("catch_and_process(spawn)")?;
+
+            handles.push(handle);
+        }
+
+        Ok(handles)
+    }
+
+    pub fn start_rx_with_error(&mut self) -> anyhow::Result<RxStream<StreamResult>> {
+        self.start_rx_with_stats(Arc::new(StreamStats::default()))
+    }
+
+    // start_rx_with_error counting into `stats` (shared by the devices of a MultiDevice)
+    fn start_rx_with_stats(
+        &mut self,
+        stats: Arc<StreamStats>,
+    ) -> anyhow::Result<RxStream<StreamResult>> {
+        // sink/source Bluetooth Packet
+
+        let (packet_sink, packet_source) = std::sync::mpsc::channel();
+        let (sdridx_to_sender, blch_to_receiver) = self.prepare_pfbch2_fsk_mpsc()?;
+
+        *self.running.lock().expect("failed to lock") = true;
+
+        let ps1 = packet_sink.clone();
+
+        let channelizer = self.wake_channelizer(sdridx_to_sender, stats.clone(), move |e| {
+            let _ = ps1.send(StreamResult::Error(e));
+        })?;
+
+        let ps2 = packet_sink.clone();
+        let ps3 = packet_sink.clone();
+        let ps4 = packet_sink.clone();
+
+        let mut handles = self.catch_and_process(
+            blch_to_receiver,
+            stats.clone(),
+            move |packet| {
+                let _ = ps2.send(StreamResult::Packet(Box::new(packet)));
+            },
+            move |fail| {
+                let _ = ps3.send(StreamResult::ProcessFail(fail));
+            },
+            move |e| {
+                let _ = ps4.send(StreamResult::Error(e));
+            },
+        )?;
+
+        handles.push(channelizer);
+
+        Ok(RxStream {
+            source: packet_source,
+            stats,
+            running: self.running.clone(),
+            handles,
+        })
+    }
+
+    // run `f` on the catcher threads for each packet; the returned stream yields errors only
+    pub fn start_rx_with_callback(
+        &mut self,
+        f: impl FnMut(crate::bluetooth::Bluetooth) + Send + 'static,
+    ) -> anyhow::Result<RxStream<StreamError>> {
+        let (error_sink, error_source) = std::sync::mpsc::channel();
+        let (sdridx_to_sender, blch_to_receiver) = self.prepare_pfbch2_fsk_mpsc()?;
+
+        *self.running.lock().expect("failed to lock") = true;
+        let stats = Arc::new(StreamStats::default());
+
+        // shared by all catcher threads
+        let f = Arc::new(std::sync::Mutex::new(f));
+
+        let es1 = error_sink.clone();
+
+        let channelizer = self.wake_channelizer(sdridx_to_sender, stats.clone(), move |e| {
+            let _ = es1.send(e);
+        })?;
+
+        let es2 = error_sink.clone();
+
+        let mut handles = self.catch_and_process(
+            blch_to_receiver,
+            stats.clone(),
+            move |packet| {
+                (f.lock().expect("failed to lock"))(packet);
+            },
+            |_fail| {},
+            move |e| {
+                let _ = es2.send(e);
+            },
+        )?;
+
+        handles.push(channelizer);
+
+        Ok(RxStream {
+            source: error_source,
+            stats,
+            running: self.running.clone(),
+            handles,
+        })
+    }
+
+    /// Receive for `duration` (or until the input ends) and report the packets
+    /// and the mean RSSI per BLE channel, e.g. before starting a long capture
+    pub fn scan(&mut self, duration: std::time::Duration) -> anyhow::Result<ScanReport> {
+        let start = std::time::Instant::now();
+        let mut stream = self.start_rx_with_error()?;
+
+        loop {
+            let timeout = duration.saturating_sub(start.elapsed());
+            match stream.recv_timeout(timeout) {
+                Ok(StreamResult::Error(e)) => log::debug!("scan: {}", e),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+
+        let elapsed = start.elapsed();
+        let mut occupancy = stream.occupancy();
+        stream.shutdown()?;
+
+        let mut channels = ChannelPlan::from_config(&self.config)
+            .ble_channels()
+            .into_iter()
+            .map(|(_, ch)| (ch, occupancy.remove(&ch).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        channels.sort_by_key(|(ch, _)| ch.to_freq());
+
+        Ok(ScanReport { elapsed, channels })
+    }
+}
+
+impl crate::device::Device {
+    /// Receive a single BLE channel with a down-converter instead of the full channelizer
+    ///
+    /// A tunable SDR is re-centered on `freq_mhz`; for the others (e.g. file input)
+    /// the channel must be within the configured band.
+    pub fn start_rx_single(
+        &mut self,
+        freq_mhz: usize,
+    ) -> anyhow::Result<RxStream<crate::bluetooth::Bluetooth>> {
+        let blch = BluetoothChannel::from_freq(freq_mhz as u32)
+            .with_context(|| format!("{} MHz is not a BLE channel", freq_mhz))?;
+
+        if self.config.driver != "file" && self.config.freq_mhz != freq_mhz {
+            self.config.freq_mhz = freq_mhz;
+            self.config.center_freq = freq_mhz as f64 * 1e6;
+
+            self.raw
+                .set_frequency(
+                    soapysdr::Direction::Rx,
+                    self.config.channels,
+                    self.config.center_freq,
+                    (),
+                )
+                .context("failed to retune")?;
+        }
+
+        let sdr_idx = ChannelPlan::from_config(&self.config)
+            .blch_to_sdr_idx(blch)
+            .with_context(|| {
+                format!(
+                    "{} MHz is out of {} MHz +/- {} MHz",
+                    freq_mhz,
+                    self.config.freq_mhz,
+                    self.config.num_channels / 2
+                )
+            })?;
+
+        let (packet_sink, packet_source) = std::sync::mpsc::channel();
+        let (tx, rx) = chunk_queue(self.config.channel_depth);
+
+        *self.running.lock().expect("failed to lock") = true;
+        let stats = Arc::new(StreamStats::default());
+
+        let downconverter = self.wake_downconverter(blch, tx, stats.clone(), |_e| {})?;
+        let mut handles = self.catch_and_process(
+            HashMap::from([(blch, (SdrIdx(sdr_idx), rx))]),
+            stats.clone(),
+            move |packet| {
+                let _ = packet_sink.send(packet);
+            },
+            |_fail| {},
+            |_e| {},
+        )?;
+
+        handles.push(downconverter);
+
+        Ok(RxStream {
+            source: packet_source,
+            stats,
+            running: self.running.clone(),
+            handles,
+        })
+    }
+}
+
+impl Drop for crate::device::Device {
+    fn drop(&mut self) {
+        *self.running.lock().expect("failed to lock") = false;
+    }
+}
+
+impl Stream for crate::device::Device {
+    fn start_rx(&mut self) -> anyhow::Result<RxStream<crate::bluetooth::Bluetooth>> {
+        // sink/source Bluetooth Packet
+
+        let (packet_sink, packet_source) = std::sync::mpsc::channel();
+        let (sdridx_to_sender, blch_to_receiver) = self.prepare_pfbch2_fsk_mpsc()?;
+
+        *self.running.lock().expect("failed to lock") = true;
+        let stats = Arc::new(StreamStats::default());
+
+        let channelizer = self.wake_channelizer(sdridx_to_sender, stats.clone(), |_e| {})?;
+        let mut handles = self.catch_and_process(
+            blch_to_receiver,
+            stats.clone(),
+            move |packet| {
+                let _ = packet_sink.send(packet);
+            },
+            |_fail| {},
+            |_e| {},
+        )?;
+
+        handles.push(channelizer);
+
+        Ok(RxStream {
+            source: packet_source,
+            stats,
+            running: self.running.clone(),
+            handles,
+        })
+    }
+
+    fn start_tx(&mut self) -> anyhow::Result<TxStream<crate::bluetooth::Bluetooth>> {
+        // sink Bluetooth Packet
+
+        let (packet_sink, packet_source) = std::sync::mpsc::channel();
+        let (tx, ack) = TxStream::new(packet_sink);
+        *self.running.lock().expect("failed to lock") = true;
+
+        self.wake_synthesizer(packet_source, ack, |e| {
+            log::error!("wake_synthesizer: {}", e);
+        })?;
+
+        Ok(tx)
+    }
+}
+
+pub enum StreamResult {
+    Packet(Box<crate::bluetooth::Bluetooth>),
+    Error(StreamError),
+    ProcessFail(ProcessFailKind),
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    /// `running` was cleared (e.g. by `RxStream::shutdown` or ctrl-c)
+    Interrupted,
+    /// the SDR (or the input file) could not be read, e.g. at the end of the file
+    DeviceRead(soapysdr::Error),
+    /// the upstream worker thread has exited
+    ChannelClosed,
+    /// a catcher panicked on the channel [MHz]; it was restarted and the channel keeps running
+    CatcherPanic {
+        freq: u32,
+        message: String,
+    },
+    Other(anyhow::Error),
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamError::Interrupted => write!(f, "Interrupted"),
+            StreamError::DeviceRead(e) => write!(f, "failed to read from the device: {}", e),
+            StreamError::ChannelClosed => write!(f, "channel closed"),
+            StreamError::CatcherPanic { freq, message } => {
+                write!(f, "catcher on {} MHz panicked: {}", freq, message)
+            }
+            StreamError::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::DeviceRead(e) => Some(e),
+            StreamError::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for StreamError {
+    fn from(e: anyhow::Error) -> Self {
+        StreamError::Other(e)
+    }
+}
+
+// how often a MultiDevice forwarder checks for shutdown while its device is idle
+const FORWARD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+impl crate::device::MultiDevice {
+    /// `Device::start_rx_with_error` on every device, merged into one stream of
+    /// (device index, result). The stats are summed over the devices.
+    pub fn start_rx_with_error(&mut self) -> anyhow::Result<RxStream<(usize, StreamResult)>> {
+        let (sink, source) = std::sync::mpsc::channel();
+        let stats = Arc::new(StreamStats::default());
+        let running = Arc::new(std::sync::Mutex::new(true));
+
+        // a connection set up on one sub-band may hop onto another
+        let connections = crate::bluetooth::Connections::default();
+
+        let mut streams = Vec::new();
+        for dev in self.devices.iter_mut() {
+            dev.connections = connections.clone();
+
+            match dev.start_rx_with_stats(stats.clone()) {
+                Ok(stream) => streams.push(stream),
+                Err(e) => {
+                    // do not leave the devices started so far running
+                    for stream in streams {
+                        let _ = stream.shutdown();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut handles = Vec::new();
+        for (i, stream) in streams.into_iter().enumerate() {
+            let sink = sink.clone();
+            let running = running.clone();
+
+            let handle = std::thread::Builder::new()
+                .name(format!("multi_device({})", i))
+                .spawn(move || {
+                    while *running.lock().expect("failed to lock") {
+                        match stream.source.recv_timeout(FORWARD_POLL_INTERVAL) {
+                            Ok(r) => {
+                                if sink.send((i, r)).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+                        }
+                    }
+
+                    if let Err(e) = stream.shutdown() {
+                        let _ = sink.send((i, StreamResult::Error(StreamError::Other(e))));
+                    }
+                })
+                .context("multi_device(spawn)")?;
+
+            handles.push(handle);
+        }
+
+        Ok(RxStream {
+            source,
+            stats,
+            running,
+            handles,
+        })
+    }
+}
+
+pub struct RxStream<ReceiveItem> {
+    source: std::sync::mpsc::Receiver<ReceiveItem>,
+    stats: Arc<StreamStats>,
+
+    // worker threads feeding `source`
+    running: Arc<std::sync::Mutex<bool>>,
+    handles: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl<T> RxStream<T> {
+    pub fn new(source: std::sync::mpsc::Receiver<T>) -> Self {
+        Self {
+            source,
+            stats: Arc::new(StreamStats::default()),
+            running: Arc::new(std::sync::Mutex::new(false)),
+            handles: Vec::new(),
+        }
+    }
+
+    pub fn stats(&self) -> StreamStatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    // the next item if one is already queued (None also once the stream has ended)
+    pub fn try_next(&mut self) -> Option<T> {
+        self.source.try_recv().ok()
+    }
+
+    // wait at most `timeout` for the next item
+    pub fn recv_timeout(
+        &mut self,
+        timeout: std::time::Duration,
+    ) -> Result<T, std::sync::mpsc::RecvTimeoutError> {
+        self.source.recv_timeout(timeout)
+    }
+
+    // per-channel burst counts and RSSI so far
+    pub fn occupancy(&self) -> HashMap<BluetoothChannel, ChannelOccupancy> {
+        self.stats.occupancy()
+    }
+
+    // true if all worker threads have exited
+    pub fn is_finished(&self) -> bool {
+        self.handles.iter().all(|h| h.is_finished())
+    }
+
+    // stop the worker threads and wait for them to exit
+    pub fn shutdown(self) -> anyhow::Result<()> {
+        *self.running.lock().expect("failed to lock") = false;
+
+        // wake_channelizer stops at the next read and drops its senders,
+        // which in turn unblocks recv() in catch_and_process
+        drop(self.source);
+
+        let mut panicked = Vec::new();
+        for handle in self.handles {
+            let name = handle.thread().name().unwrap_or("<unnamed>").to_string();
+
+            if handle.join().is_err() {
+                panicked.push(name);
+            }
+        }
+
+        if !panicked.is_empty() {
+            anyhow::bail!("thread panicked: {}", panicked.join(", "));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct TxStream<SendItem> {
+    sink: std::sync::mpsc::Sender<SendItem>,
+
+    // one () per item delivered by the consumer of `sink`
+    acks: std::sync::mpsc::Receiver<()>,
+    pending: AtomicUsize,
+}
+
+impl<T> TxStream<T> {
+    // the returned sender is for the consumer of `sink` to acknowledge each item
+    pub fn new(sink: std::sync::mpsc::Sender<T>) -> (Self, std::sync::mpsc::Sender<()>) {
+        let (ack, acks) = std::sync::mpsc::channel();
+
+        let stream = Self {
+            sink,
+            acks,
+            pending: AtomicUsize::new(0),
+        };
+
+        (stream, ack)
+    }
+
+    pub fn send(&self, item: T) -> anyhow::Result<()> {
+        self.sink
+            .send(item)
+            .map_err(|_| anyhow::anyhow!("tx stream is closed"))?;
+        self.pending.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    // wait until every item passed to `send` has been delivered
+    pub fn flush(&self) -> anyhow::Result<()> {
+        while self.pending.load(Ordering::Relaxed) > 0 {
+            self.acks
+                .recv()
+                .context("tx stream is closed before delivering")?;
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+}
+
+impl RxStream<crate::bluetooth::Bluetooth> {
+    // packets accepted by `f`; Unimplemented ones are always skipped
+    pub fn filter_inner(
+        self,
+        mut f: impl FnMut(&crate::bluetooth::PacketInner) -> bool,
+    ) -> impl Iterator<Item = crate::bluetooth::Bluetooth> {
+        self.filter(move |p| {
+            !matches!(
+                p.packet.inner,
+                crate::bluetooth::PacketInner::Unimplemented(_)
+            ) && f(&p.packet.inner)
+        })
+    }
+
+    pub fn advertisements(
+        self,
+    ) -> impl Iterator<Item = (crate::bluetooth::Advertisement, crate::bluetooth::Bluetooth)> {
+        self.filter_map(|p| match p.packet.inner {
+            crate::bluetooth::PacketInner::Advertisement(ref adv) => Some((adv.clone(), p)),
+            _ => None,
+        })
+    }
+
+    /// Packets sorted by burst timestamp.
+    ///
+    /// A packet is held until one `window` newer (in burst time) has arrived,
+    /// or for at most `max_latency` (wall clock). Packets more than `window` late are
+    /// yielded as they come. Packets without a timestamp are not delayed.
+    pub fn ordered(
+        self,
+        window: std::time::Duration,
+        max_latency: std::time::Duration,
+    ) -> OrderedRxStream {
+        OrderedRxStream {
+            stream: self,
+            window: chrono::TimeDelta::from_std(window).unwrap_or(chrono::TimeDelta::MAX),
+            max_latency,
+            pending: std::collections::BinaryHeap::new(),
+            newest: None,
+            seq: 0,
+            closed: false,
+        }
+    }
+}
+
+impl<T> std::iter::Iterator for RxStream<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.recv().ok()
+    }
+}
+
+// packet buffered by OrderedRxStream, ordered by (timestamp, arrival)
+struct PendingPacket {
+    timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    seq: usize,
+    arrived: std::time::Instant,
+    packet: crate::bluetooth::Bluetooth,
+}
+
+impl PartialEq for PendingPacket {
+    fn eq(&self, other: &Self) -> bool {
+        (self.timestamp, self.seq) == (other.timestamp, other.seq)
+    }
+}
+
+impl Eq for PendingPacket {}
+
+impl PartialOrd for PendingPacket {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingPacket {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.timestamp, self.seq).cmp(&(other.timestamp, other.seq))
+    }
+}
+
+/// See `RxStream::ordered`
+pub struct OrderedRxStream {
+    stream: RxStream<crate::bluetooth::Bluetooth>,
+
+    window: chrono::TimeDelta,
+    max_latency: std::time::Duration,
+
+    pending: std::collections::BinaryHeap<std::cmp::Reverse<PendingPacket>>,
+    // newest timestamp seen so far
+    newest: Option<chrono::DateTime<chrono::Utc>>,
+    seq: usize,
+    closed: bool,
+}
+
+impl OrderedRxStream {
+    pub fn stats(&self) -> StreamStatsSnapshot {
+        self.stream.stats()
+    }
+
+    fn push(&mut self, packet: crate::bluetooth::Bluetooth) {
+        let timestamp = packet.bytes_packet.as_ref().and_then(|b| b.timestamp);
+        self.newest = self.newest.max(timestamp);
+
+        self.pending.push(std::cmp::Reverse(PendingPacket {
+            timestamp,
+            seq: self.seq,
+            arrived: std::time::Instant::now(),
+            packet,
+        }));
+        self.seq += 1;
+    }
+
+    // time left until the longest waiting packet has to be released
+    fn time_left(&self) -> std::time::Duration {
+        let oldest = self.pending.iter().map(|p| p.0.arrived).min();
+
+        match oldest {
+            Some(arrived) => self.max_latency.saturating_sub(arrived.elapsed()),
+            None => self.max_latency,
+        }
+    }
+
+    fn head_is_ready(&self) -> bool {
+        let Some(std::cmp::Reverse(head)) = self.pending.peek() else {
+            return false;
+        };
+
+        let settled = match (head.timestamp, self.newest) {
+            (Some(timestamp), Some(newest)) => newest - timestamp >= self.window,
+            _ => true,
+        };
+
+        self.closed || settled || self.time_left().is_zero()
+    }
+}
+
+impl std::iter::Iterator for OrderedRxStream {
+    type Item = crate::bluetooth::Bluetooth;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.head_is_ready() {
+                return self.pending.pop().map(|p| p.0.packet);
+            }
+
+            if self.closed {
+                return None;
+            }
+
+            let received = if self.pending.is_empty() {
+                self.stream.source.recv().map_err(|_| ())
+            } else {
+                match self.stream.source.recv_timeout(self.time_left()) {
+                    Ok(packet) => Ok(packet),
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Err(()),
+                }
+            };
+
+            match received {
+                Ok(packet) => self.push(packet),
+                Err(()) => self.closed = true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::packets::timestamped;
+
+    // the defaults of a virtual device on `freq_mhz` (not checked, e.g. for ble_channels)
+    fn config(freq_mhz: usize) -> crate::device::sdr::SDRConfig {
+        crate::device::sdr::SDRConfig {
+            center_freq: freq_mhz as f64 * 1.0e6,
+            freq_mhz,
+            ..crate::device::sdr::SDRConfig::builder()
+                .driver("virtual")
+                .build()
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn read_recovery_rewinds_only_at_end_of_input() {
+        use soapysdr::ErrorCode;
+
+        assert_eq!(
+            read_recovery(ErrorCode::StreamError, 2),
+            ReadRecovery::Rewind
+        );
+        assert_eq!(read_recovery(ErrorCode::StreamError, 1), ReadRecovery::Fail);
+
+        // a timeout neither ends nor rewinds the input
+        assert_eq!(read_recovery(ErrorCode::Timeout, 2), ReadRecovery::Retry);
+        assert_eq!(read_recovery(ErrorCode::Timeout, 1), ReadRecovery::Retry);
+
+        assert_eq!(read_recovery(ErrorCode::Corruption, 2), ReadRecovery::Fail);
+        assert_eq!(read_recovery(ErrorCode::Other, 2), ReadRecovery::Fail);
+    }
+
+    #[test]
+    fn rx_input_binary_file() {
+        use crate::device::iq_file::{FileSink, IqFileWriter, IqFormat, IqHeader};
+
+        let path = std::env::temp_dir().join(format!("rfraptor-rx-{}", std::process::id()));
+        let sink = FileSink {
+            path: path.display().to_string(),
+            format: IqFormat::Cf32Bin,
+            header: IqHeader {
+                sample_rate: 16e6,
+                center_freq: 2427e6,
+            },
+        };
+
+        let block = (0..5)
+            .map(|i| num_complex::Complex::new(i as f32, 0.))
+            .collect::<Vec<_>>();
+        let mut writer = IqFileWriter::create(&sink).unwrap();
+        writer.write(&block).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut input = RxInput::open_file(&sink.path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // a block larger than the buffer is handed out over several reads
+        let mut buffer = [num_complex::Complex::default(); 3];
+        assert_eq!(input.read(&mut buffer).unwrap(), 3);
+        assert_eq!(buffer[..3], block[..3]);
+        assert_eq!(input.read(&mut buffer).unwrap(), 2);
+        assert_eq!(buffer[..2], block[3..]);
+
+        let end = input.read(&mut buffer).unwrap_err();
+        assert_eq!(end.code, soapysdr::ErrorCode::StreamError);
+    }
+
+    #[test]
+    fn ble_channels_in_band() {
+        let channels = ble_channels(&config(2427)).unwrap();
+        assert_eq!(channels.len(), 8);
+        assert!(channels
+            .iter()
+            .all(|(_, blch)| (2420..=2434).contains(&blch.to_freq())));
+
+        assert!(ble_channels(&config(2500)).is_err());
+    }
+
+    #[test]
+    fn ble_channels_band_edge() {
+        let edge = BluetoothChannel::from_freq(2480).unwrap();
+
+        for freq_mhz in 2460..=2500 {
+            // only even channel counts pass channel_layout
+            for num_channels in (2..=40).step_by(2) {
+                let mut c = config(freq_mhz);
+                c.num_channels = num_channels;
+
+                let Ok(channels) = ble_channels(&c) else {
+                    continue;
+                };
+
+                let outputs = channels
+                    .iter()
+                    .filter(|(_, blch)| *blch == edge)
+                    .map(|(sdr_idx, _)| sdr_idx.0)
+                    .collect::<Vec<_>>();
+                let expect = ChannelPlan::from_config(&c).freq_to_sdr_idx(2480);
+                assert_eq!(outputs, expect.into_iter().collect::<Vec<_>>());
+            }
+        }
+
+        // e.g. a 2480 MHz output from both sides of the center
+        let conflict = [(SdrIdx(3), edge), (SdrIdx(12), edge)];
+        let err = check_unique_channels(&conflict).unwrap_err();
+        assert!(err.to_string().contains("2480 MHz"), "{}", err);
+    }
+
+    #[test]
+    fn bluetooth_channel_freq() {
+        assert_eq!(BluetoothChannel::from_freq(2402).unwrap().index(), 0);
+        assert_eq!(BluetoothChannel::from_freq(2480).unwrap().index(), 39);
+        assert_eq!(BluetoothChannel::from_freq(2426).unwrap().to_freq(), 2426);
+
+        assert!(BluetoothChannel::from_freq(2400).is_none());
+        assert!(BluetoothChannel::from_freq(2427).is_none());
+        assert!(BluetoothChannel::from_freq(2482).is_none());
+    }
+
+    #[test]
+    fn capture_duration() {
+        let stats = StreamStats::default();
+        assert_eq!(stats.duration(), None);
+
+        stats.capture_started();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        stats.capture_stopped();
+
+        let snapshot = stats.snapshot();
+        assert!(snapshot.capture_end.unwrap() >= snapshot.capture_start.unwrap());
+        assert!(snapshot.duration().unwrap() >= chrono::TimeDelta::milliseconds(10));
+
+        // does not grow after the end
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(stats.duration(), snapshot.duration());
+    }
+
+    #[test]
+    fn spectrum_tap() {
+        let (tx, spectrum) = std::sync::mpsc::sync_channel(4);
+        let mut stage = ChannelizerStage::new(&config(2427), HashMap::new());
+        stage.spectrum = Some(tx);
+
+        // +3 MHz of 16 MHz
+        let tone = (0..4096)
+            .map(|n| {
+                num_complex::Complex::from_polar(
+                    0.5,
+                    2. * std::f32::consts::PI * 3. / 16. * n as f32,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        stage.process(&tone, false, &StreamStats::default());
+
+        let row = spectrum.try_recv().unwrap();
+        assert_eq!(row.len(), 16);
+
+        let peak = (0..row.len())
+            .max_by(|&a, &b| row[a].total_cmp(&row[b]))
+            .unwrap();
+        assert_eq!(peak, 3);
+        assert_eq!(ChannelPlan::new(2427, 16).sdr_idx_to_freq(peak), Some(2430));
+    }
+
+    #[test]
+    fn warmup_blocks() {
+        let config = crate::device::sdr::SDRConfig {
+            warmup_blocks: 5,
+            ..config(2427)
+        };
+
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+        let (tx, rx) = chunk_queue(16);
+        let mut stage = ChannelizerStage::new(&config, HashMap::from([(SdrIdx(15), (blch, tx))]));
+        let stats = StreamStats::default();
+
+        for _ in 0..5 {
+            let block = vec![num_complex::Complex::new(0.1, 0.); 1024];
+            stage.process(&block, false, &stats);
+        }
+        assert!(rx.try_recv().is_err());
+
+        let block = vec![num_complex::Complex::new(0.1, 0.); 1024];
+        stage.process(&block, false, &stats);
+        assert_eq!(rx.try_recv().unwrap().len(), 1024 / 8);
+    }
+
+    #[test]
+    fn energy_gate() {
+        let config = crate::device::sdr::SDRConfig {
+            energy_gate_db: Some(-40.),
+            ..config(2427)
+        };
+
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+        let (tx, rx) = chunk_queue(16);
+        let mut stage = ChannelizerStage::new(&config, HashMap::from([(SdrIdx(15), (blch, tx))]));
+        let stats = StreamStats::default();
+
+        let silence = vec![num_complex::Complex::default(); 1024];
+        stage.process(&silence, false, &stats);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(stats.snapshot().gated, 1);
+
+        // -20 dBFS
+        let signal = vec![num_complex::Complex::new(0.1, 0.); 1024];
+        stage.process(&signal, false, &stats);
+        assert_eq!(rx.try_recv().unwrap().len(), 1024 / 8);
+        assert_eq!(stats.snapshot().gated, 1);
+    }
+
+    #[test]
+    fn partial_chunks() {
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+        let (tx, rx) = chunk_queue(64);
+        let mut stage =
+            ChannelizerStage::new(&config(2427), HashMap::from([(SdrIdx(15), (blch, tx))]));
+        let stats = StreamStats::default();
+
+        // 13 is not a multiple of num_channels / 2 = 8
+        let block = vec![num_complex::Complex::new(0.1, 0.); 13];
+        for _ in 0..10 {
+            stage.process(&block, false, &stats);
+        }
+
+        let channelized = rx.try_iter().map(|v| v.len()).sum::<usize>();
+        assert_eq!(channelized, 13 * 10 / 8);
+
+        // the leftover is dropped across an overflow
+        stage.process(&block[..7], true, &stats);
+        assert_eq!(rx.try_recv().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn downconverter_partial_blocks() {
+        let blch = BluetoothChannel::from_freq(2430).unwrap();
+        let tone = (0..16 * 64)
+            .map(|i| num_complex::Complex::from_polar(0.5, 0.3 * i as f32))
+            .collect::<Vec<_>>();
+
+        let mut whole = DownconverterStage::new(&config(2427), blch);
+        let expected = whole.process(&tone, false).unwrap();
+        assert_eq!(expected.len(), tone.len() / 8);
+
+        // 13 is not a multiple of the decimation (num_channels / 2 = 8): the tail is carried
+        let mut stage = DownconverterStage::new(&config(2427), blch);
+        let split = tone
+            .chunks(13)
+            .flat_map(|block| stage.process(block, false).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(split.len(), expected.len());
+        for (a, b) in split.iter().zip(&expected) {
+            assert!((a - b).norm() < 1e-4);
+        }
+
+        // the leftover is dropped across an overflow
+        assert_eq!(stage.process(&tone[..7], true).unwrap().len(), 0);
+        assert_eq!(stage.process(&tone[..1], false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn burst_across_blocks() {
+        let num_channels = 16;
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+        let adv = crate::bluetooth::Advertisement {
+            pdu_header: crate::bluetooth::PDUHeader {
+                pdu_type: crate::bluetooth::PDUType::AdvNonconnInd,
+                rfu: false,
+                ch_sel: false,
+                tx_add: false,
+                rx_add: false,
+            },
+            length: 6 + 3,
+            address: "12:34:56:00:01:00".parse().unwrap(),
+            data: vec![crate::bluetooth::AdvData::new(0x01, &[0x06])],
+        };
+
+        let bits =
+            crate::bitops::pdu_to_bits(&adv.encode(), 2426, crate::bluetooth::ADV_ACCESS_ADDRESS);
+        let synthesized = synthesize_bits(
+            &mut crate::channelizer::Synthesizer::new(num_channels),
+            &mut crate::fsk::FskMod::new(16e6, num_channels as _).unwrap(),
+            num_channels,
+            SdrIdx(15),
+            &bits,
+        )
+        .unwrap();
+
+        // two reads splitting the burst, off the channelizer input boundary
+        let (tx, rx) = chunk_queue(16);
+        let mut stage =
+            ChannelizerStage::new(&config(2427), HashMap::from([(SdrIdx(15), (blch, tx))]));
+        let stats = StreamStats::default();
+
+        let split = synthesized.len() / 2 + 3;
+        stage.process(&synthesized[..split], false, &stats);
+        stage.process(&synthesized[split..], false, &stats);
+
+        let chunks = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 2);
+
+        let mut catcher = Catcher::new(
+            blch,
+            crate::burst::BurstParams::default(),
+            Box::new(crate::fsk::FskDemod::new(16e6, num_channels).unwrap()),
+        )
+        .unwrap();
+        // the demodulated preamble of this burst has one bit error
+        catcher.preamble_tolerance = 1;
+
+        let packets = std::cell::RefCell::new(Vec::new());
+        let sender = |bt| packets.borrow_mut().push(bt);
+
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            catch_chunk(
+                &mut catcher,
+                chunk,
+                &stats,
+                &sender,
+                &|_| {},
+                &|e| panic!("{:?}", e),
+                || unreachable!(),
+            );
+
+            // the squelch stays open across the recv boundary
+            if i == 0 {
+                assert!(catcher.burst.in_burst);
+                assert!(packets.borrow().is_empty());
+            }
+        }
+
+        let packets = packets.into_inner();
+        assert_eq!(packets.len(), 1);
+        let crate::bluetooth::PacketInner::Advertisement(ref received) = packets[0].packet.inner
+        else {
+            panic!("unexpected packet: {:?}", packets[0].packet.inner);
+        };
+        assert_eq!(received.address, adv.address);
+    }
+
+    #[test]
+    fn channel_index() {
+        let index = |freq| BluetoothChannel::from_freq(freq).unwrap().channel_index();
+
+        assert_eq!(index(2402), 37);
+        assert_eq!(index(2426), 38);
+        assert_eq!(index(2480), 39);
+
+        for i in 0..=36 {
+            assert_eq!(
+                BluetoothChannel::from_data_channel(i)
+                    .unwrap()
+                    .channel_index(),
+                i
+            );
+        }
+
+        for i in 0..=39 {
+            assert_eq!(
+                BluetoothChannel::from_channel_index(i)
+                    .unwrap()
+                    .channel_index(),
+                i
+            );
+        }
+        assert_eq!(BluetoothChannel::from_channel_index(40), None);
+
+        // every RF channel has exactly one channel index
+        for freq in (2402..=2480).step_by(2) {
+            let ch = BluetoothChannel::from_channel_index(index(freq)).unwrap();
+            assert_eq!(ch.to_freq(), freq);
+        }
+    }
+
+    #[test]
+    fn data_channel_freq() {
+        let freq = |i| BluetoothChannel::from_data_channel(i).map(|ch| ch.to_freq());
+
+        assert_eq!(freq(0), Some(2404));
+        assert_eq!(freq(10), Some(2424));
+        assert_eq!(freq(11), Some(2428));
+        assert_eq!(freq(36), Some(2478));
+        assert_eq!(freq(37), None);
+    }
+
+    #[test]
+    fn channel_plan_2427() {
+        let plan = ChannelPlan::new(2427, 16);
+
+        assert_eq!(plan.sdr_idx_to_freq(0), Some(2427));
+        assert_eq!(plan.sdr_idx_to_freq(7), Some(2434));
+        assert_eq!(plan.sdr_idx_to_freq(8), Some(2419));
+        assert_eq!(plan.sdr_idx_to_freq(15), Some(2426));
+        assert_eq!(plan.sdr_idx_to_freq(16), None);
+
+        assert_eq!(plan.freq_to_sdr_idx(2419), Some(8));
+        assert_eq!(plan.freq_to_sdr_idx(2434), Some(7));
+        assert_eq!(plan.freq_to_sdr_idx(2418), None);
+        assert_eq!(plan.freq_to_sdr_idx(2435), None);
+
+        let channels = plan.ble_channels();
+        let freqs = channels
+            .iter()
+            .map(|(_, blch)| blch.to_freq())
+            .collect::<Vec<_>>();
+        assert_eq!(freqs, [2428, 2430, 2432, 2434, 2420, 2422, 2424, 2426]);
+
+        for (sdr_idx, blch) in channels {
+            assert_eq!(plan.blch_to_sdr_idx(blch), Some(sdr_idx));
+        }
+    }
+
+    #[test]
+    fn channel_plan_edge() {
+        let plan = ChannelPlan::new(2480, 16);
+
+        // 2482..=2487 are out of the BLE band
+        let channels = plan.ble_channels();
+        assert_eq!(
+            channels
+                .iter()
+                .map(|(i, blch)| (*i, blch.index()))
+                .collect::<Vec<_>>(),
+            [(0, 39), (8, 35), (10, 36), (12, 37), (14, 38)]
+        );
+
+        assert_eq!(plan.freq_to_sdr_idx(2480), Some(0));
+        assert_eq!(plan.freq_to_sdr_idx(2472), Some(8));
+        assert_eq!(plan.freq_to_sdr_idx(2488), None);
+    }
+
+    #[test]
+    fn slow_consumer_drops() {
+        let stats = StreamStats::default();
+        let (tx, rx) = chunk_queue(4);
+
+        for i in 0..1000 {
+            send_or_drop(&tx, vec![i; 1024], Backpressure::Drop, &stats);
+        }
+
+        // the newest chunks are kept
+        assert_eq!(stats.snapshot().dropped, 1000 - 4);
+        let kept = rx.try_iter().map(|chunk| chunk[0]).collect::<Vec<_>>();
+        assert_eq!(kept, vec![996, 997, 998, 999]);
+
+        // space is available again once the consumer catches up
+        send_or_drop(&tx, vec![0; 1024], Backpressure::Drop, &stats);
+        assert_eq!(stats.snapshot().dropped, 1000 - 4);
+    }
+
+    #[test]
+    fn chunk_queue_block_and_close() {
+        let (tx, rx) = chunk_queue(1);
+        tx.send(0).unwrap();
+
+        // the second send waits for the consumer
+        let consumer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            let first = rx.recv().unwrap();
+            (first, rx)
+        });
+        tx.send(1).unwrap();
+
+        let (first, rx) = consumer.join().unwrap();
+        assert_eq!(first, 0);
+
+        // the queued chunks are still received after the sender is gone
+        drop(tx);
+        assert_eq!(rx.recv(), Ok(1));
+        assert!(rx.recv().is_err());
+
+        let (tx, rx) = chunk_queue(1);
+        drop(rx);
+        assert_eq!(tx.send(0), Err(0));
+        assert_eq!(tx.send_drop_oldest(0), Err(0));
+    }
+
+    // ignores the burst and returns the bits of a fixed advertisement
+    struct MockDemod {
+        bits: Vec<u8>,
+    }
+
+    impl crate::fsk::Demodulator for MockDemod {
+        fn demodulate(
+            &mut self,
+            burst: crate::burst::Packet,
+        ) -> anyhow::Result<crate::fsk::Packet> {
+            Ok(crate::fsk::Packet {
+                raw: Some(burst),
+                bits: self.bits.clone(),
+                demod: Vec::new(),
+                cfo: 0.0,
+                deviation: 1.0,
+                snr: 1.0,
+                symbol_start: 0,
+                sample_per_symbol: 1.,
+            })
+        }
+    }
+
+    // MockDemod returning an ADV_IND (4 bits are left after its CRC, see bitops::tests)
+    fn adv_demod() -> MockDemod {
+        let payload = [0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x02, 0x01, 0x06];
+
+        MockDemod {
+            bits: crate::bitops::packet_to_bits(
+                &payload,
+                2426,
+                crate::bluetooth::ADV_ACCESS_ADDRESS,
+            ),
+        }
+    }
+
+    // noise floor, a burst of `len` samples and the floor again to close it
+    fn burst_samples(len: usize) -> Vec<num_complex::Complex<f32>> {
+        let floor = num_complex::Complex::new(3e-3, 0.0);
+        let tone = num_complex::Complex::new(0.5, 0.0);
+
+        std::iter::repeat(floor)
+            .take(1000)
+            .chain(std::iter::repeat(tone).take(len))
+            .chain(std::iter::repeat(floor).take(1000))
+            .collect()
+    }
+
+    // packets and failures (except for the samples outside of a burst) of `catcher`
+    fn feed_burst(
+        catcher: &mut Catcher,
+        len: usize,
+        stats: &StreamStats,
+    ) -> (Vec<crate::bluetooth::Bluetooth>, Vec<ProcessFailKind>) {
+        let mut packets = Vec::new();
+        let mut fails = Vec::new();
+
+        for s in burst_samples(len) {
+            match catcher.catch(s, stats) {
+                Ok(bt) => packets.push(bt),
+                Err(ProcessFailKind::Catcher) => {}
+                Err(e) => fails.push(e),
+            }
+        }
+
+        (packets, fails)
+    }
+
+    #[test]
+    fn catcher_with_mock_demodulator() {
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+
+        let mut catcher = Catcher::new(
+            blch,
+            crate::burst::BurstParams::default(),
+            Box::new(adv_demod()),
+        )
+        .unwrap();
+        let stats = StreamStats::default();
+
+        let (packets, _) = feed_burst(&mut catcher, 400, &stats);
+        assert_eq!(packets.len(), 1);
+
+        let byte_packet = packets[0].bytes_packet.as_ref().unwrap();
+        assert_eq!(byte_packet.aa, crate::bluetooth::ADV_ACCESS_ADDRESS);
+        assert_eq!(byte_packet.freq, 2426);
+        assert!(packets[0].packet.crc_valid);
+
+        assert_eq!(stats.occupancy()[&blch].bursts, 1);
+    }
+
+    #[test]
+    fn catcher_aa_filter() {
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+
+        let results = |aa_filter: &[u32]| {
+            let mut catcher = Catcher::new(
+                blch,
+                crate::burst::BurstParams::default(),
+                Box::new(adv_demod()),
+            )
+            .unwrap();
+            catcher.aa_filter = Some(aa_filter.iter().copied().collect());
+
+            let (packets, fails) = feed_burst(&mut catcher, 400, &StreamStats::default());
+            let filtered = fails
+                .iter()
+                .filter(|e| matches!(e, ProcessFailKind::Filtered))
+                .count();
+
+            (packets.len(), filtered)
+        };
+
+        assert_eq!(
+            results(&[0x12345678, crate::bluetooth::ADV_ACCESS_ADDRESS]),
+            (1, 0)
+        );
+        assert_eq!(results(&[0x12345678]), (0, 1));
+    }
+
+    #[test]
+    fn catcher_remain_bits_event() {
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+
+        let mut catcher = Catcher::new(
+            blch,
+            crate::burst::BurstParams::default(),
+            Box::new(adv_demod()),
+        )
+        .unwrap();
+        let (tx, events) = std::sync::mpsc::sync_channel(4);
+        catcher.events = Some(tx);
+
+        let (packets, _) = feed_burst(&mut catcher, 400, &StreamStats::default());
+        assert_eq!(packets.len(), 1);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![PipelineEvent::RemainBits { freq: 2426, len: 4 }]
+        );
+    }
+
+    // panics on the first burst
+    struct PanicDemod;
+
+    impl crate::fsk::Demodulator for PanicDemod {
+        fn demodulate(&mut self, _: crate::burst::Packet) -> anyhow::Result<crate::fsk::Packet> {
+            panic!("mock demodulator panicked");
+        }
+    }
+
+    #[test]
+    fn catcher_panic_restarts() {
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+
+        let mut catcher = Catcher::new(
+            blch,
+            crate::burst::BurstParams::default(),
+            Box::new(PanicDemod),
+        )
+        .unwrap();
+        let stats = StreamStats::default();
+
+        let packets = std::cell::RefCell::new(Vec::new());
+        let errors = std::cell::RefCell::new(Vec::new());
+        let sender = |bt| packets.borrow_mut().push(bt);
+        let on_error = |e| errors.borrow_mut().push(e);
+
+        let restart = || {
+            Catcher::new(
+                blch,
+                crate::burst::BurstParams::default(),
+                Box::new(adv_demod()),
+            )
+        };
+
+        catch_chunk(
+            &mut catcher,
+            burst_samples(400),
+            &stats,
+            &sender,
+            &|_| {},
+            &on_error,
+            restart,
+        );
+        assert!(packets.borrow().is_empty());
+        assert!(matches!(
+            &errors.borrow()[..],
+            [StreamError::CatcherPanic { freq: 2426, message }] if message == "mock demodulator panicked"
+        ));
+        assert_eq!(stats.snapshot().panics, 1);
+
+        // the restarted catcher keeps decoding
+        catch_chunk(
+            &mut catcher,
+            burst_samples(400),
+            &stats,
+            &sender,
+            &|_| {},
+            &on_error,
+            || unreachable!(),
+        );
+        assert_eq!(packets.borrow().len(), 1);
+        assert_eq!(errors.borrow().len(), 1);
+        assert_eq!(stats.snapshot().packets, 1);
+    }
+
+    #[test]
+    fn short_burst_is_dumped() {
+        let blch = BluetoothChannel::from_freq(2426).unwrap();
+        let mock = MockDemod { bits: Vec::new() };
+
+        let mut catcher =
+            Catcher::new(blch, crate::burst::BurstParams::default(), Box::new(mock)).unwrap();
+        let (dump, dumped) = std::sync::mpsc::channel();
+        catcher.dump = Some(dump);
+
+        let before = chrono::Utc::now();
+
+        let (_, fails) = feed_burst(&mut catcher, 20, &StreamStats::default());
+        assert_eq!(
+            fails
+                .iter()
+                .filter(|e| matches!(e, ProcessFailKind::TooShort))
+                .count(),
+            1
+        );
+
+        let (timestamp, data) = dumped.try_recv().unwrap();
+        assert!(data.len() < 132);
+        assert!(timestamp >= before && timestamp <= chrono::Utc::now());
+        assert!(dumped.try_recv().is_err());
+    }
+
+    #[test]
+    fn flush_waits_for_delivery() {
+        let (sink, source) = std::sync::mpsc::channel();
+        let (tx, ack) = TxStream::new(sink);
+
+        let received = Arc::new(AtomicUsize::new(0));
+
+        // virtual peer which takes a while to pick up each item
+        let peer = {
+            let received = received.clone();
+
+            std::thread::spawn(move || {
+                for _item in source.iter() {
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    received.fetch_add(1, Ordering::Relaxed);
+                    ack.send(()).unwrap();
+                }
+            })
+        };
+
+        tx.send(1u32).unwrap();
+        tx.send(2u32).unwrap();
+        tx.flush().unwrap();
+        assert_eq!(received.load(Ordering::Relaxed), 2);
+
+        // nothing pending
+        tx.flush().unwrap();
+
+        drop(tx);
+        peer.join().unwrap();
+    }
+
+    #[test]
+    fn flush_fails_without_peer() {
+        let (sink, source) = std::sync::mpsc::channel();
+        let (tx, ack) = TxStream::new(sink);
+
+        tx.send(1u32).unwrap();
+        drop(ack);
+
+        assert!(tx.flush().is_err());
+        drop(source);
+        assert!(tx.send(2u32).is_err());
+    }
+
+    #[test]
+    fn synthesized_length() {
+        let num_channels = 16;
+        let adv = crate::bluetooth::Advertisement {
+            pdu_header: crate::bluetooth::PDUHeader {
+                pdu_type: crate::bluetooth::PDUType::AdvNonconnInd,
+                rfu: false,
+                ch_sel: false,
+                tx_add: false,
+                rx_add: false,
+            },
+            length: 6 + 3,
+            address: "12:34:56:00:01:00".parse().unwrap(),
+            data: vec![crate::bluetooth::AdvData::new(0x01, &[0x06])],
+        };
+
+        let bits =
+            crate::bitops::pdu_to_bits(&adv.encode(), 2426, crate::bluetooth::ADV_ACCESS_ADDRESS);
+        let modulated = crate::fsk::FskMod::new(16e6, num_channels as _)
+            .unwrap()
+            .modulate(&bits)
+            .unwrap();
+
+        let synthesized = synthesize_bits(
+            &mut crate::channelizer::Synthesizer::new(num_channels),
+            &mut crate::fsk::FskMod::new(16e6, num_channels as _).unwrap(),
+            num_channels,
+            SdrIdx(7),
+            &bits,
+        )
+        .unwrap();
+
+        assert_eq!(
+            synthesized.len(),
+            (modulated.len() + 2 * TX_PADDING) * num_channels / 2
+        );
+        assert!(synthesized.iter().any(|s| s.norm() > 0.));
+    }
+
+    fn millis(p: &crate::bluetooth::Bluetooth) -> i64 {
+        p.bytes_packet
+            .as_ref()
+            .unwrap()
+            .timestamp
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn ordered_within_window() {
+        let (sink, source) = std::sync::mpsc::channel();
+
+        // no packet is more than 5 ms late
+        for ms in [2, 0, 1, 4, 3, 6, 5, 9, 7, 8, 30] {
+            sink.send(timestamped(ms)).unwrap();
+        }
+
+        let mut ordered = RxStream::new(source).ordered(
+            std::time::Duration::from_millis(5),
+            std::time::Duration::from_secs(60),
+        );
+
+        // released by the packet at 30 ms while `sink` is still open
+        let sorted = ordered
+            .by_ref()
+            .take(10)
+            .map(|p| millis(&p))
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, (0..10).collect::<Vec<_>>());
+
+        drop(sink);
+        assert_eq!(ordered.map(|p| millis(&p)).collect::<Vec<_>>(), vec![30]);
+    }
+
+    #[test]
+    fn try_next() {
+        let (sink, source) = std::sync::mpsc::channel();
+        let mut stream = RxStream::new(source);
+
+        assert_eq!(stream.try_next(), None);
+
+        sink.send(1).unwrap();
+        assert_eq!(stream.try_next(), Some(1));
+        assert_eq!(stream.try_next(), None);
+
+        assert_eq!(
+            stream.recv_timeout(std::time::Duration::from_millis(10)),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout)
+        );
+        sink.send(2).unwrap();
+        assert_eq!(
+            stream.recv_timeout(std::time::Duration::from_millis(10)),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn ordered_max_latency() {
+        let (sink, source) = std::sync::mpsc::channel();
+        sink.send(timestamped(0)).unwrap();
+
+        let mut ordered = RxStream::new(source).ordered(
+            std::time::Duration::from_secs(3600),
+            std::time::Duration::from_millis(50),
+        );
+
+        let start = std::time::Instant::now();
+        assert_eq!(millis(&ordered.next().unwrap()), 0);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+
+        drop(sink);
+        assert!(ordered.next().is_none());
     }
 }
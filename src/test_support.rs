@@ -0,0 +1,135 @@
+// helpers shared by the unit tests
+
+pub mod packets;
+
+use num_complex::Complex;
+use rand::prelude::*;
+
+/// Add white gaussian noise at `snr_db` relative to the mean power of `signal`
+pub fn awgn(rng: &mut impl Rng, signal: &mut [Complex<f32>], snr_db: f32) {
+    let power = signal.iter().map(|s| s.norm_sqr()).sum::<f32>() / signal.len().max(1) as f32;
+    let sigma = (power / 10f32.powf(snr_db / 10.) / 2.).sqrt();
+
+    for s in signal.iter_mut() {
+        // Box-Muller
+        let u1 = rng.gen_range(f32::EPSILON..1.0);
+        let u2 = rng.gen_range(0.0..1.0);
+        let r = (-2. * u1.ln()).sqrt() * sigma;
+        let theta = 2. * std::f32::consts::PI * u2;
+
+        *s += Complex::new(r * theta.cos(), r * theta.sin());
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BerPoint {
+    pub snr_db: f32,
+    /// bit errors over AA + PDU + CRC, a lost packet counts as half of its bits
+    pub ber: f32,
+    /// packets which `bits_to_packet` could not recover
+    pub lost: usize,
+}
+
+/// FskMod -> AWGN -> FskDemod -> bits_to_packet for `trials` random advertisements per SNR
+pub fn ber_sweep(snrs_db: &[f32], trials: usize, seed: u64) -> Vec<BerPoint> {
+    const FREQ: usize = 2426;
+    const NUM_CHANNELS: usize = 16;
+    const SAMPLE_RATE: f32 = NUM_CHANNELS as f32 * 1e6;
+
+    let mut rng = SmallRng::seed_from_u64(seed);
+
+    let mut modulater = crate::fsk::FskMod::new(SAMPLE_RATE, NUM_CHANNELS as _).unwrap();
+    let mut demodulater = crate::fsk::FskDemod::new(SAMPLE_RATE, NUM_CHANNELS).unwrap();
+
+    snrs_db
+        .iter()
+        .map(|&snr_db| {
+            let mut errors = 0;
+            let mut total = 0;
+            let mut lost = 0;
+
+            for _ in 0..trials {
+                let payload = (0..6 + 16).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+                let pdu = [&[0, payload.len() as u8], &payload[..]].concat();
+                let expected = packets::adv_bytes(&pdu);
+
+                let bits = crate::bitops::packet_to_bits(
+                    &payload,
+                    FREQ,
+                    crate::bluetooth::ADV_ACCESS_ADDRESS,
+                );
+                let mut signal = modulater.modulate(&bits).expect("modulate failed");
+                awgn(&mut rng, &mut signal, snr_db);
+
+                total += expected.len() * 8;
+
+                let received = demodulater
+                    .demodulate_signal(&signal)
+                    .and_then(|p| crate::bitops::bits_to_packet(&p.bits, FREQ));
+
+                match received {
+                    Ok(p) if p.bytes.len() == expected.len() => {
+                        errors += p
+                            .bytes
+                            .iter()
+                            .zip(&expected)
+                            .map(|(a, b)| (a ^ b).count_ones() as usize)
+                            .sum::<usize>();
+                    }
+                    _ => {
+                        lost += 1;
+                        errors += expected.len() * 8 / 2;
+                    }
+                }
+            }
+
+            BerPoint {
+                snr_db,
+                ber: errors as f32 / total.max(1) as f32,
+                lost,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn awgn_power() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut signal = vec![Complex::new(1.0, 0.0); 100_000];
+
+        awgn(&mut rng, &mut signal, 10.);
+
+        let noise = signal
+            .iter()
+            .map(|s| (s - Complex::new(1.0, 0.0)).norm_sqr())
+            .sum::<f32>()
+            / signal.len() as f32;
+        assert!((noise - 0.1).abs() < 0.005, "noise power {}", noise);
+    }
+
+    #[test]
+    fn ber_vs_snr() {
+        const TRIALS: usize = 20;
+
+        let snrs = [30., 20., 17., 14., 11., 8., 5.];
+        let points = ber_sweep(&snrs, TRIALS, 0);
+
+        // error free down to 17 dB, most packets are lost from 8 dB
+        for p in points.iter().filter(|p| p.snr_db >= 17.) {
+            assert_eq!((p.ber, p.lost), (0., 0), "{:?}", p);
+        }
+        for p in points.iter().filter(|p| p.snr_db <= 8.) {
+            assert!(TRIALS / 2 < p.lost && 0.25 < p.ber, "{:?}", p);
+        }
+
+        for w in points.windows(2) {
+            assert!(w[0].ber <= w[1].ber, "{:?}", w);
+        }
+        assert!(points[0].ber < points[points.len() - 1].ber);
+    }
+}
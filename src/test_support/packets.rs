@@ -0,0 +1,49 @@
+// packet fixtures shared by the unit tests (the demo's tests include this file as well)
+
+/// ADV_IND from 12:34:56:00:01:00 without AdvData
+pub const ADV_PDU: [u8; 8] = [0x00, 0x06, 0x00, 0x01, 0x00, 0x56, 0x34, 0x12];
+
+/// Advertising access address + `pdu` + its CRC
+pub fn adv_bytes(pdu: &[u8]) -> Vec<u8> {
+    let mut bytes = crate::bluetooth::ADV_ACCESS_ADDRESS.to_le_bytes().to_vec();
+    bytes.extend_from_slice(pdu);
+    bytes.extend_from_slice(&crate::bitops::crc24(pdu, crate::bitops::ADV_CRC_INIT));
+
+    bytes
+}
+
+/// `bytes` (AA + PDU + CRC) received on 2426 MHz, without timestamp or RSSI
+pub fn byte_packet(bytes: Vec<u8>) -> crate::bitops::BytePacket {
+    crate::bitops::BytePacket {
+        raw: None,
+        bytes,
+        aa: crate::bluetooth::ADV_ACCESS_ADDRESS,
+        freq: 2426,
+        delta: 0,
+        offset: 0,
+        remain_bits: Vec::new(),
+        bits: None,
+        timestamp: None,
+        rssi_average: None,
+    }
+}
+
+/// Advertising `pdu` received on `freq` [MHz]
+pub fn adv_packet(pdu: &[u8], freq: usize) -> crate::bluetooth::Bluetooth {
+    let byte_packet = crate::bitops::BytePacket {
+        freq,
+        ..byte_packet(adv_bytes(pdu))
+    };
+
+    crate::bluetooth::Bluetooth::from_bytes(byte_packet, freq).unwrap()
+}
+
+/// `ADV_PDU` on 2426 MHz with a burst timestamp of `ms` [ms]
+pub fn timestamped(ms: i64) -> crate::bluetooth::Bluetooth {
+    let byte_packet = crate::bitops::BytePacket {
+        timestamp: chrono::DateTime::from_timestamp_millis(ms),
+        ..byte_packet(adv_bytes(&ADV_PDU))
+    };
+
+    crate::bluetooth::Bluetooth::from_bytes(byte_packet, 2426).unwrap()
+}
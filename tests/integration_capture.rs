@@ -0,0 +1,40 @@
+#[test]
+fn capture_file_device() {
+    let dir = std::env::temp_dir();
+    let config = dir.join(format!("rfraptor-capture-{}.yaml", std::process::id()));
+    let output = dir.join(format!("rfraptor-capture-{}.pcap", std::process::id()));
+
+    std::fs::write(
+        &config,
+        "devices:\n- !File\n  direction: Rx\n  path: tests/test_sample_rx.txt\n",
+    )
+    .unwrap();
+
+    let run = std::process::Command::new(env!("CARGO_BIN_EXE_capture"))
+        .arg("--path")
+        .arg(&config)
+        .arg("--output")
+        .arg(&output)
+        .arg("--count")
+        .arg("2")
+        .output()
+        .unwrap();
+
+    std::fs::remove_file(&config).unwrap();
+
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    assert!(
+        run.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&run.stderr)
+    );
+    assert!(stdout.contains("2 packets written"));
+
+    let pcap = std::fs::read(&output).unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    // global header, then the packets
+    assert!(pcap.len() > 24);
+    assert_eq!(pcap[..4], 0xa1b2c3d4u32.to_le_bytes());
+}
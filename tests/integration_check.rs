@@ -0,0 +1,31 @@
+#[test]
+fn check_file_device() {
+    let path = std::env::temp_dir().join(format!("rfraptor-check-{}.yaml", std::process::id()));
+
+    std::fs::write(
+        &path,
+        "devices:\n- !File\n  direction: Rx\n  path: tests/test_sample_rx.txt\n",
+    )
+    .unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rfraptor"))
+        .arg("--path")
+        .arg(&path)
+        .arg("--check")
+        .output()
+        .unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert!(stdout.contains("path: \"tests/test_sample_rx.txt\""));
+    assert!(stdout.contains("driver: file"));
+    assert!(!stdout.contains("streams:"));
+}
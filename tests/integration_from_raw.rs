@@ -0,0 +1,61 @@
+use rfraptor::*;
+
+// the defaults of a virtual device on `freq_mhz` (not checked, from_raw has to)
+fn config(freq_mhz: usize) -> device::sdr::SDRConfig {
+    device::sdr::SDRConfig {
+        center_freq: freq_mhz as f64 * 1.0e6,
+        freq_mhz,
+        ..device::sdr::SDRConfig::builder()
+            .driver("virtual")
+            .build()
+            .unwrap()
+    }
+}
+
+#[test]
+fn test_from_raw_virtual() {
+    let raw = soapysdr::Device::new("driver=virtual").expect("Failed to open device");
+    let dev = device::Device::from_raw(raw, config(2440)).expect("Failed to use device");
+
+    let applied = dev.config.read_back(&dev.raw).expect("Failed to read back");
+    assert!(applied.iter().all(|a| a.mismatches(&dev.config).is_empty()));
+
+    // no BLE channel around 2300 MHz
+    let raw = soapysdr::Device::new("driver=virtual").expect("Failed to open device");
+    assert!(device::Device::from_raw(raw, config(2300)).is_err());
+}
+
+#[test]
+fn test_readback_virtual() {
+    let config = device::config::List {
+        devices: vec![device::config::Device::Virtual {
+            direction: "RxTx".to_string(),
+            freq_mhz: Some(2440),
+            sample_rate_hz: None,
+            num_channels: None,
+            worker_threads: None,
+            worker_priority: None,
+            stream_buffers: None,
+            aa_tolerance: None,
+        }],
+    };
+
+    let dev = device::open_device(config).expect("Failed to open device");
+    let readback = dev[0].readback().expect("Failed to read back");
+
+    let directions = readback
+        .applied
+        .iter()
+        .map(|a| a.direction)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        directions,
+        vec![soapysdr::Direction::Rx, soapysdr::Direction::Tx]
+    );
+
+    for applied in &readback.applied {
+        assert_eq!(applied.center_freq, 2440e6);
+        assert_eq!(applied.sample_rate, dev[0].config.sample_rate);
+    }
+    assert_eq!(readback.drift(&dev[0].config), Vec::<String>::new());
+}
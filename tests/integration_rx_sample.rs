@@ -1,6 +1,24 @@
 use rfraptor::stream::Stream;
 use rfraptor::*;
 
+// File source of the sample capture with the defaults
+// (an enum variant has no struct update syntax, the tests override a field through a `let`)
+fn file_rx_config() -> device::config::Device {
+    device::config::Device::File {
+        direction: "Rx".to_string(),
+        path: "tests/test_sample_rx.txt".to_string(),
+        freq_mhz: None,
+        sample_rate_hz: None,
+        num_channels: None,
+        repeat: None,
+        worker_threads: None,
+        worker_priority: None,
+        stream_buffers: None,
+        aa_tolerance: None,
+        detect_channel: None,
+    }
+}
+
 #[test]
 fn test_sample_rx() {
     env_logger::builder()
@@ -10,10 +28,7 @@ fn test_sample_rx() {
     soapysdr::configure_logging();
 
     let config = device::config::List {
-        devices: vec![device::config::Device::File {
-            direction: "Rx".to_string(),
-            path: "tests/test_sample_rx.txt".to_string(),
-        }],
+        devices: vec![file_rx_config()],
     };
 
     let mut rx = device::open_device(config).expect("Failed to open device");
@@ -45,3 +60,430 @@ fn test_sample_rx() {
         }
     }
 }
+
+#[test]
+fn test_sample_rx_multi_device() {
+    let config = device::config::List {
+        devices: vec![file_rx_config(), file_rx_config()],
+    };
+
+    let devices = device::open_device(config).expect("Failed to open device");
+    let mut multi = device::MultiDevice::new(devices);
+
+    let mut stream = multi.start_rx_with_error().expect("Failed to start rx");
+
+    let mut packets = [0; 2];
+    for (i, r) in stream.by_ref() {
+        if let stream::StreamResult::Packet(_) = r {
+            packets[i] += 1;
+        }
+    }
+
+    assert_eq!(packets, [4, 4]);
+    assert_eq!(stream.stats().packets, 8);
+}
+
+#[test]
+fn test_sample_rx_stats() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let mut stream = rx[0].start_rx().expect("Failed to start rx");
+    let packets = stream.by_ref().count();
+
+    let stats = stream.stats();
+    assert_eq!(packets, 4);
+    assert_eq!(stats.packets, packets);
+    assert_eq!(stats.dropped, 0);
+    assert_eq!(stats.overflows, 0);
+    assert!(stats.samples > 0);
+
+    // the reader has deactivated the stream by the time the stream ends
+    assert!(stats.capture_end.is_some());
+    assert!(stats.duration().unwrap() > chrono::TimeDelta::zero());
+}
+
+#[test]
+fn test_sample_rx_shutdown() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let stream = rx[0].start_rx().expect("Failed to start rx");
+    stream.shutdown().expect("Failed to shutdown");
+
+    assert!(!*rx[0].running.lock().unwrap());
+}
+
+#[test]
+fn test_sample_rx_freq() {
+    let mut file = file_rx_config();
+    let device::config::Device::File { freq_mhz, .. } = &mut file else {
+        unreachable!()
+    };
+    *freq_mhz = Some(2440);
+
+    let config = device::config::List {
+        devices: vec![file],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    assert_eq!(rx[0].config.freq_mhz, 2440);
+    assert_eq!(rx[0].config.center_freq, 2440e6);
+    assert_eq!(rx[0].config.num_channels, 16);
+
+    let stream = rx[0].start_rx().expect("Failed to start rx");
+    stream.shutdown().expect("Failed to shutdown");
+}
+
+#[test]
+fn test_sample_rx_repeat() {
+    let mut file = file_rx_config();
+    let device::config::Device::File { repeat, .. } = &mut file else {
+        unreachable!()
+    };
+    *repeat = Some(3);
+
+    let config = device::config::List {
+        devices: vec![file],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let packets = rx[0].start_rx().expect("Failed to start rx").count();
+
+    assert_eq!(packets, 4 * 3);
+}
+
+#[test]
+fn test_sample_rx_callback() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = count.clone();
+
+    let errors = rx[0]
+        .start_rx_with_callback(move |_packet| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        })
+        .expect("Failed to start rx");
+
+    // ends when every worker has exited
+    errors.for_each(drop);
+
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 4);
+}
+
+#[test]
+fn test_sample_rx_occupancy() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let mut stream = rx[0].start_rx().expect("Failed to start rx");
+    let mut freqs = stream.by_ref().map(|p| p.freq as u32).collect::<Vec<_>>();
+    freqs.sort();
+    freqs.dedup();
+
+    let occupancy = stream.occupancy();
+
+    let mut occupied = occupancy
+        .iter()
+        .filter(|(_, o)| o.packets > 0)
+        .map(|(ch, _)| ch.to_freq())
+        .collect::<Vec<_>>();
+    occupied.sort();
+
+    assert_eq!(occupied, freqs);
+    for o in occupancy.values() {
+        assert!(o.bursts >= o.packets);
+        assert!(o.mean_rssi().is_some());
+    }
+}
+
+#[test]
+fn test_sample_rx_interrupted() {
+    let mut file = file_rx_config();
+    let device::config::Device::File { repeat, .. } = &mut file else {
+        unreachable!()
+    };
+    // keep reading until the stream is stopped
+    *repeat = Some(usize::MAX);
+
+    let config = device::config::List {
+        devices: vec![file],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let results = rx[0].start_rx_with_error().expect("Failed to start rx");
+    *rx[0].running.lock().unwrap() = false;
+
+    let interrupted = results.into_iter().any(|r| {
+        matches!(
+            r,
+            stream::StreamResult::Error(stream::StreamError::Interrupted)
+        )
+    });
+
+    assert!(interrupted);
+}
+
+#[test]
+fn test_sample_rx_single() {
+    let config = || device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config()).expect("Failed to open device");
+    let packets: Vec<rfraptor::bluetooth::Bluetooth> =
+        rx[0].start_rx().expect("Failed to start rx").collect();
+
+    let freq = packets[0].freq;
+    let expected = packets.iter().filter(|p| p.freq == freq).count();
+
+    let mut rx = device::open_device(config()).expect("Failed to open device");
+    let single: Vec<rfraptor::bluetooth::Bluetooth> = rx[0]
+        .start_rx_single(freq)
+        .expect("Failed to start rx")
+        .collect();
+
+    assert_eq!(single.len(), expected);
+    assert!(single.iter().all(|p| p.freq == freq));
+}
+
+#[test]
+fn test_sample_rx_advertisements() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let advertisements = rx[0]
+        .start_rx()
+        .expect("Failed to start rx")
+        .advertisements()
+        .collect::<Vec<_>>();
+
+    assert!(!advertisements.is_empty());
+    for (adv, packet) in &advertisements {
+        assert!(matches!(
+            packet.packet.inner,
+            rfraptor::bluetooth::PacketInner::Advertisement(ref a) if a == adv
+        ));
+    }
+}
+
+#[test]
+fn test_sample_rx_worker_threads() {
+    let mut file = file_rx_config();
+    let device::config::Device::File {
+        worker_threads,
+        worker_priority,
+        ..
+    } = &mut file
+    else {
+        unreachable!()
+    };
+    *worker_threads = Some(2);
+    // the lowest one, lowering the priority needs no permission
+    *worker_priority = Some(0);
+
+    let config = device::config::List {
+        devices: vec![file],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let mut stream = rx[0].start_rx().expect("Failed to start rx");
+    let mut addresses = stream
+        .by_ref()
+        .filter_map(|p| match p.packet.inner {
+            bluetooth::PacketInner::Advertisement(adv) => Some(adv.address),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    addresses.sort_by_key(|m| m.address);
+
+    let a = bluetooth::MacAddress {
+        address: [0x1f, 0x6e, 0x9a, 0x98, 0x48, 0xf6],
+    };
+    let b = bluetooth::MacAddress {
+        address: [0x5e, 0xb0, 0x41, 0x4e, 0xb8, 0x65],
+    };
+    assert_eq!(addresses, vec![a.clone(), a, b.clone(), b]);
+    assert_eq!(stream.stats().packets, 4);
+}
+
+#[test]
+fn test_sample_rx_events() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let (tx, events) = std::sync::mpsc::sync_channel(1024);
+    rx[0].tap_events(tx);
+
+    let packets = rx[0].start_rx().expect("Failed to start rx").count();
+    assert_eq!(packets, 4);
+
+    let read = events
+        .try_iter()
+        .filter_map(|event| match event {
+            stream::PipelineEvent::ReadBlock { samples } => Some(samples),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert!(!read.is_empty());
+    assert!(read.iter().all(|&samples| samples > 0));
+}
+
+#[test]
+fn test_sample_rx_record_bursts() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    let margin = burst::RecordMargin { pre: 16, post: 16 };
+    let (tx, recordings) = std::sync::mpsc::channel();
+    rx[0].record_bursts(margin, tx);
+
+    let mut stream = rx[0].start_rx().expect("Failed to start rx");
+    assert_eq!(stream.by_ref().count(), 4);
+
+    let occupancy = stream.occupancy();
+    let stats = stream.stats();
+    stream.shutdown().expect("Failed to shutdown");
+    drop(rx);
+
+    let recordings = recordings.iter().collect::<Vec<_>>();
+
+    // one recording per caught burst, on the channel it was caught
+    for (blch, o) in occupancy.iter() {
+        let count = recordings
+            .iter()
+            .filter(|(freq, _)| *freq == blch.to_freq())
+            .count();
+        assert_eq!(count, o.bursts, "{} MHz", blch.to_freq());
+    }
+    assert_eq!(
+        recordings.len(),
+        occupancy.values().map(|o| o.bursts).sum::<usize>()
+    );
+
+    for (_, recording) in recordings.iter() {
+        assert!(recording.len > 0);
+        assert!(recording.pre <= margin.pre);
+        assert!(recording.data.len() <= recording.pre + recording.len + margin.post);
+    }
+
+    // the silence between the bursts is not recorded: each of the 16 channels
+    // gets stats.samples * 2 / 16 samples (2 per symbol), much more than its bursts
+    for blch in occupancy.keys() {
+        let recorded = recordings
+            .iter()
+            .filter(|(freq, _)| *freq == blch.to_freq())
+            .map(|(_, r)| r.data.len())
+            .sum::<usize>();
+        assert!(recorded < stats.samples / 8, "{} MHz", blch.to_freq());
+    }
+}
+
+#[test]
+fn test_sample_rx_scan() {
+    let config = device::config::List {
+        devices: vec![file_rx_config()],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+
+    // the file ends well before the timeout
+    let report = rx[0]
+        .scan(std::time::Duration::from_secs(60))
+        .expect("Failed to scan");
+    assert!(report.elapsed < std::time::Duration::from_secs(60));
+
+    // 2427 MHz +/- 8 MHz
+    let freqs = report
+        .channels
+        .iter()
+        .map(|(ch, _)| ch.to_freq())
+        .collect::<Vec<_>>();
+    assert_eq!(freqs, (2420..=2434).step_by(2).collect::<Vec<_>>());
+
+    // the advertisements of the sample are on channel 38
+    let active = report
+        .active()
+        .into_iter()
+        .map(|ch| ch.channel_index())
+        .collect::<Vec<_>>();
+    assert_eq!(active, vec![38]);
+
+    let packets = report
+        .channels
+        .iter()
+        .map(|(_, o)| o.packets)
+        .sum::<usize>();
+    assert_eq!(packets, 4);
+}
+
+#[test]
+fn test_sample_rx_binary() {
+    use rfraptor::device::iq_file::{FileSink, IqFileReader, IqFileWriter, IqFormat, IqHeader};
+
+    let path = std::env::temp_dir().join(format!("rfraptor-rx-{}.cs8bin", std::process::id()));
+    let sink = FileSink {
+        path: path.display().to_string(),
+        format: IqFormat::Cs8Bin,
+        header: IqHeader {
+            sample_rate: 16e6,
+            center_freq: 2427e6,
+        },
+    };
+
+    let mut text = IqFileReader::open("tests/test_sample_rx.txt").expect("Failed to open");
+    let mut writer = IqFileWriter::create(&sink).expect("Failed to create");
+    while let Some(block) = text.read_block().expect("Failed to read") {
+        writer.write(&block).expect("Failed to write");
+    }
+    writer.flush().expect("Failed to flush");
+    drop(writer);
+
+    let mut file = file_rx_config();
+    let device::config::Device::File { path: source, .. } = &mut file else {
+        unreachable!()
+    };
+    *source = sink.path.clone();
+
+    let config = device::config::List {
+        devices: vec![file],
+    };
+
+    let mut rx = device::open_device(config).expect("Failed to open device");
+    assert_eq!(rx[0].file_source, Some(sink.path.clone()));
+    assert_eq!(rx[0].config.center_freq, 2427e6);
+
+    let packets = rx[0].start_rx().expect("Failed to start rx").count();
+    std::fs::remove_file(&path).unwrap();
+
+    // same as the text version (test_sample_rx)
+    assert_eq!(packets, 4);
+}
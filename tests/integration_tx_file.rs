@@ -0,0 +1,65 @@
+use rfraptor::stream::Stream;
+use rfraptor::*;
+
+#[test]
+fn test_file_sink() {
+    let path = std::env::temp_dir().join(format!("rfraptor-tx-{}.cf32", std::process::id()));
+
+    let config = device::config::List {
+        devices: vec![device::config::Device::FileSink {
+            path: path.display().to_string(),
+            format: None,
+            freq_mhz: None,
+            sample_rate_hz: None,
+            num_channels: None,
+        }],
+    };
+
+    let mut dev = device::open_device(config).expect("Failed to open device");
+    let tx = dev[0].start_tx().expect("Failed to start tx");
+
+    let adv = bluetooth::Advertisement {
+        pdu_header: bluetooth::PDUHeader {
+            pdu_type: bluetooth::PDUType::AdvInd,
+            rfu: false,
+            ch_sel: false,
+            tx_add: false,
+            rx_add: false,
+        },
+        length: 6 + 13,
+        address: bluetooth::MacAddress {
+            address: [0x00, 0x01, 0x00, 0x56, 0x34, 0x12],
+        },
+        data: vec![bluetooth::AdvData::new(0x09, b"hello:World")],
+    };
+
+    tx.send(bluetooth::Bluetooth {
+        bytes_packet: None,
+        packet: bluetooth::BluetoothPacket {
+            inner: bluetooth::PacketInner::Advertisement(adv.clone()),
+            crc: [0; 3],
+            crc_valid: false,
+        },
+        remain: Vec::new(),
+        freq: 2426,
+    })
+    .unwrap();
+    tx.flush().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let num_channels = dev[0].config.num_channels;
+    let bits = bitops::pdu_to_bits(&adv.encode(), 2426, bluetooth::ADV_ACCESS_ADDRESS);
+    let modulated = fsk::FskMod::new(dev[0].config.sample_rate as _, num_channels as _)
+        .unwrap()
+        .modulate(&bits)
+        .unwrap();
+
+    // interleaved f32 I/Q
+    assert!(!data.is_empty());
+    assert_eq!(
+        data.len() / 8,
+        (modulated.len() + 2 * stream::TX_PADDING) * num_channels / 2
+    );
+}
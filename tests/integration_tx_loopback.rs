@@ -0,0 +1,140 @@
+use rfraptor::stream::Stream;
+use rfraptor::*;
+
+#[test]
+fn test_virtual_loopback() {
+    let config = device::config::List {
+        devices: vec![device::config::Device::Virtual {
+            direction: "RxTx".to_string(),
+            freq_mhz: None,
+            sample_rate_hz: None,
+            num_channels: None,
+            worker_threads: None,
+            worker_priority: None,
+            stream_buffers: None,
+            aa_tolerance: None,
+        }],
+    };
+
+    let mut dev = device::open_device(config).expect("Failed to open device");
+
+    let mut rx = dev[0].start_rx().expect("Failed to start rx");
+    let tx = dev[0].start_tx().expect("Failed to start tx");
+
+    let address = bluetooth::MacAddress {
+        address: [0x00, 0x01, 0x00, 0x56, 0x34, 0x12],
+    };
+    let data = b"hello:World".to_vec();
+
+    let packet = bluetooth::Bluetooth {
+        bytes_packet: None,
+        packet: bluetooth::BluetoothPacket {
+            inner: bluetooth::PacketInner::Advertisement(bluetooth::Advertisement {
+                pdu_header: bluetooth::PDUHeader {
+                    pdu_type: bluetooth::PDUType::AdvInd,
+                    rfu: false,
+                    ch_sel: false,
+                    tx_add: false,
+                    rx_add: false,
+                },
+                length: data.len() as u8 + 7,
+                address: address.clone(),
+                data: vec![bluetooth::AdvData {
+                    len: data.len() as u8,
+                    data: data.clone(),
+                }],
+            }),
+            crc: [0, 0, 0],
+            crc_valid: false,
+        },
+        remain: Vec::new(),
+        freq: 2426,
+    };
+
+    tx.send(packet).expect("Failed to send packet");
+
+    let received = rx
+        .recv_timeout(std::time::Duration::from_secs(10))
+        .expect("no packet received");
+
+    assert_eq!(received.freq, 2426);
+    assert!(received.packet.crc_valid);
+
+    let bluetooth::PacketInner::Advertisement(ref adv) = received.packet.inner else {
+        panic!("unexpected packet: {:?}", received.packet.inner);
+    };
+    assert_eq!(adv.address, address);
+    assert_eq!(adv.data[0].data, data);
+}
+
+#[test]
+fn test_virtual_stream_buffers() {
+    let config = device::config::List {
+        devices: vec![device::config::Device::Virtual {
+            direction: "Rx".to_string(),
+            freq_mhz: None,
+            sample_rate_hz: None,
+            num_channels: None,
+            worker_threads: None,
+            worker_priority: None,
+            stream_buffers: Some(16),
+            aa_tolerance: None,
+        }],
+    };
+
+    let mut dev = device::open_device(config).expect("Failed to open device");
+    assert_eq!(dev[0].config.stream_args(), "buffers=16");
+
+    // the driver accepts the stream args
+    let rx = dev[0].start_rx().expect("Failed to start rx");
+    rx.shutdown().expect("Failed to shut down rx");
+}
+
+#[test]
+fn test_loopback_stream() {
+    let broker = stream::LoopbackBroker::new();
+    let mut alice = broker.stream();
+    let mut bob = broker.stream();
+
+    let mut alice_rx = alice.start_rx().expect("Failed to start rx");
+    let alice_tx = alice.start_tx().expect("Failed to start tx");
+    let mut bob_rx = bob.start_rx().expect("Failed to start rx");
+
+    let data = b"hello:Bob".to_vec();
+    let packet = bluetooth::Bluetooth {
+        bytes_packet: None,
+        packet: bluetooth::BluetoothPacket {
+            inner: bluetooth::PacketInner::Advertisement(bluetooth::Advertisement {
+                pdu_header: bluetooth::PDUHeader {
+                    pdu_type: bluetooth::PDUType::AdvNonconnInd,
+                    rfu: false,
+                    ch_sel: false,
+                    tx_add: false,
+                    rx_add: false,
+                },
+                length: 6 + 2 + data.len() as u8,
+                address: "12:34:56:00:01:00".parse().unwrap(),
+                data: vec![bluetooth::AdvData::new(0x09, &data)],
+            }),
+            crc: [0, 0, 0],
+            crc_valid: false,
+        },
+        remain: Vec::new(),
+        freq: 2426,
+    };
+
+    alice_tx
+        .send(packet.clone())
+        .expect("Failed to send packet");
+    alice_tx.flush().expect("Failed to flush");
+
+    let received = bob_rx
+        .recv_timeout(std::time::Duration::from_secs(1))
+        .expect("no packet received");
+
+    assert_eq!(received.freq, packet.freq);
+    assert_eq!(received.packet, packet.packet);
+
+    // the sender does not hear itself
+    assert!(alice_rx.try_next().is_none());
+}
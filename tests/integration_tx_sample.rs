@@ -4,8 +4,10 @@ use rfraptor::*;
 fn fsk_bits() {
     let num_channels = 16;
 
-    let mut demodulater = fsk::FskDemod::new(20e6, num_channels as _);
-    let mut modulater = fsk::FskMod::new(20e6, num_channels as _);
+    let mut demodulater =
+        fsk::FskDemod::new(20e6, num_channels as _).expect("Failed to create the modem");
+    let mut modulater =
+        fsk::FskMod::new(20e6, num_channels as _).expect("Failed to create the modem");
 
     let original_bytes = (0..0x10).map(|i| i as u8).collect::<Vec<_>>();
 
@@ -50,10 +52,12 @@ fn pfbch_fsk_bits() {
     let mut channelizer = channelizer::Channelizer::new(num_channels);
     let mut synthesizer = channelizer::Synthesizer::new(num_channels);
 
-    let mut burst = burst::Burst::default();
+    let mut burst = burst::Burst::new().expect("Failed to create the burst catcher");
 
-    let mut demodulater = fsk::FskDemod::new(20e6, num_channels as _);
-    let mut modulater = fsk::FskMod::new(20e6, num_channels as _);
+    let mut demodulater =
+        fsk::FskDemod::new(20e6, num_channels as _).expect("Failed to create the modem");
+    let mut modulater =
+        fsk::FskMod::new(20e6, num_channels as _).expect("Failed to create the modem");
 
     let original_bytes = (0..0x10).map(|i| i as u8).collect::<Vec<_>>();
 